@@ -0,0 +1,215 @@
+use std::{fmt, ops::Range};
+
+use thiserror::Error;
+
+use crate::defs::{LC3MemAddr, SignedLC3Word};
+
+/// Category of assembly failure, without any location information.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum AsmErrorKind {
+    #[error("`{0}` is not an instruction, register, number, or label")]
+    UnknownToken(String),
+    #[error("{0} does not fit in a 16 bit word")]
+    NumberTooLarge(String),
+    #[error("string is missing its closing quote")]
+    UnclosedString,
+    #[error("\\{0} is not a valid escape sequence")]
+    InvalidEscape(char),
+    #[error("string ends with an incomplete escape sequence")]
+    IncompleteEscape,
+    #[error("{0:?} is not an ASCII character")]
+    NonAscii(char),
+    #[error("expected {0}")]
+    Unexpected(&'static str),
+    #[error("{value} does not fit in a {width} bit field")]
+    ValueOutOfRange { value: SignedLC3Word, width: u8 },
+    #[error("label {0} is never defined")]
+    UndefinedLabel(String),
+    #[error("label {label} is too far away for a {width} bit offset")]
+    LabelOutOfRange { label: String, width: u8 },
+    #[error("label {0} is defined more than once")]
+    DuplicateLabel(String),
+    #[error("label {0} is defined outside of a .ORIG block")]
+    LabelOutsideBlock(String),
+    #[error("code must be preceded by .ORIG")]
+    CodeOutsideBlock,
+    #[error(".END without a matching .ORIG")]
+    UnmatchedEnd,
+    #[error("program has no .ORIG")]
+    MissingOrigin,
+    #[error("block at x{0:04X} runs past the end of memory")]
+    BlockPastMemory(LC3MemAddr),
+    #[error("block at x{0:04X} overlaps block at x{1:04X}")]
+    OverlappingBlocks(LC3MemAddr, LC3MemAddr),
+}
+
+impl AsmErrorKind {
+    /// Stable identifier for this kind of failure, e.g. `E007`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnknownToken(_) => "E001",
+            Self::NumberTooLarge(_) => "E002",
+            Self::UnclosedString => "E003",
+            Self::InvalidEscape(_) => "E004",
+            Self::IncompleteEscape => "E005",
+            Self::NonAscii(_) => "E006",
+            Self::Unexpected(_) => "E007",
+            Self::ValueOutOfRange { .. } => "E008",
+            Self::UndefinedLabel(_) => "E009",
+            Self::LabelOutOfRange { .. } => "E010",
+            Self::DuplicateLabel(_) => "E011",
+            Self::LabelOutsideBlock(_) => "E012",
+            Self::CodeOutsideBlock => "E013",
+            Self::UnmatchedEnd => "E014",
+            Self::MissingOrigin => "E015",
+            Self::BlockPastMemory(_) => "E016",
+            Self::OverlappingBlocks(_, _) => "E017",
+        }
+    }
+}
+
+/// Failure at the `index`th token of a lexed chain.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("token {index}: {kind}")]
+pub struct TokenError {
+    pub index: usize,
+    pub kind: AsmErrorKind,
+}
+
+/// Failure within a single line, before the line is placed in a file.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("column {}: {kind}", columns.start + 1)]
+pub struct LineError {
+    /// Byte range of the offending text within the line.
+    pub columns: Range<usize>,
+    pub kind: AsmErrorKind,
+}
+
+/// Location of an [`AsmError`] in the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    /// Name of the source file, if known.
+    pub file: Option<String>,
+    /// 1-indexed line in the source text.
+    pub line: usize,
+    /// Byte range of the offending text within the line.
+    pub columns: Range<usize>,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{file}:")?;
+        }
+        write!(f, "{}:{}", self.line, self.columns.start + 1)
+    }
+}
+
+/// Failure to assemble a single source line.
+///
+/// Displays as the full diagnostic, e.g.
+///
+/// ```text
+/// error[E008]: 32 does not fit in a 5 bit field
+///  --> prog.asm:2:13
+///   |
+/// 2 | ADD R1, R1, #32
+///   |             ^^^
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub span: Span,
+    pub kind: AsmErrorKind,
+    /// Full text of the line at [`Span::line`].
+    pub source_line: String,
+}
+
+impl AsmError {
+    /// Stable identifier for this kind of failure.
+    pub fn code(&self) -> &'static str {
+        self.kind.code()
+    }
+
+    /// The source line with the span underlined by carets.
+    pub fn snippet(&self) -> String {
+        let line_no = self.span.line.to_string();
+        let gutter = " ".repeat(line_no.len());
+
+        let text = self.source_line.trim_end();
+        let start = self.span.columns.start.min(text.len());
+        let end = self.span.columns.end.clamp(start, text.len());
+
+        // Keep tabs so the carets line up with the text above
+        let indent: String = text[..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let carets = "^".repeat(text[start..end].chars().count().max(1));
+
+        format!("{gutter} |\n{line_no} | {text}\n{gutter} | {indent}{carets}")
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let gutter = " ".repeat(self.span.line.to_string().len());
+        writeln!(f, "error[{}]: {}", self.code(), self.kind)?;
+        writeln!(f, "{gutter}--> {}", self.span)?;
+        write!(f, "{}", self.snippet())
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// Every failure encountered while assembling a source file.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n\n"))]
+pub struct AsmErrors(pub Vec<AsmError>);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn error(file: Option<&str>, source_line: &str, columns: Range<usize>) -> AsmError {
+        AsmError {
+            span: Span {
+                file: file.map(str::to_string),
+                line: 12,
+                columns,
+            },
+            kind: AsmErrorKind::ValueOutOfRange {
+                value: 32,
+                width: 5,
+            },
+            source_line: source_line.to_string(),
+        }
+    }
+
+    #[test]
+    fn render_diagnostic() {
+        let err = error(Some("prog.asm"), "  ADD R1, R1, #32 ; comment", 14..17);
+
+        assert_eq!(err.code(), "E008");
+        assert_eq!(
+            err.to_string(),
+            "error[E008]: 32 does not fit in a 5 bit field\n\
+             \x20 --> prog.asm:12:15\n\
+             \x20  |\n\
+             12 |   ADD R1, R1, #32 ; comment\n\
+             \x20  |               ^^^"
+        );
+    }
+
+    #[test]
+    fn render_tabs_and_line_end() {
+        let err = error(None, "\tADD\tR1, R1, #32", 13..14);
+        assert_eq!(err.span.to_string(), "12:14");
+        assert!(err
+            .snippet()
+            .ends_with(&format!("| \t   \t{}^", " ".repeat(8))));
+
+        // Errors at the end of the line still get a caret
+        let err = error(None, "ADD R1, R1", 10..11);
+        assert!(err.snippet().ends_with(&format!("| {}^", " ".repeat(10))));
+    }
+}
@@ -1,8 +1,4 @@
-use crate::{
-    assembler::{MaybeUnresolvedInstr, Op, PseudoOp, Token},
-    defs::{LC3Word, RegAddr},
-};
-use anyhow::Result;
+use crate::assembler::{MaybeUnresolvedInstr, Token, TokenError};
 
 // All of these functions are inlined because they work on the same exact data but are split up for
 // legibility
@@ -10,7 +6,7 @@ use anyhow::Result;
 /// First stage of the lexer operation, where any prefix labels are stripped out
 #[inline]
 pub fn prefix_label_pass(token_chain: Vec<Token>) -> (Option<String>, Vec<Token>) {
-    if token_chain[0].is_string() {
+    if token_chain.first().is_some_and(Token::is_string) {
         let label_str: String = match token_chain[0].clone() {
             Token::STRING(label) => label,
             _ => panic!("This shouldn't happen"),
@@ -24,18 +20,34 @@ pub fn prefix_label_pass(token_chain: Vec<Token>) -> (Option<String>, Vec<Token>
 }
 
 /// Second stage of the lexer operation, where a chain of unresolved instructions is created from
-/// the asm op. If the line consists only of a comment, then an empty Vec is returned
+/// the asm op or pseudo-op. If the line consists only of a comment, then an empty Vec is returned
+///
+/// Error indices count from the start of the chain, with comments removed.
 #[inline]
-pub fn construct_instruction_pass(token_chain: Vec<Token>) -> Result<Vec<MaybeUnresolvedInstr>> {
-    let mut result: Vec<MaybeUnresolvedInstr> = Vec::new();
-
-    result.push(MaybeUnresolvedInstr::new_from_chain(token_chain));
-
-    Ok(result)
+pub fn construct_instruction_pass(
+    token_chain: Vec<Token>,
+) -> Result<Vec<MaybeUnresolvedInstr>, TokenError> {
+    let token_chain: Vec<Token> = token_chain
+        .into_iter()
+        .filter(|token| !matches!(token, Token::COMMENT(_)))
+        .collect();
+
+    if token_chain.iter().all(Token::is_semicolon) {
+        Ok(Vec::new())
+    } else if let Some(Token::META(_)) = token_chain.first() {
+        MaybeUnresolvedInstr::new_from_pseudo_chain(token_chain)
+    } else {
+        Ok(vec![MaybeUnresolvedInstr::new_from_chain(token_chain)?])
+    }
 }
 
 /// Wrapper function to provide a cleaner API for the lexing passes
-pub fn lexer(token_chain: Vec<Token>) -> (Option<String>, Result<Vec<MaybeUnresolvedInstr>>) {
+pub fn lexer(
+    token_chain: Vec<Token>,
+) -> (
+    Option<String>,
+    Result<Vec<MaybeUnresolvedInstr>, TokenError>,
+) {
     let (label, chain) = prefix_label_pass(token_chain);
     let result = construct_instruction_pass(chain);
 
@@ -47,6 +59,10 @@ pub fn lexer(token_chain: Vec<Token>) -> (Option<String>, Result<Vec<MaybeUnreso
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::{
+        assembler::{AsmErrorKind, Op, PseudoOp},
+        defs::RegAddr,
+    };
 
     #[test]
     fn lex_label_instr() {
@@ -83,7 +99,8 @@ mod test {
             Token::COMMA,
             Token::REGISTER(RegAddr::One),
             Token::COMMA,
-            Token::NUM(0b10011),
+            // #-13, imm5 0b10011
+            Token::NUM(0xFFF3),
             Token::SEMICOLON,
         ];
         let (label, instr) = lexer(test_vec);
@@ -115,7 +132,7 @@ mod test {
             Token::COMMA,
             Token::REGISTER(RegAddr::One),
             Token::COMMA,
-            Token::NUM(0b10011),
+            Token::NUM(0xFFF3),
             Token::SEMICOLON,
         ];
         let (label, instr) = lexer(test_vec);
@@ -156,7 +173,7 @@ mod test {
             Token::COMMA,
             Token::REGISTER(RegAddr::Two),
             Token::COMMA,
-            Token::NUM(0b111000),
+            Token::NUM(0xFFF8),
             Token::SEMICOLON,
         ];
         let (label, instr) = lexer(test_vec);
@@ -209,7 +226,7 @@ mod test {
             Token::COMMA,
             Token::REGISTER(RegAddr::Two),
             Token::COMMA,
-            Token::NUM(0b111000),
+            Token::NUM(0xFFF8),
             Token::SEMICOLON,
         ];
         let (label, instr) = lexer(test_vec);
@@ -319,4 +336,166 @@ mod test {
         assert_eq!(label, None);
         assert_eq!(instr.unwrap().first().unwrap().value, 0b0000101011111111);
     }
+
+    #[test]
+    fn lex_comment_only() {
+        let test_vec = vec![
+            Token::COMMENT(";; Nothing here".to_string()),
+            Token::SEMICOLON,
+        ];
+        let (label, instr) = lexer(test_vec);
+
+        assert_eq!(label, None);
+        assert!(instr.unwrap().is_empty());
+    }
+
+    #[test]
+    fn lex_label_only() {
+        let test_vec = vec![Token::STRING("DONE1".to_string()), Token::SEMICOLON];
+        let (label, instr) = lexer(test_vec);
+
+        assert_eq!(label.unwrap(), "DONE1");
+        assert!(instr.unwrap().is_empty());
+    }
+
+    #[test]
+    fn lex_label_binding() {
+        let test_vec = vec![
+            Token::INSTR(Op::BR(true, true, true)),
+            Token::STRING("LOOP".to_string()),
+            Token::SEMICOLON,
+        ];
+        let (_, instr) = lexer(test_vec);
+
+        let instr = instr.unwrap().pop().unwrap();
+        assert_eq!(instr.value, 0b0000111000000000);
+        assert_eq!(instr.bindings[0].label, "LOOP");
+        assert_eq!(instr.bindings[0].begin_offset, 9);
+        assert_eq!(instr.bindings[0].end_offset, 0);
+    }
+
+    #[test]
+    fn lex_negative_imm() {
+        let test_vec = vec![
+            Token::INSTR(Op::ADD),
+            Token::REGISTER(RegAddr::Two),
+            Token::COMMA,
+            Token::REGISTER(RegAddr::Two),
+            Token::COMMA,
+            Token::NUM(0xFFFF),
+            Token::SEMICOLON,
+        ];
+        let (_, instr) = lexer(test_vec);
+
+        assert_eq!(instr.unwrap().first().unwrap().value, 0b0001010010111111);
+    }
+
+    #[test]
+    fn lex_rejects_bad_operands() {
+        // Immediate too large
+        let test_vec = vec![
+            Token::INSTR(Op::ADD),
+            Token::REGISTER(RegAddr::Two),
+            Token::COMMA,
+            Token::REGISTER(RegAddr::Two),
+            Token::COMMA,
+            Token::NUM(32),
+            Token::SEMICOLON,
+        ];
+        assert!(lexer(test_vec).1.is_err());
+
+        // Missing operand
+        let test_vec = vec![
+            Token::INSTR(Op::ADD),
+            Token::REGISTER(RegAddr::Two),
+            Token::COMMA,
+            Token::REGISTER(RegAddr::Two),
+            Token::SEMICOLON,
+        ];
+        assert!(lexer(test_vec).1.is_err());
+
+        // Not an instruction
+        let test_vec = vec![Token::META(PseudoOp::ILLEGAL), Token::SEMICOLON];
+        assert!(lexer(test_vec).1.is_err());
+    }
+
+    #[test]
+    fn lex_trap_instrs() {
+        let test_vec = vec![Token::INSTR(Op::TRAP), Token::NUM(0x25), Token::SEMICOLON];
+        let (label, instr) = lexer(test_vec);
+
+        assert_eq!(label, None);
+        assert_eq!(instr.unwrap().first().unwrap().value, 0xF025);
+
+        let test_vec = vec![Token::INSTR(Op::TRAP), Token::NUM(0xFF), Token::SEMICOLON];
+        let (_, instr) = lexer(test_vec);
+        assert_eq!(instr.unwrap().first().unwrap().value, 0xF0FF);
+
+        for (op, word) in [
+            (Op::GETC, 0xF020),
+            (Op::OUT, 0xF021),
+            (Op::PUTS, 0xF022),
+            (Op::IN, 0xF023),
+            (Op::PUTSP, 0xF024),
+            (Op::HALT, 0xF025),
+        ] {
+            let (_, instr) = lexer(vec![Token::INSTR(op), Token::SEMICOLON]);
+            assert_eq!(instr.unwrap().first().unwrap().value, word);
+        }
+    }
+
+    #[test]
+    fn lex_bad_trap_instrs() {
+        // Vector must be an unsigned byte
+        let test_vec = vec![Token::INSTR(Op::TRAP), Token::NUM(0x100), Token::SEMICOLON];
+        assert!(lexer(test_vec).1.is_err());
+
+        let test_vec = vec![Token::INSTR(Op::TRAP), Token::NUM(0xFFFF), Token::SEMICOLON];
+        assert!(lexer(test_vec).1.is_err());
+
+        let test_vec = vec![
+            Token::INSTR(Op::TRAP),
+            Token::STRING("LABEL".to_string()),
+            Token::SEMICOLON,
+        ];
+        assert!(lexer(test_vec).1.is_err());
+
+        // Aliases take no operands
+        let test_vec = vec![Token::INSTR(Op::HALT), Token::NUM(0x25), Token::SEMICOLON];
+        assert!(lexer(test_vec).1.is_err());
+    }
+
+    #[test]
+    fn lex_error_index() {
+        // Missing operand is reported at the end of the line
+        let test_vec = vec![
+            Token::INSTR(Op::LDR),
+            Token::REGISTER(RegAddr::One),
+            Token::COMMA,
+            Token::REGISTER(RegAddr::Two),
+            Token::SEMICOLON,
+        ];
+        let err = lexer(test_vec).1.unwrap_err();
+        assert_eq!(err.index, 4);
+
+        // Bad operands are reported where they appear
+        let test_vec = vec![
+            Token::INSTR(Op::NOT),
+            Token::REGISTER(RegAddr::One),
+            Token::COMMA,
+            Token::NUM(1),
+            Token::SEMICOLON,
+        ];
+        let err = lexer(test_vec).1.unwrap_err();
+        assert_eq!(err.index, 3);
+        assert_eq!(err.kind, AsmErrorKind::Unexpected("a register"));
+
+        let test_vec = vec![
+            Token::META(PseudoOp::BLKW),
+            Token::NUM(1),
+            Token::NUM(2),
+            Token::SEMICOLON,
+        ];
+        assert_eq!(lexer(test_vec).1.unwrap_err().index, 2);
+    }
 }
@@ -1,28 +1,46 @@
+use std::{collections::BTreeMap, ops::Range};
+
 use crate::{
-    defs::{LC3Word, Op, PseudoOp, RegAddr},
+    defs::{LC3MemAddr, LC3Word, Op, PseudoOp, RegAddr, SignedLC3Word, ADDR_SPACE_SIZE},
+    executors::LC3,
     instruction::{
-        ADD_OPCODE, ALL_JUMP_OPCODES, ALL_LOAD_OPCODES, ALL_STORE_OPCODES, AND_OPCODE,
-        BRANCH_OPCODE, JSR_OPCODE, NOT_OPCODE,
+        Trap, ADD_OPCODE, ALL_JUMP_OPCODES, ALL_LOAD_OPCODES, ALL_STORE_OPCODES, AND_OPCODE,
+        BRANCH_OPCODE, JSR_OPCODE, NOT_OPCODE, TRAP_OPCODE,
     },
 };
-use anyhow::{bail, Result};
 use strum_macros::EnumDiscriminants;
 
+mod error;
+pub use error::{AsmError, AsmErrorKind, AsmErrors, LineError, Span, TokenError};
 pub mod lexer;
+mod symbols;
+pub use symbols::{SymParseError, SymbolTable};
 pub mod tokenizer;
 
+/// Reference to a label that is filled in once all labels are known.
+///
+/// The label's value occupies bits `[end_offset, begin_offset)`.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Binding {
     pub label: String,
     pub begin_offset: u8,
     pub end_offset: u8,
+    pub kind: BindingKind,
+}
+
+/// How a [`Binding`] turns a label address into bits.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BindingKind {
+    /// Offset from the incremented PC, as used by instruction operands.
+    PCOffset,
+    /// The label's address itself, as used by `.FILL`.
+    Address,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct MaybeUnresolvedInstr {
     value: LC3Word,
-    ///Label, Start offset, End offset
-    bindings: Vec<(String, u8, u8)>,
+    bindings: Vec<Binding>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -31,13 +49,14 @@ enum ExpectItem {
     Reg(u8),
     Offset(u8, u8),
     RegOrOffset(u8, u8),
+    Unsigned(u8, u8),
     Comma,
     Semicolon,
     Bits(LC3Word),
 }
 
 impl ExpectItem {
-    fn test(&self, token: Token) -> Result<TokenCheckResult> {
+    fn test(&self, token: Token) -> Result<TokenCheckResult, AsmErrorKind> {
         match self {
             ExpectItem::Code(val) => Ok(TokenCheckResult::Value((*val as u16) << 12)),
             ExpectItem::Reg(shift) => token.is_register(*shift),
@@ -45,20 +64,21 @@ impl ExpectItem {
                 if token.is_comma() {
                     Ok(TokenCheckResult::Value(0b0))
                 } else {
-                    bail!("Expected a comma, but it wasn't found!")
+                    Err(AsmErrorKind::Unexpected("a comma"))
                 }
             }
             ExpectItem::Semicolon => {
                 if token.is_semicolon() {
                     Ok(TokenCheckResult::Value(0b0))
                 } else {
-                    bail!("Expect a semicolon, but it wasn't found!")
+                    Err(AsmErrorKind::Unexpected("the end of the line"))
                 }
             }
             ExpectItem::RegOrOffset(shift, max_len) => {
                 token.is_register_or_offset(*shift, *max_len)
             }
             ExpectItem::Offset(shift, max_len) => token.is_offset(*shift, *max_len),
+            ExpectItem::Unsigned(shift, max_len) => token.is_unsigned(*shift, *max_len),
             ExpectItem::Bits(bits) => Ok(TokenCheckResult::Value(*bits)),
         }
     }
@@ -162,6 +182,14 @@ impl Op {
             ExpectItem::Semicolon,
         ];
 
+        // JMP that also clears the privilege bit
+        let jmpt_sequence = vec![
+            ExpectItem::Code(ALL_JUMP_OPCODES[0]),
+            ExpectItem::Reg(6),
+            ExpectItem::Bits(1),
+            ExpectItem::Semicolon,
+        ];
+
         let jsr_sequence = vec![
             ExpectItem::Code(JSR_OPCODE),
             ExpectItem::Bits(0b1 << 11),
@@ -169,7 +197,11 @@ impl Op {
             ExpectItem::Semicolon,
         ];
 
-        let jsrr_sequence = vec![ExpectItem::Code(JSR_OPCODE), ExpectItem::Reg(6)];
+        let jsrr_sequence = vec![
+            ExpectItem::Code(JSR_OPCODE),
+            ExpectItem::Reg(6),
+            ExpectItem::Semicolon,
+        ];
 
         let ret_sequence = vec![
             ExpectItem::Code(ALL_JUMP_OPCODES[2]),
@@ -179,6 +211,21 @@ impl Op {
 
         let rti_sequence = vec![ExpectItem::Code(ALL_JUMP_OPCODES[1]), ExpectItem::Semicolon];
 
+        let trap_sequence = vec![
+            ExpectItem::Code(TRAP_OPCODE),
+            ExpectItem::Unsigned(0, 8),
+            ExpectItem::Semicolon,
+        ];
+
+        // Aliases are fully specified by their trap vector
+        let trap_alias_sequence = |trap: Trap| {
+            vec![
+                ExpectItem::Code(TRAP_OPCODE),
+                ExpectItem::Bits(trap.into()),
+                ExpectItem::Semicolon,
+            ]
+        };
+
         // Actually do the work here
         match self {
             Op::ADD => add_sequence,
@@ -192,6 +239,7 @@ impl Op {
             Op::STR => str_sequence,
             Op::NOT => not_sequence,
             Op::JMP => jmp_sequence,
+            Op::JMPT => jmpt_sequence,
             Op::JSR => jsr_sequence,
             Op::JSRR => jsrr_sequence,
             Op::RET => ret_sequence,
@@ -208,56 +256,158 @@ impl Op {
                 ];
                 br_sequence
             }
-            _ => todo!(),
+            Op::TRAP => trap_sequence,
+            Op::GETC => trap_alias_sequence(Trap::Getc),
+            Op::OUT => trap_alias_sequence(Trap::Out),
+            Op::PUTS => trap_alias_sequence(Trap::PutS),
+            Op::IN => trap_alias_sequence(Trap::In),
+            Op::PUTSP => trap_alias_sequence(Trap::PutSp),
+            Op::HALT => trap_alias_sequence(Trap::Halt),
+            // No valid sequence
+            Op::ILLEGAL => Vec::new(),
         }
     }
 }
 
 impl MaybeUnresolvedInstr {
     /// Return a new MaybeUnresolvedInstr given a chain of Tokens
-    fn new_from_chain(mut chain: Vec<Token>) -> MaybeUnresolvedInstr {
-        if let Token::INSTR(op) = chain[0] {
-            let sequence = op.get_sequence();
-
-            // Because we include the insertion of specified bits in the sequence,
-            // we need to add some Token::None into the iterator to properly test the chain
-            for (i, element) in sequence.iter().enumerate() {
-                if let ExpectItem::Bits(_) = element {
-                    println!("Inserting Token::NONE at {}", i);
-                    chain.insert(i, Token::NONE);
-                }
-            }
+    fn new_from_chain(chain: Vec<Token>) -> Result<MaybeUnresolvedInstr, TokenError> {
+        let Some(Token::INSTR(op)) = chain.first().cloned() else {
+            return Err(TokenError {
+                index: 0,
+                kind: AsmErrorKind::Unexpected("an instruction or pseudo-op"),
+            });
+        };
 
-            let mut results: Vec<TokenCheckResult> = Vec::new();
-            let test_chain = sequence.iter().zip(chain.iter());
-            for (expected, token) in test_chain {
-                println!("Expecting {:?}, Found {:?}", expected, token);
+        let sequence = op.get_sequence();
+        if sequence.is_empty() {
+            return Err(TokenError {
+                index: 0,
+                kind: AsmErrorKind::Unexpected("an instruction or pseudo-op"),
+            });
+        }
 
-                results.push(expected.test(token.clone()).unwrap());
+        let mut values: Vec<LC3Word> = Vec::new();
+        let mut bindings: Vec<Binding> = Vec::new();
+        let last = chain.len() - 1;
+        let mut tokens = chain.into_iter().enumerate();
+        for expected in &sequence {
+            // Specified bits don't correspond to a token in the chain
+            if let ExpectItem::Bits(bits) = expected {
+                values.push(*bits);
+                continue;
             }
 
-            let mut values: Vec<LC3Word> = Vec::new();
-            let mut bindings: Vec<Binding> = Vec::new();
-            for result in results {
-                match result {
-                    TokenCheckResult::Value(val) => values.push(val),
-                    TokenCheckResult::Binding(binding) => bindings.push(binding),
+            let (index, token) = tokens.next().unwrap_or((last, Token::NONE));
+            let checked = match token {
+                // Reaching the end of the line early means an operand is missing
+                Token::SEMICOLON | Token::NONE if *expected != ExpectItem::Semicolon => {
+                    Err(AsmErrorKind::Unexpected("another operand"))
                 }
+                token => expected.test(token),
+            };
+
+            match checked {
+                Ok(TokenCheckResult::Value(val)) => values.push(val),
+                Ok(TokenCheckResult::Binding(binding)) => bindings.push(binding),
+                Err(kind) => return Err(TokenError { index, kind }),
             }
+        }
 
-            let mut instr = MaybeUnresolvedInstr {
-                value: 0b0,
-                bindings: Vec::new(),
-            };
+        let mut instr = MaybeUnresolvedInstr {
+            value: 0b0,
+            bindings,
+        };
 
-            instr.flatten_values(values);
+        instr.flatten_values(values);
 
-            instr
-        } else {
-            todo!()
+        Ok(instr)
+    }
+
+    /// Return the data words laid out by a pseudo-op chain.
+    ///
+    /// `.ORIG` and `.END` place no words, and are handled by [`assemble`].
+    fn new_from_pseudo_chain(chain: Vec<Token>) -> Result<Vec<MaybeUnresolvedInstr>, TokenError> {
+        let data = |value| MaybeUnresolvedInstr {
+            value,
+            bindings: Vec::new(),
+        };
+        let err = |index, expected| TokenError {
+            index,
+            kind: AsmErrorKind::Unexpected(expected),
+        };
+
+        match chain.as_slice() {
+            [Token::META(PseudoOp::FILL), Token::NUM(value), Token::SEMICOLON] => {
+                Ok(vec![data(*value)])
+            }
+            [Token::META(PseudoOp::FILL), Token::STRING(label), Token::SEMICOLON] => {
+                Ok(vec![MaybeUnresolvedInstr {
+                    value: 0,
+                    bindings: vec![Binding {
+                        label: label.clone(),
+                        begin_offset: LC3Word::BITS as u8,
+                        end_offset: 0,
+                        kind: BindingKind::Address,
+                    }],
+                }])
+            }
+            [Token::META(PseudoOp::FILL), Token::NUM(_) | Token::STRING(_), ..] => {
+                Err(err(2, "the end of the line"))
+            }
+            [Token::META(PseudoOp::FILL), ..] => Err(err(1, "a value or label")),
+            [Token::META(PseudoOp::BLKW), Token::NUM(count), Token::SEMICOLON] => {
+                Ok(vec![data(0); *count as usize])
+            }
+            [Token::META(PseudoOp::BLKW), Token::NUM(_), ..] => Err(err(2, "the end of the line")),
+            [Token::META(PseudoOp::BLKW), ..] => Err(err(1, "a word count")),
+            [Token::META(PseudoOp::STRINGZ), Token::QUOTES, Token::STRING(text), Token::QUOTES, Token::SEMICOLON] => {
+                Ok(unescape(text)
+                    .map_err(|kind| TokenError { index: 2, kind })?
+                    .into_iter()
+                    .chain(std::iter::once(0))
+                    .map(data)
+                    .collect())
+            }
+            [Token::META(PseudoOp::STRINGZ), Token::QUOTES, Token::STRING(_), Token::QUOTES, ..] => {
+                Err(err(4, "the end of the line"))
+            }
+            [Token::META(PseudoOp::STRINGZ), ..] => Err(err(1, "a quoted string")),
+            _ => Err(err(0, "a data pseudo-op")),
         }
     }
 
+    /// Fill in every label binding, given this instruction is placed at `addr`.
+    fn resolve(self, addr: LC3MemAddr, symbols: &SymbolTable) -> Result<LC3Word, AsmErrorKind> {
+        let mut value = self.value;
+
+        for binding in self.bindings {
+            let target = symbols
+                .get(&binding.label)
+                .ok_or_else(|| AsmErrorKind::UndefinedLabel(binding.label.clone()))?;
+
+            let width = binding.begin_offset - binding.end_offset;
+            let bits = match binding.kind {
+                BindingKind::PCOffset => {
+                    // PC has already been incremented when the offset is applied
+                    let offset = target.wrapping_sub(addr.wrapping_add(1)) as SignedLC3Word;
+                    if !fits_signed(offset, width) {
+                        return Err(AsmErrorKind::LabelOutOfRange {
+                            label: binding.label,
+                            width,
+                        });
+                    }
+                    offset as LC3Word
+                }
+                BindingKind::Address => target,
+            };
+
+            value |= (bits & width_mask(width)) << binding.end_offset;
+        }
+
+        Ok(value)
+    }
+
     /// Flattens a given Vec of LC3Words into &self
     fn flatten_values(&mut self, values: Vec<LC3Word>) {
         for value in values {
@@ -285,69 +435,130 @@ enum TokenCheckResult {
     Binding(Binding),
 }
 
-impl Token {
-    fn is_string(&self) -> bool {
-        if let Token::STRING(_) = self {
-            true
+/// Convert `.STRINGZ` contents into words, processing escape sequences.
+fn unescape(text: &str) -> Result<Vec<LC3Word>, AsmErrorKind> {
+    let mut chars = text.chars();
+    let mut words = Vec::with_capacity(text.len());
+
+    while let Some(c) = chars.next() {
+        let c = if c == '\\' {
+            match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some('0') => '\0',
+                Some('e') => '\x1B',
+                Some('\\') => '\\',
+                Some('"') => '"',
+                Some('\'') => '\'',
+                Some(x) => return Err(AsmErrorKind::InvalidEscape(x)),
+                None => return Err(AsmErrorKind::IncompleteEscape),
+            }
         } else {
-            false
+            c
+        };
+
+        if !c.is_ascii() {
+            return Err(AsmErrorKind::NonAscii(c));
         }
+        words.push(c as LC3Word);
+    }
+
+    Ok(words)
+}
+
+/// Mask covering the bottom `width` bits of a word.
+const fn width_mask(width: u8) -> LC3Word {
+    ((1_u32 << width) - 1) as LC3Word
+}
+
+/// True if `value` is representable as a `width` bit two's complement number.
+const fn fits_signed(value: SignedLC3Word, width: u8) -> bool {
+    let bound = 1_i32 << (width - 1);
+    (value as i32) >= -bound && (value as i32) < bound
+}
+
+/// True if `num`, read as a two's complement word, fits in a signed `width`
+/// bit field.
+const fn fits_field(num: LC3Word, width: u8) -> bool {
+    fits_signed(num as SignedLC3Word, width)
+}
+
+/// Error for `num` not fitting in a `width` bit field.
+fn out_of_range(num: LC3Word, width: u8) -> AsmErrorKind {
+    AsmErrorKind::ValueOutOfRange {
+        value: num as SignedLC3Word,
+        width,
+    }
+}
+
+impl Token {
+    fn is_string(&self) -> bool {
+        matches!(self, Token::STRING(_))
     }
 
     fn is_comma(&self) -> bool {
-        if let Token::COMMA = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, Token::COMMA)
     }
 
     fn is_semicolon(&self) -> bool {
-        if let Token::SEMICOLON = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, Token::SEMICOLON)
     }
 
-    fn is_register(&self, shift: u8) -> Result<TokenCheckResult> {
+    fn is_register(&self, shift: u8) -> Result<TokenCheckResult, AsmErrorKind> {
         if let Token::REGISTER(reg) = self {
             let mut value = 0b0;
             value |= LC3Word::from(*reg) << shift;
             Ok(TokenCheckResult::Value(value))
         } else {
-            bail!("NOT A REGISTER")
+            Err(AsmErrorKind::Unexpected("a register"))
         }
     }
 
-    fn is_offset(&self, shift: u8, max_len: u8) -> Result<TokenCheckResult> {
+    fn is_offset(&self, shift: u8, max_len: u8) -> Result<TokenCheckResult, AsmErrorKind> {
         let result: TokenCheckResult;
 
         if let Token::NUM(num) = self {
             let mut value = 0b0;
-            let max_mask = 1 << (max_len + 1);
 
-            if *num < max_mask {
-                value |= num << shift;
+            if fits_field(*num, max_len) {
+                value |= (num & width_mask(max_len)) << shift;
                 result = TokenCheckResult::Value(value);
             } else {
-                bail!("TOO BIG")
+                return Err(out_of_range(*num, max_len));
             }
         } else if let Token::STRING(label) = self {
             let binding = Binding {
                 label: label.clone(),
                 begin_offset: shift + max_len,
                 end_offset: shift,
+                kind: BindingKind::PCOffset,
             };
             result = TokenCheckResult::Binding(binding);
         } else {
-            bail!("NOT OFFSET")
+            return Err(AsmErrorKind::Unexpected("an offset or label"));
         }
 
         Ok(result)
     }
 
-    fn is_register_or_offset(&self, shift: u8, max_len: u8) -> Result<TokenCheckResult> {
+    fn is_unsigned(&self, shift: u8, max_len: u8) -> Result<TokenCheckResult, AsmErrorKind> {
+        if let Token::NUM(num) = self {
+            if u32::from(*num) < (1 << max_len) {
+                Ok(TokenCheckResult::Value(num << shift))
+            } else {
+                Err(out_of_range(*num, max_len))
+            }
+        } else {
+            Err(AsmErrorKind::Unexpected("a number"))
+        }
+    }
+
+    fn is_register_or_offset(
+        &self,
+        shift: u8,
+        max_len: u8,
+    ) -> Result<TokenCheckResult, AsmErrorKind> {
         let result: TokenCheckResult;
 
         if let Token::REGISTER(reg) = self {
@@ -356,34 +567,651 @@ impl Token {
             result = TokenCheckResult::Value(value);
         } else if let Token::NUM(num) = self {
             let mut value = 0b0;
-            let max_mask = 1 << (max_len + 1);
 
-            if *num < max_mask {
-                value |= num << shift;
+            if fits_field(*num, max_len) {
+                value |= (num & width_mask(max_len)) << shift;
                 value |= 1 << max_len;
                 result = TokenCheckResult::Value(value);
             } else {
-                bail!("TOO BIG")
+                return Err(out_of_range(*num, max_len));
             }
-        } else if let Token::STRING(label) = self {
-            let binding = Binding {
-                label: label.clone(),
-                begin_offset: shift + max_len,
-                end_offset: shift,
-            };
-            result = TokenCheckResult::Binding(binding);
         } else {
-            bail!("NOT REGISTER OR OFFSET")
+            return Err(AsmErrorKind::Unexpected("a register or number"));
         }
 
         Ok(result)
     }
 }
 
-pub fn translate_line(line: &str) -> MaybeUnresolvedInstr {
-    todo!()
+/// Contents of a single assembly source line, after lexing.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum LineContent {
+    /// `.ORIG` directive, starting a new block of words at the address.
+    Origin(LC3MemAddr),
+    /// `.END` directive, ending the current block.
+    End,
+    /// Words to place in memory, possibly awaiting label resolution.
+    Words(Vec<MaybeUnresolvedInstr>),
+}
+
+/// Tokenize and lex a single line of assembly.
+///
+/// Returns the label defined on this line, if any, alongside its contents.
+pub fn translate_line(line: &str) -> Result<(Option<String>, LineContent), LineError> {
+    let (tokens, spans): (Vec<_>, Vec<_>) = tokenizer::tokenize_line_spanned(line)?
+        .into_iter()
+        .filter(|(token, _)| !matches!(token, Token::COMMENT(_)))
+        .unzip();
+    let (label, chain) = lexer::prefix_label_pass(tokens);
+
+    // Chain indices are offset by the label, if present
+    let spans = &spans[usize::from(label.is_some())..];
+    let locate = |err: TokenError| LineError {
+        columns: spans[err.index.min(spans.len() - 1)].clone(),
+        kind: err.kind,
+    };
+
+    let content = match chain.first() {
+        Some(Token::META(PseudoOp::ORIG)) => match chain.as_slice() {
+            [_, Token::NUM(origin), Token::SEMICOLON] => LineContent::Origin(*origin),
+            [_, Token::NUM(_), ..] => {
+                return Err(locate(TokenError {
+                    index: 2,
+                    kind: AsmErrorKind::Unexpected("the end of the line"),
+                }))
+            }
+            _ => {
+                return Err(locate(TokenError {
+                    index: 1,
+                    kind: AsmErrorKind::Unexpected("an address"),
+                }))
+            }
+        },
+        Some(Token::META(PseudoOp::END)) => LineContent::End,
+        _ => LineContent::Words(lexer::construct_instruction_pass(chain).map_err(locate)?),
+    };
+
+    Ok((label, content))
+}
+
+/// Produce the final machine word for `instr`, located at `addr`.
+pub fn resolve_instr(
+    instr: MaybeUnresolvedInstr,
+    addr: LC3MemAddr,
+    symbols: &SymbolTable,
+) -> Result<LC3Word, AsmErrorKind> {
+    instr.resolve(addr, symbols)
+}
+
+/// Contiguous run of words, as produced by a single `.ORIG`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjBlock {
+    /// Address of the first word.
+    pub origin: LC3MemAddr,
+    pub words: Vec<LC3Word>,
 }
 
-pub fn resolve_instr(instr: MaybeUnresolvedInstr) -> String {
-    todo!()
+impl ObjBlock {
+    /// Object file contents, as consumed by
+    /// [`populate_from_bin`](crate::executors::populate_from_bin).
+    ///
+    /// The origin is followed by each word, all big endian.
+    pub fn obj(&self) -> Vec<u8> {
+        std::iter::once(self.origin)
+            .chain(self.words.iter().copied())
+            .flat_map(LC3Word::to_be_bytes)
+            .collect()
+    }
+}
+
+/// Output of a successful [`assemble`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assembled {
+    /// Every block, in source order.
+    pub blocks: Vec<ObjBlock>,
+    /// Address of every label in the program.
+    pub symbols: SymbolTable,
+    /// Source line of every word, by address.
+    pub lines: BTreeMap<LC3MemAddr, usize>,
+}
+
+impl Assembled {
+    /// Load every block into `processor`.
+    ///
+    /// Equivalent to calling
+    /// [`populate_from_bin`](crate::executors::populate_from_bin) on each
+    /// [`ObjBlock::obj`].
+    pub fn populate<P: LC3>(&self, processor: &mut P) {
+        for block in &self.blocks {
+            processor.populate(block.origin, block.words.iter().copied());
+        }
+    }
+}
+
+/// Assemble LC-3 source text into object code.
+///
+/// The first pass lays out every line and records label addresses, the second
+/// pass fills in each label reference. All line errors are collected, rather
+/// than stopping at the first.
+///
+/// Each `.ORIG` starts a new block, which is closed by `.END`. Anything
+/// between an `.END` and the next `.ORIG` is ignored.
+pub fn assemble(source: &str) -> Result<Assembled, AsmErrors> {
+    assemble_source(None, source)
+}
+
+/// [`assemble`], with `file` named in the span of every error.
+pub fn assemble_named(file: &str, source: &str) -> Result<Assembled, AsmErrors> {
+    assemble_source(Some(file), source)
+}
+
+fn assemble_source(file: Option<&str>, source: &str) -> Result<Assembled, AsmErrors> {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut errors = Vec::new();
+    let mut push_error = |line: usize, columns: Range<usize>, kind: AsmErrorKind| {
+        errors.push(AsmError {
+            span: Span {
+                file: file.map(str::to_string),
+                line,
+                columns,
+            },
+            kind,
+            source_line: lines.get(line - 1).copied().unwrap_or_default().to_string(),
+        })
+    };
+
+    let mut symbols = SymbolTable::new();
+    // Unresolved instructions with their source line number, by block origin
+    let mut blocks: Vec<(LC3MemAddr, Vec<(usize, MaybeUnresolvedInstr)>)> = Vec::new();
+    // Whether the last block is still accepting words
+    let mut open = false;
+    // Whether the last block was closed by `.END`
+    let mut ended = false;
+
+    // First pass: layout and symbol table
+    for (line, text) in lines.iter().enumerate().map(|(idx, text)| (idx + 1, *text)) {
+        let (label, content) = match translate_line(text) {
+            Ok(translated) => translated,
+            Err(_) if ended => continue,
+            Err(err) => {
+                push_error(line, err.columns, err.kind);
+                continue;
+            }
+        };
+
+        if ended && !matches!(content, LineContent::Origin(_)) {
+            continue;
+        }
+
+        if let Some(label) = label {
+            match blocks.last() {
+                Some((origin, instrs)) if open => {
+                    let addr = origin.wrapping_add(instrs.len() as u16);
+                    if symbols.insert(label.clone(), addr).is_some() {
+                        push_error(
+                            line,
+                            label_columns(text, &label, true),
+                            AsmErrorKind::DuplicateLabel(label),
+                        );
+                    }
+                }
+                _ => push_error(
+                    line,
+                    label_columns(text, &label, true),
+                    AsmErrorKind::LabelOutsideBlock(label),
+                ),
+            }
+        }
+
+        match content {
+            LineContent::Origin(addr) => {
+                blocks.push((addr, Vec::new()));
+                open = true;
+                ended = false;
+            }
+            LineContent::End => {
+                if open {
+                    open = false;
+                    ended = true;
+                } else {
+                    push_error(line, line_columns(text), AsmErrorKind::UnmatchedEnd);
+                }
+            }
+            LineContent::Words(words) => match blocks.last_mut() {
+                _ if words.is_empty() => (),
+                Some((_, instrs)) if open => {
+                    instrs.extend(words.into_iter().map(|word| (line, word)))
+                }
+                _ => push_error(line, line_columns(text), AsmErrorKind::CodeOutsideBlock),
+            },
+        }
+    }
+
+    if blocks.is_empty() {
+        let line = lines.len().max(1);
+        let columns = line_columns(lines.get(line - 1).copied().unwrap_or_default());
+        push_error(line, columns, AsmErrorKind::MissingOrigin);
+        return Err(AsmErrors(errors));
+    }
+
+    // Blocks must fit in memory without overlapping each other
+    let mut ranges: Vec<_> = blocks
+        .iter()
+        .filter(|(_, instrs)| !instrs.is_empty())
+        .map(|(origin, instrs)| (*origin as usize, *origin as usize + instrs.len()))
+        .collect();
+    ranges.sort();
+    let block_error = |origin: usize| {
+        let line = first_line(&blocks, origin);
+        (line, line_columns(lines[line - 1]))
+    };
+    for (origin, end) in &ranges {
+        if *end > ADDR_SPACE_SIZE {
+            let (line, columns) = block_error(*origin);
+            push_error(
+                line,
+                columns,
+                AsmErrorKind::BlockPastMemory(*origin as LC3MemAddr),
+            );
+        }
+    }
+    for pair in ranges.windows(2) {
+        if pair[1].0 < pair[0].1 {
+            let (line, columns) = block_error(pair[1].0);
+            push_error(
+                line,
+                columns,
+                AsmErrorKind::OverlappingBlocks(pair[1].0 as LC3MemAddr, pair[0].0 as LC3MemAddr),
+            );
+        }
+    }
+
+    // Second pass: label resolution
+    let mut resolved = Vec::with_capacity(blocks.len());
+    let mut word_lines = BTreeMap::new();
+    for (origin, instrs) in blocks {
+        let mut words = Vec::with_capacity(instrs.len());
+        for (idx, (line, instr)) in instrs.into_iter().enumerate() {
+            let addr = origin.wrapping_add(idx as u16);
+            word_lines.insert(addr, line);
+            match resolve_instr(instr, addr, &symbols) {
+                Ok(word) => words.push(word),
+                Err(kind) => {
+                    let columns = match &kind {
+                        AsmErrorKind::UndefinedLabel(label)
+                        | AsmErrorKind::LabelOutOfRange { label, .. } => {
+                            label_columns(lines[line - 1], label, false)
+                        }
+                        _ => line_columns(lines[line - 1]),
+                    };
+                    push_error(line, columns, kind)
+                }
+            }
+        }
+        resolved.push(ObjBlock { origin, words });
+    }
+
+    if errors.is_empty() {
+        Ok(Assembled {
+            blocks: resolved,
+            symbols,
+            lines: word_lines,
+        })
+    } else {
+        Err(AsmErrors(errors))
+    }
+}
+
+/// Source line of the first word in the block starting at `origin`.
+fn first_line(blocks: &[(LC3MemAddr, Vec<(usize, MaybeUnresolvedInstr)>)], origin: usize) -> usize {
+    blocks
+        .iter()
+        .find(|(block_origin, _)| *block_origin as usize == origin)
+        .and_then(|(_, instrs)| instrs.first())
+        .map_or(1, |(line, _)| *line)
+}
+
+/// Columns covering everything but surrounding whitespace on `text`.
+fn line_columns(text: &str) -> Range<usize> {
+    (text.len() - text.trim_start().len())..text.trim_end().len()
+}
+
+/// Columns of the definition of `label` on `text`, or its last use.
+///
+/// Falls back to [`line_columns`] if the label is not found.
+fn label_columns(text: &str, label: &str, definition: bool) -> Range<usize> {
+    let tokens = tokenizer::tokenize_line_spanned(text).unwrap_or_default();
+    let mut matching = tokens
+        .into_iter()
+        .filter(|(token, _)| matches!(token, Token::STRING(name) if name == label))
+        .map(|(_, columns)| columns);
+
+    let found = if definition {
+        matching.next()
+    } else {
+        matching.next_back()
+    };
+    found.unwrap_or_else(|| line_columns(text))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::executors::core::CoreLC3;
+
+    #[test]
+    fn assemble_labels() {
+        let source = "
+            ;; Count down R1
+                    .ORIG   x3000
+            TOP     ADD     R1, R1, #-1
+                    BRp     TOP
+                    LD      R2, VALUE
+            SKIP
+                    JSR     SKIP
+            VALUE   NOT     R3, R2
+            ";
+        let assembled = assemble(source).unwrap();
+
+        assert_eq!(assembled.blocks.len(), 1);
+        assert_eq!(assembled.blocks[0].origin, 0x3000);
+        assert_eq!(
+            assembled.blocks[0].words,
+            vec![0x127F, 0x03FE, 0x2401, 0x4FFF, 0x96BF]
+        );
+        assert_eq!(assembled.symbols["TOP"], 0x3000);
+        assert_eq!(assembled.symbols["SKIP"], 0x3003);
+        assert_eq!(assembled.symbols["VALUE"], 0x3004);
+        assert_eq!(
+            assembled.blocks[0].obj(),
+            vec![0x30, 0x00, 0x12, 0x7F, 0x03, 0xFE, 0x24, 0x01, 0x4F, 0xFF, 0x96, 0xBF]
+        );
+    }
+
+    #[test]
+    fn assemble_lower_case() {
+        let source = r#"
+                    .orig   x3000
+            top     add     r1, r1, #-1
+                    brnz    done
+                    ld      r2, value
+                    br      top
+            done    puts
+                    halt
+            value   .fill   x1234
+            text    .stringz "a"
+                    .blkw   1
+                    .end
+            "#;
+        let upper = r#"
+                    .ORIG   X3000
+            top     ADD     R1, R1, #-1
+                    BRNZ    done
+                    LD      R2, value
+                    BR      top
+            done    PUTS
+                    HALT
+            value   .FILL   X1234
+            text    .STRINGZ "a"
+                    .BLKW   1
+                    .END
+            "#;
+        let assembled = assemble(source).unwrap();
+
+        assert_eq!(assembled.blocks, assemble(upper).unwrap().blocks);
+        assert_eq!(
+            assembled.blocks[0].words[..4],
+            [0x127F, 0x0C02, 0x2403, 0x0FFC]
+        );
+        assert_eq!(assembled.symbols["value"], 0x3006);
+    }
+
+    #[test]
+    fn assemble_stops_at_end() {
+        let source = ".ORIG x3000\nRET\n.END\nNOT NOT NOT";
+        assert_eq!(assemble(source).unwrap().blocks[0].words, vec![0xC1C0]);
+    }
+
+    #[test]
+    fn assemble_collects_errors() {
+        let source = "
+                    .ORIG   x3000
+            A       ADD     R1, R1, #32
+            B       BRp     MISSING
+                    LDR     R1, R2
+            B       RET
+            ";
+        let errors = assemble(source).unwrap_err().0;
+
+        let lines: Vec<_> = errors.iter().map(|err| err.span.line).collect();
+        assert_eq!(lines, vec![3, 5, 6, 4]);
+    }
+
+    #[test]
+    fn assemble_offset_range() {
+        let source = "
+                    .ORIG   x3000
+                    BRnzp   FAR
+                    .END
+            ";
+        assert!(assemble(source).is_err());
+
+        let mut far_source = String::from(".ORIG x3000\nBR FAR\n");
+        far_source.push_str(&"RET\n".repeat(300));
+        far_source.push_str("FAR RET\n");
+        let errors = assemble(&far_source).unwrap_err().0;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span.line, 2);
+    }
+
+    #[test]
+    fn assemble_signed_field_bounds() {
+        let word = |line: &str| {
+            assemble(&format!(".ORIG x3000\n{line}\n.END"))
+                .map(|assembled| assembled.blocks[0].words[0])
+        };
+
+        // imm5
+        assert_eq!(word("ADD R1, R1, #15"), Ok(0x126F));
+        assert_eq!(word("ADD R1, R1, #-16"), Ok(0x1270));
+        assert!(word("ADD R1, R1, #16").is_err());
+        assert!(word("ADD R1, R1, #31").is_err());
+        assert!(word("AND R1, R1, #-17").is_err());
+        // offset6
+        assert_eq!(word("LDR R1, R2, #31"), Ok(0x629F));
+        assert_eq!(word("LDR R1, R2, #-32"), Ok(0x62A0));
+        assert!(word("LDR R1, R2, #32").is_err());
+        assert!(word("STR R1, R2, #63").is_err());
+        assert!(word("STR R1, R2, #-33").is_err());
+        // PCoffset9
+        assert_eq!(word("LD R1, #255"), Ok(0x22FF));
+        assert_eq!(word("LD R1, #-256"), Ok(0x2300));
+        assert!(word("LD R1, #256").is_err());
+        assert!(word("BRz #-257").is_err());
+        // PCoffset11
+        assert_eq!(word("JSR #1023"), Ok(0x4BFF));
+        assert_eq!(word("JSR #-1024"), Ok(0x4C00));
+        assert!(word("JSR #1024").is_err());
+        assert!(word("JSR #-1025").is_err());
+        // Hex is read as a 16 bit word, so xFFFF is -1
+        assert_eq!(word("ADD R1, R1, xFFFF"), Ok(0x127F));
+        assert!(word("ADD R1, R1, x1F").is_err());
+    }
+
+    #[test]
+    fn assemble_requires_origin() {
+        assert!(assemble("ADD R1, R1, R1").is_err());
+        assert!(assemble("").is_err());
+    }
+
+    #[test]
+    fn assemble_pseudo_ops() {
+        let source = r#"
+                    .ORIG   x3000
+                    LEA     R0, TEXT
+            PTR     .FILL   TEXT
+                    .FILL   #-3
+                    .FILL   xBEEF
+            SPACE   .BLKW   2
+            TEXT    .STRINGZ "a\n\"b\\"
+            EMPTY   .STRINGZ ""
+                    .END
+            "#;
+        let assembled = assemble(source).unwrap();
+
+        assert_eq!(
+            assembled.blocks[0].words,
+            vec![
+                0xE005, 0x3006, 0xFFFD, 0xBEEF, 0x0000, 0x0000, 0x0061, 0x000A, 0x0022, 0x0062,
+                0x005C, 0x0000, 0x0000
+            ]
+        );
+        assert_eq!(assembled.symbols["PTR"], 0x3001);
+        assert_eq!(assembled.symbols["SPACE"], 0x3004);
+        assert_eq!(assembled.symbols["TEXT"], 0x3006);
+        assert_eq!(assembled.symbols["EMPTY"], 0x300C);
+    }
+
+    #[test]
+    fn assemble_multiple_blocks() {
+        let source = "
+                    .ORIG   x3000
+            MAIN    LD      R0, DATA_PTR
+                    JSRR    R0
+                    RET
+            DATA_PTR .FILL  DATA
+                    .END
+            this line is ignored
+                    .ORIG   x4000
+            DATA    .FILL   MAIN
+                    .END
+            ";
+        let source = source.to_string() + ".ORIG x2FFF\n.FILL x1\n";
+        let assembled = assemble(&source).unwrap();
+
+        assert_eq!(
+            assembled.blocks,
+            vec![
+                ObjBlock {
+                    origin: 0x3000,
+                    words: vec![0x2002, 0x4000, 0xC1C0, 0x4000]
+                },
+                ObjBlock {
+                    origin: 0x4000,
+                    words: vec![0x3000]
+                },
+                ObjBlock {
+                    origin: 0x2FFF,
+                    words: vec![0x0001]
+                },
+            ]
+        );
+
+        let lines: Vec<_> = assembled.lines.iter().map(|(a, l)| (*a, *l)).collect();
+        assert_eq!(
+            lines,
+            vec![
+                (0x2FFF, 13),
+                (0x3000, 3),
+                (0x3001, 4),
+                (0x3002, 5),
+                (0x3003, 6),
+                (0x4000, 10)
+            ]
+        );
+
+        let mut processor = CoreLC3::new();
+        assembled.populate(&mut processor);
+        assert_eq!(processor.mem(0x2FFF), 0x0001);
+        assert_eq!(processor.mem(0x3003), 0x4000);
+        assert_eq!(processor.mem(0x4000), 0x3000);
+    }
+
+    #[test]
+    fn assemble_overlapping_blocks() {
+        let source = "
+                    .ORIG   x3000
+                    .BLKW   4
+                    .END
+                    .ORIG   x3003
+                    .FILL   x0
+                    .END
+            ";
+        let errors = assemble(source).unwrap_err().0;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span.line, 6);
+
+        let source = ".ORIG xFFFF\n.BLKW 2";
+        assert!(assemble(source).is_err());
+    }
+
+    #[test]
+    fn assemble_bad_pseudo_ops() {
+        let source = r#"
+                    .ORIG   x3000
+                    .FILL
+                    .BLKW   LABEL
+                    .STRINGZ "\q"
+                    .STRINGZ "ü"
+                    .STRINGZ 5
+            "#;
+        let lines: Vec<_> = assemble(source)
+            .unwrap_err()
+            .0
+            .iter()
+            .map(|err| err.span.line)
+            .collect();
+        assert_eq!(lines, vec![3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn assemble_outside_block() {
+        let source = "
+            EARLY   ADD     R0, R0, R0
+                    .END
+            ";
+        let lines: Vec<_> = assemble(source)
+            .unwrap_err()
+            .0
+            .iter()
+            .map(|err| err.span.line)
+            .collect();
+        assert_eq!(lines, vec![2, 2, 3, 4]);
+    }
+
+    #[test]
+    fn assemble_diagnostics() {
+        let source = "\
+        .ORIG   x3000
+        ADD     R1, R1, #32
+        BRp     MISSING
+        JMP     R1 R2
+        ";
+        let errors = assemble_named("prog.asm", source).unwrap_err().0;
+
+        let summary: Vec<_> = errors
+            .iter()
+            .map(|err| (err.code(), err.span.line, err.span.columns.clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("E008", 2, 24..27),
+                ("E007", 4, 19..21),
+                ("E009", 3, 16..23)
+            ]
+        );
+        assert!(errors
+            .iter()
+            .all(|err| err.span.file.as_deref() == Some("prog.asm")));
+
+        assert_eq!(
+            errors[2].to_string(),
+            "error[E009]: label MISSING is never defined\n \
+             --> prog.asm:3:17\n  \
+             |\n\
+             3 |         BRp     MISSING\n  \
+             |                 ^^^^^^^"
+        );
+    }
 }
@@ -0,0 +1,272 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, Write},
+    ops::Index,
+};
+
+use thiserror::Error;
+
+use crate::defs::LC3MemAddr;
+
+/// Address of every label in a program, as produced by
+/// [`assemble`](super::assemble).
+///
+/// Supports lookup in both directions, so tools can show label names for
+/// addresses. Tables are equal if they define the same labels, regardless of
+/// definition order.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    addrs: BTreeMap<String, LC3MemAddr>,
+    /// Labels at each address, in definition order.
+    labels: BTreeMap<LC3MemAddr, Vec<String>>,
+}
+
+/// Failure to read a `.sym` file.
+#[derive(Debug, Error)]
+pub enum SymParseError {
+    #[error("line {line}: {text:?} is not a symbol table entry")]
+    InvalidEntry { line: usize, text: String },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Define `label` at `addr`.
+    ///
+    /// Returns the previous address if `label` was already defined.
+    pub fn insert<S: Into<String>>(&mut self, label: S, addr: LC3MemAddr) -> Option<LC3MemAddr> {
+        let label = label.into();
+        let prev = self.addrs.insert(label.clone(), addr);
+
+        if let Some(prev) = prev {
+            if let Some(labels) = self.labels.get_mut(&prev) {
+                labels.retain(|existing| *existing != label);
+                if labels.is_empty() {
+                    self.labels.remove(&prev);
+                }
+            }
+        }
+        self.labels.entry(addr).or_default().push(label);
+
+        prev
+    }
+
+    /// Address of `label`, if defined.
+    pub fn get(&self, label: &str) -> Option<LC3MemAddr> {
+        self.addrs.get(label).copied()
+    }
+
+    /// First label defined at `addr`, if any.
+    pub fn label(&self, addr: LC3MemAddr) -> Option<&str> {
+        self.labels(addr).first().map(String::as_str)
+    }
+
+    /// Every label defined at `addr`, in definition order.
+    pub fn labels(&self, addr: LC3MemAddr) -> &[String] {
+        self.labels.get(&addr).map_or(&[], Vec::as_slice)
+    }
+
+    /// All labels, ordered by address.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, LC3MemAddr)> {
+        self.labels
+            .iter()
+            .flat_map(|(addr, labels)| labels.iter().map(|label| (label.as_str(), *addr)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.addrs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addrs.is_empty()
+    }
+
+    /// Write the table in PennSim's `.sym` text format.
+    ///
+    /// This is the commented table originally written by `lc3as`, with a tab
+    /// after each entry's `//`. PennSim treats labels case-insensitively, so
+    /// they are written upper case:
+    ///
+    /// ```text
+    /// // Symbol table
+    /// // Scope level 0:
+    /// //    Symbol Name       Page Address
+    /// //    ----------------  ------------
+    /// //    LOOP              3002
+    /// ```
+    pub fn write_sym<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, "// Symbol table")?;
+        writeln!(out, "// Scope level 0:")?;
+        writeln!(out, "//\tSymbol Name       Page Address")?;
+        writeln!(out, "//\t----------------  ------------")?;
+        for (label, addr) in self.iter() {
+            let label = label.to_uppercase();
+            writeln!(out, "//\t{label:<16}  {addr:04X}")?;
+        }
+        writeln!(out)
+    }
+
+    /// [`Self::write_sym`] into a `String`.
+    pub fn to_sym_string(&self) -> String {
+        let mut out = Vec::new();
+        self.write_sym(&mut out)
+            .expect("Writing to a Vec cannot fail");
+        String::from_utf8(out).expect("Symbol table is written as UTF-8")
+    }
+
+    /// Read a table in the `.sym` text format.
+    ///
+    /// Labels are kept as written. PennSim's `$` entries, marking each
+    /// instruction address, are skipped.
+    pub fn read_sym<R: BufRead>(input: R) -> Result<Self, SymParseError> {
+        let mut table = Self::new();
+
+        for (idx, text) in input.lines().enumerate() {
+            let text = text?;
+            let invalid = || SymParseError::InvalidEntry {
+                line: idx + 1,
+                text: text.clone(),
+            };
+
+            let Some(entry) = text.strip_prefix("//\t") else {
+                // Header comments and trailing blank lines
+                if text.starts_with("//") || text.trim().is_empty() {
+                    continue;
+                } else {
+                    return Err(invalid());
+                }
+            };
+
+            match entry.split_whitespace().collect::<Vec<_>>().as_slice() {
+                ["Symbol", "Name", "Page", "Address"] => (),
+                [dashes, _] if dashes.starts_with('-') => (),
+                // PennSim instruction markers
+                ["$", _] => (),
+                [label, addr] => {
+                    let addr = LC3MemAddr::from_str_radix(addr, 16).map_err(|_| invalid())?;
+                    table.insert(*label, addr);
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(table)
+    }
+}
+
+impl PartialEq for SymbolTable {
+    fn eq(&self, other: &Self) -> bool {
+        self.addrs == other.addrs
+    }
+}
+
+impl Eq for SymbolTable {}
+
+impl Index<&str> for SymbolTable {
+    type Output = LC3MemAddr;
+
+    fn index(&self, label: &str) -> &Self::Output {
+        &self.addrs[label]
+    }
+}
+
+impl<S: Into<String>> FromIterator<(S, LC3MemAddr)> for SymbolTable {
+    fn from_iter<T: IntoIterator<Item = (S, LC3MemAddr)>>(iter: T) -> Self {
+        let mut table = Self::new();
+        for (label, addr) in iter {
+            table.insert(label, addr);
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sample() -> SymbolTable {
+        [("Loop", 0x3002), ("START", 0x3000), ("also_start", 0x3000)]
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn lookup() {
+        let mut table = sample();
+
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.get("Loop"), Some(0x3002));
+        assert_eq!(table["START"], 0x3000);
+        assert_eq!(table.get("loop"), None);
+        assert_eq!(table.label(0x3000), Some("START"));
+        assert_eq!(table.labels(0x3000), ["START", "also_start"]);
+        assert_eq!(table.label(0x3001), None);
+
+        assert_eq!(table.insert("START", 0x3001), Some(0x3000));
+        assert_eq!(table.label(0x3000), Some("also_start"));
+        assert_eq!(table.label(0x3001), Some("START"));
+        assert_eq!(
+            table.iter().collect::<Vec<_>>(),
+            vec![("also_start", 0x3000), ("START", 0x3001), ("Loop", 0x3002)]
+        );
+    }
+
+    #[test]
+    fn write_formats() {
+        let table = sample();
+
+        assert_eq!(
+            table.to_sym_string(),
+            "// Symbol table\n\
+             // Scope level 0:\n\
+             //\tSymbol Name       Page Address\n\
+             //\t----------------  ------------\n\
+             //\tSTART             3000\n\
+             //\tALSO_START        3000\n\
+             //\tLOOP              3002\n\
+             \n"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let table: SymbolTable = [("LOOP", 0x3002), ("START", 0x3000)].into_iter().collect();
+        let read = SymbolTable::read_sym(table.to_sym_string().as_bytes());
+        assert_eq!(read.unwrap(), table);
+    }
+
+    #[test]
+    fn read_penn_sim() {
+        let sym = "// Symbol table\n\
+                   // Scope level 0:\n\
+                   //\tSymbol Name       Page Address\n\
+                   //\t----------------  ------------\n\
+                   //\tDONE              3002\n\
+                   //\tREV               3000\n\
+                   //\t$               3000\n\
+                   //\t$               3001\n\
+                   \n";
+        let table = SymbolTable::read_sym(sym.as_bytes()).unwrap();
+
+        assert_eq!(
+            table.iter().collect::<Vec<_>>(),
+            vec![("REV", 0x3000), ("DONE", 0x3002)]
+        );
+    }
+
+    #[test]
+    fn read_invalid() {
+        assert!(matches!(
+            SymbolTable::read_sym("//\tLABEL  xyz\n".as_bytes()),
+            Err(SymParseError::InvalidEntry { line: 1, .. })
+        ));
+        assert!(matches!(
+            SymbolTable::read_sym("// Symbol table\nLABEL 3000\n".as_bytes()),
+            Err(SymParseError::InvalidEntry { line: 2, .. })
+        ));
+    }
+}
@@ -1,15 +1,22 @@
-use anyhow::{bail, Result};
+use std::ops::Range;
+
 use once_cell::sync::Lazy;
-use regex::{bytes::RegexSet, Regex};
+use regex::{
+    bytes::{RegexSet, RegexSetBuilder},
+    Regex,
+};
+
+use crate::assembler::{AsmErrorKind, LineError, Token};
+use crate::defs::{LC3Word, Op, PseudoOp, RegAddr, SignedLC3Word};
 
-use crate::assembler::Token;
-use crate::defs::{Op, PseudoOp, RegAddr};
+// Mnemonics, directives and registers are case-insensitive, as in PennSim and
+// lc3tools. Labels are not.
 
 // This follows the same ordering as defs.rs > pub enum Op
-const INSTR_PATTERN: [&str; 23] = [
+const INSTR_PATTERN: [&str; 24] = [
     r"^ADD$",
     r"^AND$",
-    r"^BR[nN]?[zZ]?[pP]?$",
+    r"^BRN?Z?P?$",
     r"^JMP$",
     r"^JSR$",
     r"^JSRR$",
@@ -30,23 +37,38 @@ const INSTR_PATTERN: [&str; 23] = [
     r"^IN$",
     r"^PUTSP$",
     r"^HALT$",
+    r"^JMPT$",
 ];
 
-const META_PATTERN: [&str; 5] = [r"^.ORIG$", r"^.FILL$", r"^BLKW$", r"^.STRINGZ$", r"^.END$"];
-const NUM_PATTERN: &str = r"^[x|#|b]-?[0-9A-F]*$";
-const REG_PATTERN: &str = r"^R[0-7],?$";
+// This follows the same ordering as defs.rs > pub enum PseudoOp
+const META_PATTERN: [&str; 5] = [
+    r"^\.ORIG$",
+    r"^\.FILL$",
+    r"^\.BLKW$",
+    r"^\.STRINGZ$",
+    r"^\.END$",
+];
+const NUM_PATTERN: &str = r"^([xX]-?[0-9A-Fa-f]+|#-?[0-9]+|[bB]-?[01]+|-?[0-9]+)$";
+const REG_PATTERN: &str = r"(?i)^R[0-7],?$";
 const COMMENT_PATTERN: &str = r"^;.*$";
 const STRING_PATTERN: &str = r"^[0-9a-zA-Z[:punct:]]+$";
 
 // Regexes get lazy compiled then stored for reuse
 static RE_REGISTER: Lazy<Regex> = Lazy::new(|| Regex::new(REG_PATTERN).unwrap());
 static RE_COMMENT: Lazy<Regex> = Lazy::new(|| Regex::new(COMMENT_PATTERN).unwrap());
-static RE_INSTR: Lazy<RegexSet> = Lazy::new(|| RegexSet::new(INSTR_PATTERN).unwrap());
-static RE_META: Lazy<RegexSet> = Lazy::new(|| RegexSet::new(META_PATTERN).unwrap());
+static RE_INSTR: Lazy<RegexSet> = Lazy::new(|| case_insensitive(&INSTR_PATTERN));
+static RE_META: Lazy<RegexSet> = Lazy::new(|| case_insensitive(&META_PATTERN));
 static RE_NUM: Lazy<Regex> = Lazy::new(|| Regex::new(NUM_PATTERN).unwrap());
 static RE_STRING: Lazy<Regex> = Lazy::new(|| Regex::new(STRING_PATTERN).unwrap());
 
-fn match_op(line: &str, target: Vec<usize>) -> Result<Op> {
+fn case_insensitive(patterns: &[&str]) -> RegexSet {
+    RegexSetBuilder::new(patterns)
+        .case_insensitive(true)
+        .build()
+        .unwrap()
+}
+
+fn match_op(line: &str, target: Vec<usize>) -> Result<Op, AsmErrorKind> {
     let mut instr_type: Op = Op::ILLEGAL;
     for item in target {
         // this should be fine because there should only ever be 1 item in the vec
@@ -57,10 +79,16 @@ fn match_op(line: &str, target: Vec<usize>) -> Result<Op> {
             2 => {
                 // this was written before this returned a vector of tokens
                 // it might be better to turn these into separate tokens
-                let n: bool = line.contains(['n', 'N']);
-                let z: bool = line.contains(['z', 'Z']);
-                let p: bool = line.contains(['p', 'P']);
-                Op::BR(n, z, p)
+                let cond = &line[2..];
+                let n: bool = cond.contains(['n', 'N']);
+                let z: bool = cond.contains(['z', 'Z']);
+                let p: bool = cond.contains(['p', 'P']);
+                // A bare BR is unconditional
+                if n || z || p {
+                    Op::BR(n, z, p)
+                } else {
+                    Op::BR(true, true, true)
+                }
             }
             3 => Op::JMP,
             4 => Op::JSR,
@@ -69,25 +97,27 @@ fn match_op(line: &str, target: Vec<usize>) -> Result<Op> {
             7 => Op::LDI,
             8 => Op::LDR,
             9 => Op::LEA,
-            10 => Op::RET,
-            11 => Op::RTI,
-            12 => Op::ST,
-            13 => Op::STI,
-            14 => Op::STR,
-            15 => Op::TRAP,
-            16 => Op::GETC,
-            17 => Op::OUT,
-            18 => Op::PUTS,
-            19 => Op::IN,
-            20 => Op::PUTSP,
-            21 => Op::HALT,
-            _ => bail!("Could not match with an operation. Likely an illegal op!"),
+            10 => Op::NOT,
+            11 => Op::RET,
+            12 => Op::RTI,
+            13 => Op::ST,
+            14 => Op::STI,
+            15 => Op::STR,
+            16 => Op::TRAP,
+            17 => Op::GETC,
+            18 => Op::OUT,
+            19 => Op::PUTS,
+            20 => Op::IN,
+            21 => Op::PUTSP,
+            22 => Op::HALT,
+            23 => Op::JMPT,
+            _ => return Err(AsmErrorKind::UnknownToken(line.to_string())),
         };
     }
     Ok(instr_type)
 }
 
-fn match_pseudo_op(target: Vec<usize>) -> Result<PseudoOp> {
+fn match_pseudo_op(line: &str, target: Vec<usize>) -> Result<PseudoOp, AsmErrorKind> {
     let mut pseudo_instr_type: PseudoOp = PseudoOp::ILLEGAL;
 
     for item in target {
@@ -99,20 +129,22 @@ fn match_pseudo_op(target: Vec<usize>) -> Result<PseudoOp> {
             2 => PseudoOp::BLKW,
             3 => PseudoOp::STRINGZ,
             4 => PseudoOp::END,
-            _ => bail!("Could not match with any pseudo operation. Likely an illegal psuedo-op!"),
+            _ => return Err(AsmErrorKind::UnknownToken(line.to_string())),
         };
     }
     Ok(pseudo_instr_type)
 }
 
 /// Take in a `&str`, returning a `Vec<Token>` that contains all syntax morphemes in the str.
-pub fn tokenize(line: &str) -> Result<Vec<Token>> {
+pub fn tokenize(line: &str) -> Result<Vec<Token>, AsmErrorKind> {
     let mut token: Vec<Token> = Vec::new(); // this value is ultimately returned
 
     if RE_REGISTER.is_match(line) {
-        let reg_num_char: char = line.chars().nth(1).unwrap();
-        let reg_num_int: u8 = reg_num_char.to_digit(10).unwrap() as u8;
-        token.push(Token::REGISTER(RegAddr::try_from(reg_num_int)?));
+        // The pattern guarantees a digit in [0, 7]
+        let reg_num_int: u8 = line.as_bytes()[1] - b'0';
+        let reg = RegAddr::try_from(reg_num_int)
+            .map_err(|_| AsmErrorKind::UnknownToken(line.to_string()))?;
+        token.push(Token::REGISTER(reg));
         if line.ends_with(',') {
             token.push(Token::COMMA)
         }
@@ -126,17 +158,22 @@ pub fn tokenize(line: &str) -> Result<Vec<Token>> {
         Ok(token)
     } else if RE_META.is_match(line.as_bytes()) {
         let matches: Vec<usize> = RE_META.matches(line.as_bytes()).into_iter().collect();
-        token.push(Token::META(match_pseudo_op(matches)?));
+        token.push(Token::META(match_pseudo_op(line, matches)?));
         Ok(token)
     } else if RE_NUM.is_match(line) {
-        let num = if line.starts_with('x') {
-            u16::from_str_radix(line.strip_prefix('x').unwrap(), 16).unwrap()
-        } else if line.starts_with('#') {
-            line.strip_prefix('#').unwrap().parse().unwrap()
-        } else {
-            bail!("Found invalid number declaration!")
+        let (digits, radix) = match line.split_at(1) {
+            ("x" | "X", digits) => (digits, 16),
+            ("#", digits) => (digits, 10),
+            ("b" | "B", digits) => (digits, 2),
+            // Bare decimal
+            _ => (line, 10),
         };
-        token.push(Token::NUM(num));
+        let num = i32::from_str_radix(digits, radix)
+            .ok()
+            .filter(|num| (i32::from(SignedLC3Word::MIN)..=i32::from(LC3Word::MAX)).contains(num))
+            .ok_or_else(|| AsmErrorKind::NumberTooLarge(line.to_string()))?;
+        // Negative numbers are stored as two's complement
+        token.push(Token::NUM(num as LC3Word));
         Ok(token)
     } else if RE_STRING.is_match(line.trim_matches('"')) {
         // Strings and labels are functionally the same but one has quotes.
@@ -152,8 +189,103 @@ pub fn tokenize(line: &str) -> Result<Vec<Token>> {
         }
         Ok(token)
     } else {
-        bail!("Could not match with a token");
+        Err(AsmErrorKind::UnknownToken(line.to_string()))
+    }
+}
+
+/// Take in a full source line, returning a `Vec<Token>` of everything on it.
+///
+/// Operands are split on whitespace and commas, `;` starts a comment that runs
+/// to the end of the line, and double quoted strings are kept whole. The chain
+/// is always terminated with [`Token::SEMICOLON`].
+pub fn tokenize_line(line: &str) -> Result<Vec<Token>, LineError> {
+    Ok(tokenize_line_spanned(line)?
+        .into_iter()
+        .map(|(token, _)| token)
+        .collect())
+}
+
+/// [`tokenize_line`], with the byte range of the text each token came from.
+///
+/// The terminating [`Token::SEMICOLON`] covers the column after the line.
+pub fn tokenize_line_spanned(line: &str) -> Result<Vec<(Token, Range<usize>)>, LineError> {
+    let mut tokens: Vec<(Token, Range<usize>)> = Vec::new();
+    let mut pos = line.len() - line.trim_start().len();
+
+    while let Some(next) = line[pos..].chars().next() {
+        let rest = &line[pos..];
+        let len = match next {
+            ';' => {
+                let comment = rest.trim_end();
+                push_word(&mut tokens, comment, pos)?;
+                comment.len()
+            }
+            ',' => {
+                tokens.push((Token::COMMA, pos..(pos + 1)));
+                1
+            }
+            '"' => {
+                let Some(len) = string_len(&rest[1..]) else {
+                    return Err(LineError {
+                        columns: pos..line.trim_end().len(),
+                        kind: AsmErrorKind::UnclosedString,
+                    });
+                };
+                tokens.push((Token::QUOTES, pos..(pos + 1)));
+                tokens.push((
+                    Token::STRING(rest[1..(len + 1)].to_string()),
+                    (pos + 1)..(pos + len + 1),
+                ));
+                tokens.push((Token::QUOTES, (pos + len + 1)..(pos + len + 2)));
+                len + 2
+            }
+            _ => {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || [',', ';', '"'].contains(&c))
+                    .unwrap_or(rest.len());
+                push_word(&mut tokens, &rest[..end], pos)?;
+                end
+            }
+        };
+        pos += len;
+        pos += line[pos..].len() - line[pos..].trim_start().len();
     }
+
+    let end = line.trim_end().len();
+    tokens.push((Token::SEMICOLON, end..(end + 1)));
+    Ok(tokens)
+}
+
+/// Tokenize a single word starting at byte `pos`, giving every token its span.
+fn push_word(
+    tokens: &mut Vec<(Token, Range<usize>)>,
+    word: &str,
+    pos: usize,
+) -> Result<(), LineError> {
+    let columns = pos..(pos + word.len());
+    let word_tokens = tokenize(word).map_err(|kind| LineError {
+        columns: columns.clone(),
+        kind,
+    })?;
+    tokens.extend(
+        word_tokens
+            .into_iter()
+            .map(|token| (token, columns.clone())),
+    );
+    Ok(())
+}
+
+/// Length of the string contents before the closing (unescaped) quote.
+fn string_len(contents: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (idx, c) in contents.char_indices() {
+        match c {
+            '"' if !escaped => return Some(idx),
+            '\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
+    }
+    None
 }
 
 #[cfg(test)]
@@ -168,6 +300,40 @@ mod test {
         assert_eq!(result[0], Token::REGISTER(RegAddr::Zero));
     }
 
+    #[test]
+    fn tokenize_branch() {
+        let result: Vec<Token> = tokenize("BRnz").unwrap();
+        assert_eq!(result[0], Token::INSTR(Op::BR(true, true, false)));
+
+        // Bare BR is shorthand for BRnzp
+        let result: Vec<Token> = tokenize("BR").unwrap();
+        assert_eq!(result[0], Token::INSTR(Op::BR(true, true, true)));
+    }
+
+    #[test]
+    fn tokenize_any_case() {
+        assert_eq!(tokenize("add").unwrap(), [Token::INSTR(Op::ADD)]);
+        assert_eq!(tokenize("Halt").unwrap(), [Token::INSTR(Op::HALT)]);
+        assert_eq!(
+            tokenize("brnZ").unwrap(),
+            [Token::INSTR(Op::BR(true, true, false))]
+        );
+        assert_eq!(
+            tokenize("br").unwrap(),
+            [Token::INSTR(Op::BR(true, true, true))]
+        );
+        assert_eq!(tokenize(".end").unwrap(), [Token::META(PseudoOp::END)]);
+        assert_eq!(
+            tokenize(".Stringz").unwrap(),
+            [Token::META(PseudoOp::STRINGZ)]
+        );
+        assert_eq!(tokenize("X3000").unwrap(), [Token::NUM(0x3000)]);
+        assert_eq!(
+            tokenize("r7,").unwrap(),
+            [Token::REGISTER(RegAddr::Seven), Token::COMMA]
+        );
+    }
+
     #[test]
     fn tokenize_register_comma() {
         // The number of registers is small enough that checking that all of them parse manually is fine
@@ -223,6 +389,13 @@ mod test {
         assert_eq!(result[0], Token::META(PseudoOp::ORIG));
     }
 
+    #[test]
+    fn tokenize_meta_blkw() {
+        let test_str: &str = ".BLKW";
+        let result: Vec<Token> = tokenize(test_str).unwrap();
+        assert_eq!(result[0], Token::META(PseudoOp::BLKW));
+    }
+
     #[test]
     #[should_panic]
     fn tokenize_meta_missing_dot() {
@@ -253,4 +426,119 @@ mod test {
         assert_eq!(result[0], Token::STRING("String.".to_string()));
         assert_eq!(result[1], Token::QUOTES);
     }
+
+    #[test]
+    fn tokenize_num_bare_dec() {
+        let test_str: &str = "12";
+        let result: Vec<Token> = tokenize(test_str).unwrap();
+        assert_eq!(result[0], Token::NUM(12));
+    }
+
+    #[test]
+    fn tokenize_num_negative() {
+        let test_str: &str = "#-1";
+        let result: Vec<Token> = tokenize(test_str).unwrap();
+        assert_eq!(result[0], Token::NUM(0xFFFF));
+    }
+
+    #[test]
+    fn tokenize_num_lower_hex() {
+        let test_str: &str = "xfe00";
+        let result: Vec<Token> = tokenize(test_str).unwrap();
+        assert_eq!(result[0], Token::NUM(0xFE00));
+    }
+
+    #[test]
+    fn tokenize_num_too_big() {
+        assert!(tokenize("#65536").is_err());
+        assert!(tokenize("#-32769").is_err());
+    }
+
+    #[test]
+    fn tokenize_hex_like_label() {
+        let test_str: &str = "xor";
+        let result: Vec<Token> = tokenize(test_str).unwrap();
+        assert_eq!(result[0], Token::STRING("xor".to_string()));
+    }
+
+    #[test]
+    fn tokenize_full_line() {
+        let test_str: &str = "LOOP1   LDR      R3,R1,#1     ;; Note -- LDR";
+        let result: Vec<Token> = tokenize_line(test_str).unwrap();
+        assert_eq!(
+            result,
+            vec![
+                Token::STRING("LOOP1".to_string()),
+                Token::INSTR(Op::LDR),
+                Token::REGISTER(RegAddr::Three),
+                Token::COMMA,
+                Token::REGISTER(RegAddr::One),
+                Token::COMMA,
+                Token::NUM(1),
+                Token::COMMENT(";; Note -- LDR".to_string()),
+                Token::SEMICOLON,
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_full_line_not() {
+        let result: Vec<Token> = tokenize_line("NOT R1, R1").unwrap();
+        assert_eq!(result[0], Token::INSTR(Op::NOT));
+    }
+
+    #[test]
+    fn tokenize_full_line_string() {
+        let test_str: &str = "FILE .STRINGZ \"This is; \\\"fun\\\"\"";
+        let result: Vec<Token> = tokenize_line(test_str).unwrap();
+        assert_eq!(result[2], Token::QUOTES);
+        assert_eq!(result[3], Token::STRING("This is; \\\"fun\\\"".to_string()));
+        assert_eq!(result[4], Token::QUOTES);
+        assert_eq!(result[5], Token::SEMICOLON);
+    }
+
+    #[test]
+    fn tokenize_full_line_unclosed_string() {
+        assert!(tokenize_line(".STRINGZ \"oops").is_err());
+    }
+
+    #[test]
+    fn full_line_spans() {
+        let spans: Vec<_> = tokenize_line_spanned("  LOOP ADD R1,R1, #-1 ; dec")
+            .unwrap()
+            .into_iter()
+            .map(|(_, columns)| columns)
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                2..6,
+                7..10,
+                11..13,
+                13..14,
+                14..16,
+                16..17,
+                18..21,
+                22..27,
+                27..28
+            ]
+        );
+
+        let spans: Vec<_> = tokenize_line_spanned(".STRINGZ \"hi\"")
+            .unwrap()
+            .into_iter()
+            .map(|(_, columns)| columns)
+            .collect();
+        assert_eq!(spans, vec![0..8, 9..10, 10..12, 12..13, 13..14]);
+
+        let err = tokenize_line_spanned("ADD R1, R1, #99999").unwrap_err();
+        assert_eq!(err.columns, 12..18);
+        assert_eq!(err.kind, AsmErrorKind::NumberTooLarge("#99999".to_string()));
+    }
+
+    #[test]
+    fn tokenize_blank_line() {
+        let result: Vec<Token> = tokenize_line("   \t ").unwrap();
+        assert_eq!(result, vec![Token::SEMICOLON]);
+    }
 }
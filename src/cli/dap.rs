@@ -0,0 +1,1059 @@
+//! Debug Adapter Protocol server, so editors can debug programs.
+//!
+//! Messages are JSON with a `Content-Length` header, normally over stdin and
+//! stdout. The `launch` request takes:
+//!
+//! | Argument      | Meaning                                         |
+//! |---------------|-------------------------------------------------|
+//! | `program`     | `.asm` or `.obj` file to debug                  |
+//! | `os`          | OS image to load before the program             |
+//! | `lc3os`       | Load the bundled PennSim OS before the program  |
+//! | `stopOnEntry` | Stop before the first instruction               |
+//!
+//! Breakpoints are set by source line in `.asm` files, including the source
+//! next to an `.obj`. Stepping over or out treats JSR, JSRR and TRAP as
+//! calls. Program output goes to the debug console, and text typed there is
+//! sent to the program as keyboard input, followed by a newline.
+//!
+//! Memory references are word addresses like `0x3000`, while offsets and
+//! counts are in bytes, two per word, big endian.
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use lc3sim_project::{
+    assembler::{assemble, SymbolTable},
+    debugger::{Breakpoint, BreakpointId, Debugger, Flow, StopReason},
+    defs::{LC3MemAddr, LC3Word, RegAddr},
+    disassembler::disassemble,
+    executors::{core::CoreLC3, StepFailure, LC3},
+    harnesses::{console::ConsoleIO, ExecutionFailure},
+};
+
+use crate::{
+    debug::{format_value, parse_target, parse_value, Run, Target},
+    Machine, MachineArgs,
+};
+
+/// Steps taken between checks for new requests while running.
+const CHUNK: u32 = 1000;
+
+/// The only thread.
+const THREAD_ID: i64 = 1;
+
+/// Variables reference of the registers scope.
+const REGISTERS_REF: i64 = 1;
+/// Variables reference of the PSR's fields.
+const PSR_REF: i64 = 2;
+
+type Console = ConsoleIO<VecDeque<u8>, Vec<u8>>;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LaunchArgs {
+    program: PathBuf,
+    #[serde(default)]
+    os: Option<PathBuf>,
+    #[serde(default)]
+    lc3os: bool,
+    #[serde(default)]
+    stop_on_entry: bool,
+}
+
+/// The launched program.
+struct Session {
+    debugger: Debugger<CoreLC3>,
+    console: Console,
+    symbols: SymbolTable,
+    /// Breakpoints of each source file.
+    breakpoints: HashMap<PathBuf, Vec<BreakpointId>>,
+    stop_on_entry: bool,
+    /// Entry point of the current subroutine.
+    entry: LC3MemAddr,
+    /// Call site of every unfinished call, and the entry point of the
+    /// subroutine it was made from, outermost first.
+    calls: Vec<(LC3MemAddr, LC3MemAddr)>,
+    run: Option<Run>,
+    /// Whether the run is paused until more input is typed.
+    waiting: bool,
+}
+
+impl Session {
+    fn lc3(&self) -> &CoreLC3 {
+        self.debugger.processor()
+    }
+}
+
+/// Serves one debugging session, writing messages to `output`.
+pub struct DapServer<W> {
+    output: W,
+    seq: i64,
+    session: Option<Session>,
+    /// Source line of every word, by source file.
+    sources: HashMap<PathBuf, BTreeMap<LC3MemAddr, usize>>,
+}
+
+impl<W: Write> DapServer<W> {
+    pub fn new(output: W) -> Self {
+        Self {
+            output,
+            seq: 0,
+            session: None,
+            sources: HashMap::new(),
+        }
+    }
+
+    /// Serves requests from `input` until the client disconnects.
+    pub fn serve<R: Read + Send + 'static>(self, input: R) -> io::Result<()> {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut input = BufReader::new(input);
+            while let Ok(Some(message)) = read_message(&mut input) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        self.run(receiver)
+    }
+
+    /// Handles `requests` as they come, running the program in between.
+    ///
+    /// A running program keeps running after the requests end, until it
+    /// stops.
+    fn run(mut self, requests: Receiver<Value>) -> io::Result<()> {
+        let mut closed = false;
+        loop {
+            let busy = self
+                .session
+                .as_ref()
+                .is_some_and(|session| session.run.is_some() && !session.waiting);
+
+            let request = if closed {
+                None
+            } else if busy {
+                match requests.try_recv() {
+                    Ok(request) => Some(request),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => {
+                        closed = true;
+                        None
+                    }
+                }
+            } else {
+                requests.recv().ok()
+            };
+
+            match request {
+                Some(request) => {
+                    let open = self.handle(&request)?;
+                    if !open {
+                        return Ok(());
+                    }
+                }
+                None if !busy => return Ok(()),
+                None => {}
+            }
+            self.advance()?;
+        }
+    }
+
+    /// Responds to `request`, returning false once the session is over.
+    fn handle(&mut self, request: &Value) -> io::Result<bool> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let args = &request["arguments"];
+
+        let result = match command {
+            "initialize" => Ok(capabilities()),
+            "launch" => self.launch(args),
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "configurationDone" => self.configuration_done(),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "LC-3" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({ "scopes": [{
+                "name": "Registers",
+                "presentationHint": "registers",
+                "variablesReference": REGISTERS_REF,
+                "expensive": false,
+            }] })),
+            "variables" => self.variables(args),
+            "setVariable" => self.set_variable(args),
+            "continue" => self.resume(Run::Continue, json!({ "allThreadsContinued": true })),
+            "next" => self.resume(Run::Over { depth: 0 }, json!({})),
+            "stepIn" => self.resume(Run::In, json!({})),
+            "stepOut" => self.resume(Run::Out { depth: 0 }, json!({})),
+            "pause" => Ok(json!({})),
+            "evaluate" => self.evaluate(args),
+            "readMemory" => self.read_memory(args),
+            "writeMemory" => self.write_memory(args),
+            "disassemble" => self.disassemble(args),
+            "disconnect" | "terminate" => Ok(json!({})),
+            _ => Err(format!("`{command}` is not supported")),
+        };
+
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": command,
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = message.into(),
+        }
+        self.send(response)?;
+
+        match command {
+            "initialize" => {}
+            "launch" if self.session.is_some() => self.event("initialized", json!({}))?,
+            "configurationDone" if self.session.as_ref().is_some_and(|s| s.stop_on_entry) => {
+                self.stop("entry", None)?
+            }
+            "pause" => self.stop("pause", None)?,
+            "disconnect" => return Ok(false),
+            "terminate" => {
+                self.event("terminated", json!({}))?;
+                return Ok(false);
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let args =
+            LaunchArgs::deserialize(args).map_err(|e| format!("Bad launch arguments: {e}"))?;
+        let machine = MachineArgs {
+            files: vec![args.program.clone()],
+            state: None,
+            os: args.os,
+            lc3os: args.lc3os,
+            pc: None,
+        };
+        let Machine { lc3, symbols } = Machine::load(&machine).map_err(|e| format!("{e:#}"))?;
+
+        let source = if is_source(&args.program) {
+            args.program
+        } else {
+            args.program.with_extension("asm")
+        };
+        self.source_lines(&source);
+
+        self.session = Some(Session {
+            entry: lc3.pc(),
+            debugger: Debugger::new(lc3),
+            console: ConsoleIO::new(VecDeque::new(), Vec::new()),
+            symbols,
+            breakpoints: HashMap::new(),
+            stop_on_entry: args.stop_on_entry,
+            calls: Vec::new(),
+            run: None,
+            waiting: false,
+        });
+        Ok(json!({}))
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let path = PathBuf::from(
+            args["source"]["path"]
+                .as_str()
+                .ok_or("Missing source path")?,
+        );
+        let requested: Vec<u64> = args["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|breakpoint| breakpoint["line"].as_u64())
+            .collect();
+
+        let lines = self.source_lines(&path).cloned().unwrap_or_default();
+        let session = self.session_mut()?;
+
+        // The request replaces every breakpoint in the file
+        for id in session.breakpoints.remove(&path).unwrap_or_default() {
+            session.debugger.remove_breakpoint(id);
+        }
+        let mut ids = Vec::new();
+        let breakpoints: Vec<Value> = requested
+            .into_iter()
+            .map(|line| match addr_of_line(&lines, line as usize) {
+                Some((addr, line)) => {
+                    ids.push(session.debugger.add_breakpoint(Breakpoint::new(addr)));
+                    json!({ "verified": true, "line": line })
+                }
+                None => json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "No code at or after this line",
+                }),
+            })
+            .collect();
+
+        session.breakpoints.insert(path, ids);
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn configuration_done(&mut self) -> Result<Value, String> {
+        let session = self.session_mut()?;
+        if !session.stop_on_entry {
+            session.run = Some(Run::Continue);
+        }
+        Ok(json!({}))
+    }
+
+    /// Sends the response, then resumes once [`Self::run`] advances.
+    fn resume(&mut self, run: Run, body: Value) -> Result<Value, String> {
+        let session = self.session_mut()?;
+        session.run = Some(run);
+        Ok(body)
+    }
+
+    fn stack_trace(&mut self) -> Result<Value, String> {
+        let session = self.session()?;
+
+        // The innermost frame is at the PC, the rest at their call sites
+        let frames: Vec<_> = std::iter::once((session.lc3().pc(), session.entry))
+            .chain(session.calls.iter().rev().copied())
+            .collect();
+
+        let frames: Vec<Value> = frames
+            .iter()
+            .enumerate()
+            .map(|(id, (addr, entry))| {
+                let name = match session.symbols.label(*entry) {
+                    Some(label) => label.to_string(),
+                    None => format!("x{entry:04X}"),
+                };
+                let mut frame = json!({
+                    "id": id,
+                    "name": name,
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": memory_reference(*addr),
+                });
+                if let Some((path, line)) = self.source_of(*addr) {
+                    frame["source"] = source(path);
+                    frame["line"] = line.into();
+                    frame["column"] = 1.into();
+                }
+                frame
+            })
+            .collect();
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    fn variables(&mut self, args: &Value) -> Result<Value, String> {
+        let lc3 = self.session()?.lc3();
+        let variables = match args["variablesReference"].as_i64() {
+            Some(REGISTERS_REF) => (0..8)
+                .map(|reg| Target::Reg(RegAddr::panic_from_u8(reg)))
+                .chain([Target::Pc, Target::Psr])
+                .map(|target| {
+                    let value = target.get(lc3);
+                    let mut variable = json!({
+                        "name": target_name(target),
+                        "value": format_value(value),
+                        "variablesReference": 0,
+                        "memoryReference": memory_reference(value),
+                    });
+                    if target == Target::Psr {
+                        variable["variablesReference"] = PSR_REF.into();
+                    }
+                    variable
+                })
+                .collect(),
+            Some(PSR_REF) => {
+                let cc = match (lc3.negative_cond(), lc3.zero_cond(), lc3.positive_cond()) {
+                    (true, _, _) => "n",
+                    (_, true, _) => "z",
+                    (_, _, true) => "p",
+                    _ => "-",
+                };
+                let mode = if lc3.privileged() {
+                    "supervisor"
+                } else {
+                    "user"
+                };
+                [
+                    ("CC", cc.to_string()),
+                    ("Mode", mode.to_string()),
+                    ("Priority", lc3.priority().to_string()),
+                ]
+                .into_iter()
+                .map(|(name, value)| json!({ "name": name, "value": value, "variablesReference": 0 }))
+                .collect()
+            }
+            _ => Vec::new(),
+        };
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn set_variable(&mut self, args: &Value) -> Result<Value, String> {
+        if args["variablesReference"].as_i64() != Some(REGISTERS_REF) {
+            return Err("Only registers can be set".to_string());
+        }
+        let session = self.session_mut()?;
+        let name = args["name"].as_str().unwrap_or_default();
+        let target = match parse_target(name, &session.symbols)? {
+            Target::Mem(_) => return Err(format!("`{name}` is not a register")),
+            target => target,
+        };
+        let value = parse_value(args["value"].as_str().unwrap_or_default(), &session.symbols)?;
+
+        target.set(session.debugger.processor_mut(), value);
+        Ok(json!({ "value": format_value(target.get(session.lc3())) }))
+    }
+
+    /// Sends typed text to the program, or shows the value of a register,
+    /// label or address.
+    fn evaluate(&mut self, args: &Value) -> Result<Value, String> {
+        let session = self.session_mut()?;
+        let expression = args["expression"].as_str().unwrap_or_default();
+
+        if args["context"].as_str() == Some("repl") {
+            let input = session.console.input_mut();
+            input.extend(expression.bytes());
+            input.push_back(b'\n');
+            session.waiting = false;
+            return Ok(json!({ "result": "", "variablesReference": 0 }));
+        }
+
+        let value = parse_target(expression.trim(), &session.symbols)?.get(session.lc3());
+        Ok(json!({
+            "result": format_value(value),
+            "variablesReference": 0,
+            "memoryReference": memory_reference(value),
+        }))
+    }
+
+    fn read_memory(&mut self, args: &Value) -> Result<Value, String> {
+        let lc3 = self.session()?.lc3();
+        let addr = memory_addr(args)?;
+        let count = args["count"].as_u64().unwrap_or_default() as usize;
+
+        // Stop at the end of memory
+        let words = count
+            .div_ceil(2)
+            .min(usize::from(LC3MemAddr::MAX - addr) + 1);
+        let data: Vec<u8> = (0..words)
+            .flat_map(|offset| lc3.mem(addr + offset as LC3MemAddr).to_be_bytes())
+            .take(count)
+            .collect();
+        Ok(json!({
+            "address": memory_reference(addr),
+            "data": STANDARD.encode(&data),
+            "unreadableBytes": count - data.len(),
+        }))
+    }
+
+    fn write_memory(&mut self, args: &Value) -> Result<Value, String> {
+        let addr = memory_addr(args)?;
+        let data = STANDARD
+            .decode(args["data"].as_str().unwrap_or_default())
+            .map_err(|e| format!("Bad memory data: {e}"))?;
+        if data.len() % 2 != 0 {
+            return Err("Memory is written in whole words".to_string());
+        }
+        if usize::from(addr) + data.len() / 2 > usize::from(LC3MemAddr::MAX) + 1 {
+            return Err("The data runs past the end of memory".to_string());
+        }
+
+        let lc3 = self.session_mut()?.debugger.processor_mut();
+        for (offset, pair) in data.chunks_exact(2).enumerate() {
+            let value = LC3Word::from_be_bytes([pair[0], pair[1]]);
+            lc3.set_mem(addr + offset as LC3MemAddr, value);
+        }
+        Ok(json!({ "bytesWritten": data.len() }))
+    }
+
+    fn disassemble(&mut self, args: &Value) -> Result<Value, String> {
+        let session = self.session()?;
+        let addr = memory_addr(args)?;
+        let start = i64::from(addr) + args["instructionOffset"].as_i64().unwrap_or_default();
+        let count = args["instructionCount"].as_i64().unwrap_or_default();
+
+        let instructions: Vec<Value> = (start..start + count)
+            .map(|addr| match LC3MemAddr::try_from(addr) {
+                Ok(addr) => {
+                    let word = session.lc3().mem(addr);
+                    let line = &disassemble(addr, [word], Some(&session.symbols))[0];
+                    let mut instruction = json!({
+                        "address": memory_reference(addr),
+                        "instructionBytes": format!("{word:04X}"),
+                        "instruction": line.text,
+                    });
+                    if let Some(label) = &line.label {
+                        instruction["symbol"] = label.as_str().into();
+                    }
+                    if let Some((path, line)) = self.source_of(addr) {
+                        instruction["location"] = source(path);
+                        instruction["line"] = line.into();
+                    }
+                    instruction
+                }
+                // Outside memory
+                Err(_) => json!({
+                    "address": format!("0x{addr:X}"),
+                    "instruction": "",
+                    "presentationHint": "invalid",
+                }),
+            })
+            .collect();
+        Ok(json!({ "instructions": instructions }))
+    }
+
+    /// Runs the program for a while, if it is running.
+    fn advance(&mut self) -> io::Result<()> {
+        let Some(session) = self.session.as_mut() else {
+            return Ok(());
+        };
+        let Some(mut run) = session.run.filter(|_| !session.waiting) else {
+            return Ok(());
+        };
+
+        let mut stop = None;
+        for _ in 0..CHUNK {
+            let addr = session.lc3().pc();
+            let inst = session.lc3().cur_inst();
+            let reason = session.debugger.step(&mut session.console);
+            match reason {
+                StopReason::Failed(ExecutionFailure::NoKeyboard) => stop = Some(Stopped::Waiting),
+                StopReason::Halted
+                | StopReason::Failed(ExecutionFailure::LC3(StepFailure::ClockDisabled)) => {
+                    stop = Some(Stopped::Exited)
+                }
+                StopReason::Failed(e) => stop = Some(Stopped::Failed(e)),
+                _ => {}
+            }
+            if stop.is_some() {
+                break;
+            }
+
+            let pc = session.lc3().pc();
+            let flow = Flow::of(inst, addr, pc);
+            match flow {
+                Flow::Call => {
+                    session.calls.push((addr, session.entry));
+                    session.entry = pc;
+                }
+                Flow::Return => {
+                    if let Some((_, entry)) = session.calls.pop() {
+                        session.entry = entry;
+                    }
+                }
+                Flow::Other => {}
+            }
+
+            if matches!(reason, StopReason::Breakpoint { .. }) {
+                stop = Some(Stopped::Reason("breakpoint"));
+                break;
+            }
+            if run.done(flow) {
+                stop = Some(Stopped::Reason("step"));
+                break;
+            }
+        }
+        session.run = Some(run);
+
+        let output = std::mem::take(session.console.output_mut());
+        if !output.is_empty() {
+            let text = String::from_utf8_lossy(&output);
+            self.event("output", json!({ "category": "stdout", "output": text }))?;
+        }
+
+        match stop {
+            None => Ok(()),
+            Some(Stopped::Reason(reason)) => self.stop(reason, None),
+            Some(Stopped::Failed(e)) => self.stop("exception", Some(e.to_string())),
+            Some(Stopped::Waiting) => {
+                let session = self.session.as_mut().expect("checked above");
+                session.waiting = true;
+                let prompt = "Waiting for input, type it in the debug console\n";
+                self.event("output", json!({ "category": "console", "output": prompt }))
+            }
+            Some(Stopped::Exited) => {
+                self.session.as_mut().expect("checked above").run = None;
+                self.event("exited", json!({ "exitCode": 0 }))?;
+                self.event("terminated", json!({}))
+            }
+        }
+    }
+
+    /// Stops the program, telling the client why.
+    fn stop(&mut self, reason: &str, text: Option<String>) -> io::Result<()> {
+        if let Some(session) = self.session.as_mut() {
+            session.run = None;
+            session.waiting = false;
+        }
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if let Some(text) = text {
+            body["description"] = "Exception".into();
+            body["text"] = text.into();
+        }
+        self.event("stopped", body)
+    }
+
+    fn session(&self) -> Result<&Session, String> {
+        self.session.as_ref().ok_or_else(not_launched)
+    }
+
+    fn session_mut(&mut self) -> Result<&mut Session, String> {
+        self.session.as_mut().ok_or_else(not_launched)
+    }
+
+    /// Source line of every word in the `.asm` file at `path`, assembling
+    /// it the first time.
+    fn source_lines(&mut self, path: &Path) -> Option<&BTreeMap<LC3MemAddr, usize>> {
+        if !self.sources.contains_key(path) {
+            let source = fs::read_to_string(path).ok()?;
+            let assembled = assemble(&source).ok()?;
+            self.sources.insert(path.to_path_buf(), assembled.lines);
+        }
+        self.sources.get(path)
+    }
+
+    /// Source file and line of the word at `addr`, if known.
+    fn source_of(&self, addr: LC3MemAddr) -> Option<(&Path, usize)> {
+        self.sources
+            .iter()
+            .find_map(|(path, lines)| Some((path.as_path(), *lines.get(&addr)?)))
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = self.seq.into();
+        let body = message.to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+        self.output.flush()
+    }
+}
+
+/// Why [`DapServer::advance`] stopped running.
+enum Stopped {
+    /// Stopped with a `stopped` event reason.
+    Reason(&'static str),
+    Failed(ExecutionFailure),
+    /// Needs input that has not been typed yet.
+    Waiting,
+    Exited,
+}
+
+fn capabilities() -> Value {
+    json!({
+        "supportsConfigurationDoneRequest": true,
+        "supportsSetVariable": true,
+        "supportsReadMemoryRequest": true,
+        "supportsWriteMemoryRequest": true,
+        "supportsDisassembleRequest": true,
+        "supportsTerminateRequest": true,
+    })
+}
+
+fn not_launched() -> String {
+    "No program has been launched".to_string()
+}
+
+fn is_source(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "asm")
+}
+
+fn source(path: &Path) -> Value {
+    json!({
+        "name": path.file_name().map(|name| name.to_string_lossy()),
+        "path": path,
+    })
+}
+
+fn target_name(target: Target) -> String {
+    match target {
+        Target::Reg(reg) => reg.to_string(),
+        Target::Pc => "PC".to_string(),
+        Target::Psr => "PSR".to_string(),
+        Target::Mem(addr) => format!("x{addr:04X}"),
+    }
+}
+
+fn memory_reference(addr: LC3MemAddr) -> String {
+    format!("0x{addr:04X}")
+}
+
+/// Word address of a `memoryReference` and byte `offset`.
+fn memory_addr(args: &Value) -> Result<LC3MemAddr, String> {
+    let reference = args["memoryReference"].as_str().unwrap_or_default();
+    let base = reference
+        .strip_prefix("0x")
+        .and_then(|hex| LC3MemAddr::from_str_radix(hex, 16).ok())
+        .ok_or_else(|| format!("`{reference}` is not a memory reference"))?;
+    let offset = args["offset"].as_i64().unwrap_or_default();
+    LC3MemAddr::try_from(i64::from(base) + offset.div_euclid(2))
+        .map_err(|_| "The offset is outside memory".to_string())
+}
+
+/// Address of the first word at or after `line`, and its line.
+fn addr_of_line(lines: &BTreeMap<LC3MemAddr, usize>, line: usize) -> Option<(LC3MemAddr, usize)> {
+    lines
+        .iter()
+        .filter(|(_, word_line)| **word_line >= line)
+        .min_by_key(|(addr, word_line)| (**word_line, **addr))
+        .map(|(addr, line)| (*addr, *line))
+}
+
+/// Reads one message, or `None` at the end of input.
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Value>> {
+    let mut len = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            // Blank lines before a header are skipped
+            if len.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                len = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; len.expect("checked above")];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{env::temp_dir, sync::mpsc::Sender, time::Duration};
+
+    use uuid::Uuid;
+
+    use lc3sim_project::assembler::assemble;
+
+    /// Doubles R1 twice in a subroutine, then reads and echoes a key.
+    const PROGRAM: &str = r#"
+                .ORIG x3000
+        START   AND R1, R1, #0
+                JSR DOUBLE
+                JSR DOUBLE
+                LEA R0, MSG
+                PUTS
+                GETC
+                OUT
+                HALT
+        DOUBLE  ADD R1, R1, R1
+                ADD R1, R1, #1
+                RET
+        MSG     .STRINGZ "ok "
+                .END
+    "#;
+
+    /// Client side of a session with a server on another thread.
+    struct Client {
+        requests: Sender<Value>,
+        messages: Receiver<Value>,
+        seq: i64,
+        /// Events received while waiting for something else.
+        events: Vec<Value>,
+        path: PathBuf,
+    }
+
+    /// Collects written messages, decoding each on flush.
+    struct Output {
+        buffer: Vec<u8>,
+        messages: Sender<Value>,
+    }
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.buffer.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            let bytes = std::mem::take(&mut self.buffer);
+            let mut reader = bytes.as_slice();
+            while let Some(message) = read_message(&mut reader)? {
+                let _ = self.messages.send(message);
+            }
+            Ok(())
+        }
+    }
+
+    impl Client {
+        fn start() -> Self {
+            let dir = temp_dir().join(Uuid::new_v4().to_string());
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("program.asm");
+            fs::write(&path, PROGRAM).unwrap();
+
+            let (requests, receiver) = mpsc::channel();
+            let (sender, messages) = mpsc::channel();
+            thread::spawn(move || {
+                let output = Output {
+                    buffer: Vec::new(),
+                    messages: sender,
+                };
+                DapServer::new(output).run(receiver).unwrap();
+            });
+
+            Self {
+                requests,
+                messages,
+                seq: 0,
+                events: Vec::new(),
+                path,
+            }
+        }
+
+        /// Sends a request and returns its response.
+        fn request(&mut self, command: &str, arguments: Value) -> Value {
+            self.seq += 1;
+            let request = json!({
+                "seq": self.seq,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            });
+            self.requests.send(request).unwrap();
+            loop {
+                let message = self.next();
+                if message["type"] == "response" {
+                    assert_eq!(message["request_seq"], self.seq);
+                    return message;
+                }
+                self.events.push(message);
+            }
+        }
+
+        /// Waits for the next `event`, returning its body.
+        fn event(&mut self, event: &str) -> Value {
+            if let Some(idx) = self.events.iter().position(|e| e["event"] == event) {
+                return self.events.remove(idx)["body"].take();
+            }
+            loop {
+                let message = self.next();
+                if message["event"] == event {
+                    return message["body"].clone();
+                }
+                self.events.push(message);
+            }
+        }
+
+        fn next(&mut self) -> Value {
+            self.messages
+                .recv_timeout(Duration::from_secs(10))
+                .expect("the server stopped responding")
+        }
+
+        /// Launches [`PROGRAM`] and sets breakpoints on `lines`.
+        fn launch(&mut self, stop_on_entry: bool, lines: &[u64]) -> Value {
+            let response = self.request("initialize", json!({ "adapterID": "lc3" }));
+            assert_eq!(response["body"]["supportsReadMemoryRequest"], true);
+
+            let path = self.path.clone();
+            let response = self.request(
+                "launch",
+                json!({ "program": path, "stopOnEntry": stop_on_entry }),
+            );
+            assert_eq!(response["success"], true, "{response}");
+            self.event("initialized");
+
+            let breakpoints: Vec<_> = lines.iter().map(|line| json!({ "line": line })).collect();
+            let response = self.request(
+                "setBreakpoints",
+                json!({ "source": { "path": path }, "breakpoints": breakpoints }),
+            );
+            self.request("configurationDone", json!({}));
+            response["body"]["breakpoints"].clone()
+        }
+
+        fn registers(&mut self) -> BTreeMap<String, String> {
+            let response = self.request("variables", json!({ "variablesReference": 1 }));
+            response["body"]["variables"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|var| {
+                    (
+                        var["name"].as_str().unwrap().to_string(),
+                        var["value"].as_str().unwrap().to_string(),
+                    )
+                })
+                .collect()
+        }
+
+        fn top_frame(&mut self) -> Value {
+            let response = self.request("stackTrace", json!({ "threadId": 1 }));
+            response["body"]["stackFrames"][0].clone()
+        }
+    }
+
+    #[test]
+    fn line_map() {
+        let lines = assemble(PROGRAM).unwrap().lines;
+        // Blank and label-only lines move to the next word
+        assert_eq!(addr_of_line(&lines, 1), Some((0x3000, 3)));
+        assert_eq!(addr_of_line(&lines, 11), Some((0x3008, 11)));
+        assert_eq!(addr_of_line(&lines, 15), None);
+    }
+
+    #[test]
+    fn breakpoints_and_stepping() {
+        let mut client = Client::start();
+        let breakpoints = client.launch(false, &[11, 15]);
+        assert_eq!(breakpoints[0]["verified"], true);
+        assert_eq!(breakpoints[0]["line"], 11);
+        assert_eq!(breakpoints[1]["verified"], false);
+
+        // In DOUBLE, called from START
+        let stopped = client.event("stopped");
+        assert_eq!(stopped["reason"], "breakpoint");
+        let response = client.request("stackTrace", json!({ "threadId": 1 }));
+        let frames = &response["body"]["stackFrames"];
+        assert_eq!(frames[0]["name"], "DOUBLE");
+        assert_eq!(frames[0]["line"], 11);
+        assert_eq!(frames[1]["name"], "START");
+        assert_eq!(frames[1]["line"], 4);
+
+        // Out of DOUBLE, then over the second call
+        client.request("stepOut", json!({ "threadId": 1 }));
+        assert_eq!(client.event("stopped")["reason"], "step");
+        assert_eq!(client.top_frame()["line"], 5);
+        client.request(
+            "setBreakpoints",
+            json!({ "source": { "path": client.path.clone() }, "breakpoints": [] }),
+        );
+        client.request("next", json!({ "threadId": 1 }));
+        client.event("stopped");
+        assert_eq!(client.top_frame()["line"], 6);
+        assert_eq!(client.registers()["R1"], "x0003 (#3)");
+
+        // Into the PUTS, serviced natively
+        client.request("stepIn", json!({ "threadId": 1 }));
+        client.event("stopped");
+        client.request("stepIn", json!({ "threadId": 1 }));
+        client.event("stopped");
+        assert_eq!(client.event("output")["output"], "ok ");
+        assert_eq!(client.top_frame()["line"], 8);
+    }
+
+    #[test]
+    fn console_input() {
+        let mut client = Client::start();
+        client.launch(false, &[]);
+        assert_eq!(client.event("output")["output"], "ok ");
+        assert_eq!(client.event("output")["category"], "console");
+
+        client.request("evaluate", json!({ "expression": "y", "context": "repl" }));
+        assert_eq!(client.event("output")["output"], "y");
+        client.event("exited");
+        client.event("terminated");
+
+        let response = client.request("disconnect", json!({}));
+        assert_eq!(response["success"], true);
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let mut client = Client::start();
+        client.launch(true, &[]);
+        assert_eq!(client.event("stopped")["reason"], "entry");
+
+        let registers = client.registers();
+        assert_eq!(registers["PC"], "x3000 (#12288)");
+        assert_eq!(registers["PSR"], "x0002 (#2)");
+
+        let response = client.request(
+            "setVariable",
+            json!({ "variablesReference": 1, "name": "R2", "value": "x-1" }),
+        );
+        assert_eq!(response["success"], false);
+        let response = client.request(
+            "setVariable",
+            json!({ "variablesReference": 1, "name": "R2", "value": "#-2" }),
+        );
+        assert_eq!(response["body"]["value"], "xFFFE (#-2)");
+
+        let psr = client.request("variables", json!({ "variablesReference": 2 }));
+        assert_eq!(psr["body"]["variables"][0]["value"], "z");
+        assert_eq!(psr["body"]["variables"][1]["value"], "supervisor");
+
+        // Two words from x3000, then a write and read back
+        let response = client.request(
+            "readMemory",
+            json!({ "memoryReference": "0x3000", "offset": 0, "count": 4 }),
+        );
+        let data = STANDARD
+            .decode(response["body"]["data"].as_str().unwrap())
+            .unwrap();
+        assert_eq!(data, [0x52, 0x60, 0x48, 0x06]);
+
+        let data = STANDARD.encode([0x12, 0x34]);
+        client.request(
+            "writeMemory",
+            json!({ "memoryReference": "0x4000", "offset": 2, "data": data }),
+        );
+        let response = client.request(
+            "evaluate",
+            json!({ "expression": "x4001", "context": "watch" }),
+        );
+        assert_eq!(response["body"]["result"], "x1234 (#4660)");
+
+        let response = client.request(
+            "disassemble",
+            json!({ "memoryReference": "0x3000", "instructionCount": 2 }),
+        );
+        let instructions = &response["body"]["instructions"];
+        assert_eq!(instructions[0]["symbol"], "START");
+        assert_eq!(instructions[1]["instruction"], "JSR DOUBLE");
+        assert_eq!(instructions[1]["line"], 4);
+    }
+
+    #[test]
+    fn pause_and_errors() {
+        let mut client = Client::start();
+        let response = client.request("stackTrace", json!({ "threadId": 1 }));
+        assert_eq!(response["success"], false);
+        assert_eq!(response["message"], "No program has been launched");
+
+        let response = client.request("launch", json!({ "program": "missing.asm" }));
+        assert_eq!(response["success"], false);
+
+        client.launch(false, &[]);
+        client.event("output");
+        client.request("pause", json!({ "threadId": 1 }));
+        assert_eq!(client.event("stopped")["reason"], "pause");
+        assert_eq!(client.top_frame()["line"], 8);
+
+        let response = client.request("bogus", json!({}));
+        assert_eq!(response["message"], "`bogus` is not supported");
+    }
+}
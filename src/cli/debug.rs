@@ -0,0 +1,731 @@
+//! Interactive debugger, following the PennSim console commands.
+
+use std::{
+    collections::BTreeSet,
+    io::{self, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use lc3sim_project::{
+    assembler::SymbolTable,
+    debugger::{Breakpoint, BreakpointId, Debugger, Flow, StopReason},
+    defs::{LC3MemAddr, LC3Word, RegAddr},
+    disassembler::disassemble,
+    executors::{reverse::Reversible, LC3},
+    harnesses::sync::SyncHarness,
+};
+
+use crate::{format_state, parse_addr};
+
+/// Set by the Ctrl-C handler to stop a running program.
+pub static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Number of words shown by `list`.
+const LIST_LEN: u16 = 10;
+
+const HELP: &str = "\
+break LOC | break set LOC   stop when PC reaches LOC
+tbreak LOC                  stop at LOC once
+break clear LOC | delete LOC
+break clearall | delete     remove every breakpoint
+break list                  show breakpoints
+step [N]                    execute N instructions
+next [N]                    step over JSR, JSRR and TRAP
+finish                      run until the current subroutine returns
+continue                    run until a breakpoint or HALT
+back [N]                    undo the last N instructions
+rcontinue                   run backwards until a breakpoint
+print [R0-R7|PC|PSR|LOC]    show registers, or one register or word
+x/N LOC                     list N words from LOC
+list [LOC]                  list the words around LOC
+set reg R0-R7|PC|PSR VALUE  also `set R3 VALUE`
+set mem LOC VALUE           also `set LOC VALUE`
+history                     show previous commands, rerun with !N or !!
+quit
+
+LOC is an address (x3000, #12288) or a label. An empty line repeats the
+last command, and Ctrl-C stops a running program.";
+
+/// Register or memory word named in a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Reg(RegAddr),
+    Pc,
+    Psr,
+    Mem(LC3MemAddr),
+}
+
+impl Target {
+    pub fn get<P: LC3>(self, lc3: &P) -> LC3Word {
+        match self {
+            Self::Reg(reg) => lc3.reg(reg),
+            Self::Pc => lc3.pc(),
+            Self::Psr => lc3.processor_status_reg(),
+            Self::Mem(addr) => lc3.mem(addr),
+        }
+    }
+
+    pub fn set<P: LC3>(self, lc3: &mut P, value: LC3Word) {
+        match self {
+            Self::Reg(reg) => lc3.set_reg(reg, value),
+            Self::Pc => lc3.set_pc(value),
+            Self::Psr => lc3.set_processor_status_reg(value),
+            Self::Mem(addr) => lc3.set_mem(addr, value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Command {
+    Break {
+        addr: LC3MemAddr,
+        temporary: bool,
+    },
+    Clear(LC3MemAddr),
+    ClearAll,
+    ListBreaks,
+    Step(u64),
+    Next(u64),
+    Finish,
+    Continue,
+    Back(u64),
+    ReverseContinue,
+    Print(Option<Target>),
+    Examine {
+        addr: Option<LC3MemAddr>,
+        count: u16,
+    },
+    List(Option<LC3MemAddr>),
+    Set(Target, LC3Word),
+    History,
+    Help,
+    Quit,
+}
+
+/// Why a run handed control back to the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stop {
+    /// The command finished normally.
+    Done,
+    Breakpoint(LC3MemAddr),
+    /// Halted, or failed.
+    Debugger(StopReason),
+    Interrupted,
+    /// Nothing earlier is remembered to go back to.
+    StartOfHistory,
+}
+
+/// How far a resumed program should run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Run {
+    Continue,
+    In,
+    /// Over calls, `depth` calls deep.
+    Over {
+        depth: u64,
+    },
+    /// Out of the current call, `depth` calls deeper.
+    Out {
+        depth: u64,
+    },
+}
+
+impl Run {
+    /// Whether the run is done after an instruction with `flow`.
+    pub fn done(&mut self, flow: Flow) -> bool {
+        match (self, flow) {
+            (Self::Continue, _) => false,
+            (Self::In, _) => true,
+            (Self::Over { depth } | Self::Out { depth }, Flow::Call) => {
+                *depth += 1;
+                false
+            }
+            (Self::Over { depth }, Flow::Return) if *depth > 0 => {
+                *depth -= 1;
+                *depth == 0
+            }
+            (Self::Over { depth }, _) => *depth == 0,
+            (Self::Out { depth: 0 }, Flow::Return) => true,
+            (Self::Out { depth }, Flow::Return) => {
+                *depth -= 1;
+                false
+            }
+            (Self::Out { .. }, Flow::Other) => false,
+        }
+    }
+}
+
+/// Command loop over a processor and its I/O harness.
+pub struct Repl<P, H> {
+    debugger: Debugger<Reversible<P>>,
+    harness: H,
+    symbols: SymbolTable,
+    history: Vec<String>,
+}
+
+impl<P: LC3, H: SyncHarness> Repl<P, H> {
+    pub fn new(lc3: P, harness: H, symbols: SymbolTable) -> Self {
+        Self {
+            debugger: Debugger::new(Reversible::new(lc3)),
+            harness,
+            symbols,
+            history: Vec::new(),
+        }
+    }
+
+    /// Reads and executes `commands` until `quit` or the end of input.
+    pub fn run<I, W>(&mut self, mut commands: I, mut out: W) -> io::Result<()>
+    where
+        I: Iterator<Item = io::Result<String>>,
+        W: Write,
+    {
+        writeln!(out, "{}", self.current_line())?;
+
+        loop {
+            write!(out, "> ")?;
+            out.flush()?;
+
+            let Some(line) = commands.next().transpose()? else {
+                return Ok(());
+            };
+            let line = match self.recall(line.trim()) {
+                Ok(Some(line)) => line,
+                Ok(None) => continue,
+                Err(msg) => {
+                    writeln!(out, "{msg}")?;
+                    continue;
+                }
+            };
+
+            self.history.push(line.clone());
+            match parse_command(&line, &self.symbols) {
+                Ok(Command::Quit) => return Ok(()),
+                Ok(command) => self.execute(command, &mut out)?,
+                Err(msg) => writeln!(out, "{msg}")?,
+            }
+        }
+    }
+
+    /// Resolves history references in `line`.
+    ///
+    /// Returns `None` for a blank line with no previous command.
+    fn recall(&self, line: &str) -> Result<Option<String>, String> {
+        let entry = match line {
+            "" => return Ok(self.history.last().cloned()),
+            "!!" => self.history.last(),
+            _ => match line.strip_prefix('!') {
+                Some(idx) => idx
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|idx| self.history.get(idx.checked_sub(1)?)),
+                None => return Ok(Some(line.to_string())),
+            },
+        };
+
+        entry
+            .cloned()
+            .map(Some)
+            .ok_or_else(|| format!("No command {line} in history"))
+    }
+
+    fn execute<W: Write>(&mut self, command: Command, out: &mut W) -> io::Result<()> {
+        let stop = match command {
+            Command::Break { addr, temporary } => {
+                // Setting a breakpoint again replaces it
+                self.clear_breakpoints(|breakpoint| breakpoint.addr() == addr);
+                let (breakpoint, kind) = if temporary {
+                    (Breakpoint::new(addr).temporary(), "Temporary breakpoint")
+                } else {
+                    (Breakpoint::new(addr), "Breakpoint")
+                };
+                self.debugger.add_breakpoint(breakpoint);
+                return writeln!(out, "{kind} set at {}", self.describe(addr));
+            }
+            Command::Clear(addr) => {
+                return match self.clear_breakpoints(|breakpoint| breakpoint.addr() == addr) {
+                    0 => writeln!(out, "No breakpoint at {}", self.describe(addr)),
+                    _ => writeln!(out, "Breakpoint cleared at {}", self.describe(addr)),
+                };
+            }
+            Command::ClearAll => {
+                self.clear_breakpoints(|_| true);
+                return writeln!(out, "All breakpoints cleared");
+            }
+            Command::ListBreaks => {
+                let mut breakpoints: Vec<&Breakpoint> = self
+                    .debugger
+                    .breakpoints()
+                    .map(|(_, breakpoint)| breakpoint)
+                    .collect();
+                breakpoints.sort_by_key(|breakpoint| breakpoint.addr());
+                if breakpoints.is_empty() {
+                    writeln!(out, "No breakpoints")?;
+                }
+                for breakpoint in breakpoints {
+                    let suffix = if breakpoint.is_temporary() {
+                        " (temporary)"
+                    } else {
+                        ""
+                    };
+                    writeln!(out, "{}{suffix}", self.describe(breakpoint.addr()))?;
+                }
+                return Ok(());
+            }
+            Command::Step(count) => self.repeat(count, |repl| repl.advance(Run::In)),
+            Command::Next(count) => self.repeat(count, |repl| repl.advance(Run::Over { depth: 0 })),
+            Command::Finish => self.advance(Run::Out { depth: 0 }),
+            Command::Continue => self.advance(Run::Continue),
+            Command::Back(count) => {
+                let count = usize::try_from(count).unwrap_or(usize::MAX);
+                if self.debugger.processor_mut().step_back(count) < count {
+                    Stop::StartOfHistory
+                } else {
+                    Stop::Done
+                }
+            }
+            Command::ReverseContinue => {
+                let breakpoints = self.breakpoint_addrs();
+                match self
+                    .debugger
+                    .processor_mut()
+                    .reverse_continue(|pc| breakpoints.contains(&pc))
+                {
+                    Some(addr) => Stop::Breakpoint(addr),
+                    None => Stop::StartOfHistory,
+                }
+            }
+            Command::Print(target) => {
+                return match target {
+                    None => writeln!(out, "{}", format_state(self.lc3())),
+                    Some(target) => self.show(target, out),
+                };
+            }
+            Command::Examine { addr, count } => {
+                let addr = addr.unwrap_or_else(|| self.lc3().pc());
+                return self.listing(addr, count, out);
+            }
+            Command::List(addr) => {
+                let addr = addr.unwrap_or_else(|| self.lc3().pc().saturating_sub(2));
+                return self.listing(addr, LIST_LEN, out);
+            }
+            Command::Set(target, value) => {
+                target.set(self.debugger.processor_mut(), value);
+                return self.show(target, out);
+            }
+            Command::History => {
+                for (idx, line) in self.history.iter().enumerate() {
+                    writeln!(out, "{:>4}  {line}", idx + 1)?;
+                }
+                return Ok(());
+            }
+            Command::Help => return writeln!(out, "{HELP}"),
+            Command::Quit => return Ok(()),
+        };
+
+        match stop {
+            Stop::Done => (),
+            Stop::Breakpoint(addr) => writeln!(out, "Breakpoint at {}", self.describe(addr))?,
+            Stop::Debugger(reason) => writeln!(out, "{reason}")?,
+            Stop::Interrupted => writeln!(out, "Interrupted")?,
+            Stop::StartOfHistory => writeln!(out, "No earlier history")?,
+        }
+        writeln!(out, "{}", self.current_line())
+    }
+
+    /// Runs `command` up to `count` times, stopping early on anything
+    /// unusual.
+    fn repeat(&mut self, count: u64, mut command: impl FnMut(&mut Self) -> Stop) -> Stop {
+        for _ in 0..count {
+            let stop = command(self);
+            if stop != Stop::Done {
+                return stop;
+            }
+        }
+        Stop::Done
+    }
+
+    /// Steps until `run` is done, or until a breakpoint, HALT, failure or
+    /// Ctrl-C.
+    fn advance(&mut self, mut run: Run) -> Stop {
+        INTERRUPTED.store(false, Ordering::Relaxed);
+
+        loop {
+            let addr = self.lc3().pc();
+            let inst = self.lc3().cur_inst();
+            match self.debugger.step(&mut self.harness) {
+                StopReason::Stepped => {}
+                StopReason::Breakpoint { addr, .. } => return Stop::Breakpoint(addr),
+                reason => return Stop::Debugger(reason),
+            }
+
+            if run.done(Flow::of(inst, addr, self.lc3().pc())) {
+                return Stop::Done;
+            }
+            if INTERRUPTED.swap(false, Ordering::Relaxed) {
+                return Stop::Interrupted;
+            }
+        }
+    }
+
+    /// Removes every breakpoint `remove` picks, returning how many.
+    fn clear_breakpoints(&mut self, remove: impl Fn(&Breakpoint) -> bool) -> usize {
+        let ids: Vec<BreakpointId> = self
+            .debugger
+            .breakpoints()
+            .filter(|(_, breakpoint)| remove(breakpoint))
+            .map(|(id, _)| id)
+            .collect();
+        for id in &ids {
+            self.debugger.remove_breakpoint(*id);
+        }
+        ids.len()
+    }
+
+    fn breakpoint_addrs(&self) -> BTreeSet<LC3MemAddr> {
+        self.debugger
+            .breakpoints()
+            .map(|(_, breakpoint)| breakpoint.addr())
+            .collect()
+    }
+
+    fn lc3(&self) -> &Reversible<P> {
+        self.debugger.processor()
+    }
+
+    fn show<W: Write>(&self, target: Target, out: &mut W) -> io::Result<()> {
+        let name = match target {
+            Target::Reg(reg) => reg.to_string(),
+            Target::Pc => "PC".to_string(),
+            Target::Psr => "PSR".to_string(),
+            Target::Mem(addr) => self.describe(addr),
+        };
+        writeln!(out, "{name}: {}", format_value(target.get(self.lc3())))
+    }
+
+    /// Disassembly of `count` words from `addr`, marking the PC and
+    /// breakpoints.
+    fn listing<W: Write>(&self, addr: LC3MemAddr, count: u16, out: &mut W) -> io::Result<()> {
+        // Stop at the end of memory
+        let end = (u32::from(addr) + u32::from(count)).min(u32::from(LC3MemAddr::MAX) + 1);
+        let words = (u32::from(addr)..end).map(|addr| self.lc3().mem(addr as LC3MemAddr));
+
+        let breakpoints = self.breakpoint_addrs();
+        for line in disassemble(addr, words, Some(&self.symbols)) {
+            let marker = match (
+                line.addr == self.lc3().pc(),
+                breakpoints.contains(&line.addr),
+            ) {
+                (true, _) => "=>",
+                (false, true) => "B ",
+                (false, false) => "  ",
+            };
+            writeln!(out, "{marker} {line}")?;
+        }
+        Ok(())
+    }
+
+    /// The instruction at the PC.
+    fn current_line(&self) -> String {
+        let pc = self.lc3().pc();
+        let line = disassemble(pc, [self.lc3().mem(pc)], Some(&self.symbols));
+        format!("=> {}", line[0])
+    }
+
+    /// `addr`, with its label if it has one.
+    fn describe(&self, addr: LC3MemAddr) -> String {
+        match self.symbols.label(addr) {
+            Some(label) => format!("x{addr:04X} ({label})"),
+            None => format!("x{addr:04X}"),
+        }
+    }
+}
+
+pub fn format_value(value: LC3Word) -> String {
+    format!("x{value:04X} (#{})", value as i16)
+}
+
+fn parse_command(line: &str, symbols: &SymbolTable) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let Some((name, args)) = words.split_first() else {
+        return Err("Expected a command".to_string());
+    };
+
+    let loc = |text: &str| parse_loc(text, symbols);
+    let count = |args: &[&str]| match args {
+        [] => Ok(1),
+        [count] => count
+            .parse::<u64>()
+            .map_err(|_| format!("`{count}` is not a count")),
+        _ => Err(format!("`{name}` takes at most one count")),
+    };
+
+    let command = match (name.to_lowercase().as_str(), args) {
+        ("b" | "break", [] | ["list"]) => Command::ListBreaks,
+        ("b" | "break", ["clearall"]) | ("d" | "delete", []) => Command::ClearAll,
+        ("b" | "break", ["set", addr] | [addr]) => Command::Break {
+            addr: loc(addr)?,
+            temporary: false,
+        },
+        ("tbreak", [addr]) => Command::Break {
+            addr: loc(addr)?,
+            temporary: true,
+        },
+        ("b" | "break", ["clear", addr]) | ("d" | "delete", [addr]) => Command::Clear(loc(addr)?),
+        ("s" | "step", args) => Command::Step(count(args)?),
+        ("n" | "next", args) => Command::Next(count(args)?),
+        ("fin" | "finish", []) => Command::Finish,
+        ("c" | "continue", []) => Command::Continue,
+        ("back", args) => Command::Back(count(args)?),
+        ("rc" | "rcontinue", []) => Command::ReverseContinue,
+        ("p" | "print", []) => Command::Print(None),
+        ("p" | "print", [target]) => Command::Print(Some(parse_target(target, symbols)?)),
+        ("l" | "list", []) => Command::List(None),
+        ("l" | "list", [addr]) => Command::List(Some(loc(addr)?)),
+        ("set", ["reg", reg, value]) => match parse_target(reg, symbols)? {
+            Target::Mem(_) => return Err(format!("`{reg}` is not a register")),
+            target => Command::Set(target, parse_value(value, symbols)?),
+        },
+        ("set", ["mem", addr, value]) => {
+            Command::Set(Target::Mem(loc(addr)?), parse_value(value, symbols)?)
+        }
+        ("set", [target, value]) => {
+            Command::Set(parse_target(target, symbols)?, parse_value(value, symbols)?)
+        }
+        ("history", []) => Command::History,
+        ("h" | "help" | "?", []) => Command::Help,
+        ("q" | "quit" | "exit", []) => Command::Quit,
+        (examine, args) if examine == "x" || examine.starts_with("x/") => {
+            let count = match examine.strip_prefix("x/") {
+                Some(count) => count
+                    .parse()
+                    .map_err(|_| format!("`{count}` is not a word count"))?,
+                None => 1,
+            };
+            let addr = match args {
+                [] => None,
+                [addr] => Some(loc(addr)?),
+                _ => return Err("`x` takes at most one location".to_string()),
+            };
+            Command::Examine { addr, count }
+        }
+        _ => return Err(format!("Unknown command `{line}`, try `help`")),
+    };
+
+    Ok(command)
+}
+
+/// Parses an address or label.
+fn parse_loc(text: &str, symbols: &SymbolTable) -> Result<LC3MemAddr, String> {
+    parse_addr(text).or_else(|err| {
+        symbols
+            .get(text)
+            .or_else(|| symbols.get(&text.to_uppercase()))
+            .ok_or(err)
+    })
+}
+
+pub fn parse_target(text: &str, symbols: &SymbolTable) -> Result<Target, String> {
+    let upper = text.to_uppercase();
+    match upper.as_str() {
+        "PC" => Ok(Target::Pc),
+        "PSR" => Ok(Target::Psr),
+        _ => match upper.strip_prefix('R').map(str::parse::<u8>) {
+            Some(Ok(reg)) if reg < 8 => Ok(Target::Reg(RegAddr::panic_from_u8(reg))),
+            _ => parse_loc(text, symbols).map(Target::Mem),
+        },
+    }
+}
+
+/// Parses a word, in any address format, as a negative number, or as a
+/// label's address.
+pub fn parse_value(text: &str, symbols: &SymbolTable) -> Result<LC3Word, String> {
+    let negative = text.strip_prefix("#-").or_else(|| text.strip_prefix('-'));
+    match negative {
+        Some(magnitude) => magnitude
+            .parse::<i16>()
+            .ok()
+            .and_then(i16::checked_neg)
+            .map(|value| value as LC3Word)
+            .ok_or_else(|| format!("`{text}` does not fit in a word")),
+        None => parse_loc(text, symbols),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use lc3sim_project::{
+        assembler::assemble, executors::core::CoreLC3, harnesses::console::ConsoleIO,
+    };
+
+    const PROGRAM: &str = r#"
+                .ORIG x3000
+        START   AND R1, R1, #0
+                JSR DOUBLE
+                JSR DOUBLE
+                LEA R0, MSG
+                PUTS
+                HALT
+        DOUBLE  ADD R1, R1, R1
+                ADD R1, R1, #1
+                RET
+        MSG     .STRINGZ "ok"
+                .END
+    "#;
+
+    /// Runs `commands` on [`PROGRAM`], returning the REPL and console output.
+    fn session(commands: &str) -> (String, String) {
+        let assembled = assemble(PROGRAM).unwrap();
+        let mut lc3 = CoreLC3::new();
+        lc3.set_native_os(true);
+        assembled.populate(&mut lc3);
+        lc3.set_pc(0x3000);
+
+        let console = ConsoleIO::new(io::empty(), Vec::new());
+        let mut repl = Repl::new(lc3, console, assembled.symbols);
+
+        let mut out = Vec::new();
+        let commands = commands.lines().map(|line| Ok(line.to_string()));
+        repl.run(commands, &mut out).unwrap();
+
+        (
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(repl.harness.into_inner().1).unwrap(),
+        )
+    }
+
+    #[test]
+    fn parse_commands() {
+        let symbols: SymbolTable = [("LOOP", 0x3004)].into_iter().collect();
+        let parse = |line| parse_command(line, &symbols);
+
+        assert_eq!(
+            parse("break set loop"),
+            Ok(Command::Break {
+                addr: 0x3004,
+                temporary: false
+            })
+        );
+        assert_eq!(parse("delete x3004"), Ok(Command::Clear(0x3004)));
+        assert_eq!(parse("s 5"), Ok(Command::Step(5)));
+        assert_eq!(
+            parse("print r3"),
+            Ok(Command::Print(Some(Target::Reg(RegAddr::Three))))
+        );
+        assert_eq!(
+            parse("x/16 x3000"),
+            Ok(Command::Examine {
+                addr: Some(0x3000),
+                count: 16
+            })
+        );
+        assert_eq!(
+            parse("set mem LOOP #-1"),
+            Ok(Command::Set(Target::Mem(0x3004), 0xFFFF))
+        );
+        assert_eq!(
+            parse("set reg PC LOOP"),
+            Ok(Command::Set(Target::Pc, 0x3004))
+        );
+        assert_eq!(
+            parse("set R7 x10"),
+            Ok(Command::Set(Target::Reg(RegAddr::Seven), 0x10))
+        );
+
+        assert!(parse("set reg x3000 1").is_err());
+        assert!(parse("break NOWHERE").is_err());
+        assert!(parse("step many").is_err());
+        assert!(parse("frobnicate").is_err());
+    }
+
+    #[test]
+    fn breakpoints_and_stepping() {
+        let (out, console) = session(
+            "tbreak DOUBLE\n\
+             continue\n\
+             finish\n\
+             print R1\n\
+             next\n\
+             print R1\n\
+             continue\n",
+        );
+
+        assert!(
+            out.contains("Temporary breakpoint set at x3006 (DOUBLE)"),
+            "{out}"
+        );
+        assert!(out.contains("Breakpoint at x3006 (DOUBLE)"), "{out}");
+        // Returned from the first call, then stepped over the second
+        assert!(out.contains("R1: x0001 (#1)\n"), "{out}");
+        assert!(out.contains("R1: x0003 (#3)\n"), "{out}");
+        // The temporary breakpoint is gone, so the program runs to HALT
+        assert!(out.contains("Halted"), "{out}");
+        assert_eq!(console, "ok");
+    }
+
+    #[test]
+    fn inspect_and_modify() {
+        let (out, _) = session(
+            "set mem x3001 xF025\n\
+             set R1 #-2\n\
+             x/2 START\n\
+             step 2\n\
+             p r1\n",
+        );
+
+        assert!(
+            out.contains("=> x3000  x5260  START       AND R1, R1, #0"),
+            "{out}"
+        );
+        assert!(out.contains("   x3001  xF025              HALT"), "{out}");
+        assert!(out.contains("Halted"), "{out}");
+        assert!(out.contains("R1: x0000 (#0)"), "{out}");
+    }
+
+    #[test]
+    fn reverse() {
+        let (out, console) = session(
+            "break DOUBLE\n\
+             continue\n\
+             continue\n\
+             print R1\n\
+             rcontinue\n\
+             print R1\n\
+             back 2\n\
+             back 5\n\
+             continue\n\
+             continue\n\
+             continue\n",
+        );
+
+        // Back from the second call to the first
+        assert!(out.contains("R1: x0001 (#1)\n"), "{out}");
+        assert!(
+            out.contains("Breakpoint at x3006 (DOUBLE)\n=> x3006  x1241  DOUBLE      ADD R1, R1, R1\n> R1: x0000 (#0)\n"),
+            "{out}"
+        );
+        assert!(
+            out.contains("> No earlier history\n=> x3000  x5260  START       AND R1, R1, #0"),
+            "{out}"
+        );
+        // Running forwards again reaches both calls
+        assert_eq!(out.matches("Breakpoint at").count(), 5, "{out}");
+        assert_eq!(console, "ok");
+    }
+
+    #[test]
+    fn history() {
+        let (out, _) = session("step\n\nhistory\n!1\n!9\n");
+
+        assert!(
+            out.contains("   1  step\n   2  step\n   3  history\n"),
+            "{out}"
+        );
+        assert!(out.contains("No command !9 in history"), "{out}");
+        assert!(
+            out.contains("=> x3006  x1241  DOUBLE      ADD R1, R1, R1"),
+            "{out}"
+        );
+    }
+}
@@ -0,0 +1,644 @@
+//! Command line front end for the simulator.
+
+mod dap;
+mod debug;
+mod tui;
+
+use std::{
+    fs::{self, File},
+    io::{self, stderr, stdin, stdout, BufReader, BufWriter, Write},
+    net::{Ipv4Addr, TcpListener},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::atomic::Ordering,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use lc3sim_project::{
+    assembler::{assemble_named, AsmErrors, SymbolTable},
+    defs::{LC3MemAddr, LC3Word, RegAddr},
+    disassembler::{disassemble, disassemble_source, DisasmLine},
+    executors::{
+        core::CoreLC3,
+        populate_from_bin,
+        profile::Profiler,
+        state::{load_state, save_state},
+        LC3,
+    },
+    gdb::GdbStub,
+    harnesses::{
+        console::ConsoleIO,
+        sync::{lim_step_continue, step_continue, SyncHarness},
+        ExecutionFailure,
+    },
+    os::load_lc3os,
+    trace::{
+        binary::{BinaryReader, BinaryWriter},
+        diff,
+        text::TextWriter,
+        Divergence, StepRecord, Tracer,
+    },
+};
+
+use dap::DapServer;
+use debug::{Repl, INTERRUPTED};
+use tui::App;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "LC-3 simulator, assembler and disassembler")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run programs until HALT, using the terminal as the console.
+    Run {
+        #[command(flatten)]
+        machine: MachineArgs,
+        /// Stop after this many instructions.
+        #[arg(long)]
+        limit: Option<u64>,
+        /// Memory to list after the run, e.g. `x3000:x300F`. Repeatable.
+        #[arg(long = "show", value_name = "RANGE", value_parser = parse_range)]
+        show: Vec<MemRange>,
+        /// Record every instruction executed to this file.
+        #[arg(long, value_name = "FILE")]
+        trace: Option<PathBuf>,
+        /// Format of the trace file.
+        #[arg(long, value_enum, default_value_t = TraceStyle::Text, requires = "trace")]
+        trace_format: TraceStyle,
+        /// Profile the run, writing the results to this file.
+        #[arg(long, value_name = "FILE")]
+        profile: Option<PathBuf>,
+        /// Format of the profile: a report, or folded stacks for flamegraphs.
+        #[arg(long, value_enum, default_value_t = ProfileStyle::Text, requires = "profile")]
+        profile_format: ProfileStyle,
+        /// Save the machine state to this file after the run, to resume
+        /// with `--state`.
+        #[arg(long, value_name = "FILE")]
+        save_state: Option<PathBuf>,
+    },
+    /// Assemble a source file into `.obj` and `.sym` files.
+    Asm {
+        file: PathBuf,
+        /// Object file to write. Defaults to FILE with an `.obj` extension.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Print an object file as assembly source.
+    Disasm {
+        file: PathBuf,
+        /// Symbol table for labels. Defaults to FILE with a `.sym` extension,
+        /// if it exists.
+        #[arg(long)]
+        sym: Option<PathBuf>,
+    },
+    /// Load programs and list memory.
+    Dump {
+        #[command(flatten)]
+        machine: MachineArgs,
+        /// Memory to list, e.g. `x3000:x300F`. Repeatable. Defaults to every
+        /// nonzero word.
+        #[arg(long = "range", value_name = "RANGE", value_parser = parse_range)]
+        ranges: Vec<MemRange>,
+    },
+    /// Step through programs one instruction at a time.
+    Debug {
+        #[command(flatten)]
+        machine: MachineArgs,
+    },
+    /// Debug programs in a full-screen terminal UI.
+    Tui {
+        #[command(flatten)]
+        machine: MachineArgs,
+    },
+    /// Serve programs to a debugger over the GDB remote protocol.
+    Gdb {
+        #[command(flatten)]
+        machine: MachineArgs,
+        /// Local TCP port to listen on.
+        #[arg(long, default_value_t = 1234)]
+        port: u16,
+        /// Talk to the debugger over stdin and stdout instead, as in
+        /// `target remote | lc3sim gdb --stdio prog.obj`.
+        ///
+        /// The program's output goes to stderr, and it has no keyboard.
+        #[arg(long, conflicts_with = "port")]
+        stdio: bool,
+    },
+    /// Serve a debugger in an editor over the Debug Adapter Protocol, on
+    /// stdin and stdout.
+    ///
+    /// The program to debug is named in the editor's launch request.
+    Dap,
+    /// Print a binary trace as text, or where it first differs from another.
+    Trace {
+        file: PathBuf,
+        /// Binary trace to compare against.
+        #[arg(long, value_name = "FILE")]
+        diff: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Args)]
+struct MachineArgs {
+    /// Object (`.obj`) or source (`.asm`) files to load, in order.
+    #[arg(required_unless_present = "state")]
+    files: Vec<PathBuf>,
+    /// Saved machine state to start from, before loading any programs.
+    ///
+    /// The PC comes from the state unless `--pc` is given.
+    #[arg(long, value_name = "FILE")]
+    state: Option<PathBuf>,
+    /// OS image to load before the programs.
+    ///
+    /// Exceptions vector to the OS's handlers. Without an OS, the standard
+    /// TRAPs are serviced natively and exceptions stop execution with an
+    /// error instead.
+    #[arg(long)]
+    os: Option<PathBuf>,
+    /// Load the bundled PennSim OS before the programs.
+    #[arg(long, conflicts_with = "os")]
+    lc3os: bool,
+    /// Initial PC. Defaults to the origin of the first program.
+    #[arg(long, value_parser = parse_addr)]
+    pc: Option<LC3MemAddr>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum TraceStyle {
+    Binary,
+    Text,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ProfileStyle {
+    Text,
+    Folded,
+}
+
+/// Inclusive range of addresses.
+#[derive(Debug, Clone, Copy)]
+struct MemRange {
+    start: LC3MemAddr,
+    end: LC3MemAddr,
+}
+
+/// Parses `x3000`, `0x3000`, `#12288` or `12288`.
+fn parse_addr(text: &str) -> Result<LC3MemAddr, String> {
+    let parsed = if let Some(hex) = text
+        .strip_prefix('x')
+        .or_else(|| text.strip_prefix('X'))
+        .or_else(|| text.strip_prefix("0x"))
+    {
+        LC3MemAddr::from_str_radix(hex, 16)
+    } else {
+        text.strip_prefix('#').unwrap_or(text).parse()
+    };
+    parsed.map_err(|_| format!("`{text}` is not an LC-3 address"))
+}
+
+/// Parses `START:END` or a single address.
+fn parse_range(text: &str) -> Result<MemRange, String> {
+    let (start, end) = match text.split_once(':') {
+        Some((start, end)) => (parse_addr(start)?, parse_addr(end)?),
+        None => (parse_addr(text)?, parse_addr(text)?),
+    };
+    if start > end {
+        return Err(format!("`{text}` ends before it starts"));
+    }
+    Ok(MemRange { start, end })
+}
+
+/// Processor with every requested file loaded.
+struct Machine {
+    lc3: CoreLC3,
+    symbols: SymbolTable,
+}
+
+impl Machine {
+    fn load(args: &MachineArgs) -> Result<Self> {
+        let mut machine = Self {
+            lc3: CoreLC3::new(),
+            symbols: SymbolTable::new(),
+        };
+
+        // Without an OS there are no exception handlers to vector to
+        let has_os = args.os.is_some() || args.lc3os;
+        machine.lc3.set_strict(!has_os);
+        machine.lc3.set_native_os(!has_os);
+        if let Some(os) = &args.os {
+            machine.load_file(os)?;
+        } else if args.lc3os {
+            load_lc3os(&mut machine.lc3);
+        }
+
+        if let Some(state) = &args.state {
+            let file =
+                File::open(state).with_context(|| format!("failed to open {}", state.display()))?;
+            load_state(&mut machine.lc3, BufReader::new(file))
+                .with_context(|| format!("failed to read {}", state.display()))?;
+        }
+
+        let mut entry = None;
+        for file in &args.files {
+            let origin = machine.load_file(file)?;
+            entry = entry.or(origin);
+        }
+
+        let entry = entry.filter(|_| args.state.is_none());
+        if let Some(pc) = args.pc.or(entry) {
+            machine.lc3.set_pc(pc);
+        }
+
+        Ok(machine)
+    }
+
+    /// Loads an `.asm` or `.obj` file, returning its first origin.
+    fn load_file(&mut self, path: &Path) -> Result<Option<LC3MemAddr>> {
+        if path.extension().is_some_and(|ext| ext == "asm") {
+            let source = fs::read_to_string(path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            let assembled = assemble_named(&path.display().to_string(), &source)?;
+
+            assembled.populate(&mut self.lc3);
+            for (label, addr) in assembled.symbols.iter() {
+                self.symbols.insert(label, addr);
+            }
+            Ok(assembled.blocks.first().map(|block| block.origin))
+        } else {
+            let bytes = read_obj_bytes(path)?;
+            let origin = LC3MemAddr::from_be_bytes([bytes[0], bytes[1]]);
+            populate_from_bin(&mut self.lc3, bytes.as_slice());
+
+            if let Some(symbols) = read_sibling_sym(path)? {
+                for (label, addr) in symbols.iter() {
+                    self.symbols.insert(label, addr);
+                }
+            }
+            Ok(Some(origin))
+        }
+    }
+}
+
+/// Reads an object file, checking that it holds at least an origin.
+fn read_obj_bytes(path: &Path) -> Result<Vec<u8>> {
+    let bytes = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    if bytes.len() < 2 || bytes.len() % 2 != 0 {
+        bail!("{} is not an LC-3 object file", path.display());
+    }
+    Ok(bytes)
+}
+
+/// Reads an object file's origin and words.
+fn read_obj(path: &Path) -> Result<(LC3MemAddr, Vec<LC3Word>)> {
+    let bytes = read_obj_bytes(path)?;
+    let mut words = bytes
+        .chunks_exact(2)
+        .map(|pair| LC3Word::from_be_bytes([pair[0], pair[1]]));
+    let origin = words.next().expect("Length was checked above");
+    Ok((origin, words.collect()))
+}
+
+fn read_sym(path: &Path) -> Result<SymbolTable> {
+    let file =
+        fs::File::open(path).with_context(|| format!("failed to read {}", path.display()))?;
+    SymbolTable::read_sym(io::BufReader::new(file))
+        .with_context(|| format!("failed to parse {}", path.display()))
+}
+
+/// Reads the `.sym` file next to `path`, if there is one.
+fn read_sibling_sym(path: &Path) -> Result<Option<SymbolTable>> {
+    let sym = path.with_extension("sym");
+    if sym.exists() {
+        read_sym(&sym).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Registers, PC and PSR, as printed after a run.
+fn format_state<P: LC3>(processor: &P) -> String {
+    let regs: Vec<String> = (0..8)
+        .map(|reg| {
+            let reg = RegAddr::panic_from_u8(reg);
+            format!("{reg}  x{:04X}", processor.reg(reg))
+        })
+        .collect();
+
+    format!(
+        "PC  x{:04X}  PSR x{:04X}{}\n{}\n{}",
+        processor.pc(),
+        processor.processor_status_reg(),
+        if processor.is_halted() {
+            "  (halted)"
+        } else {
+            ""
+        },
+        regs[..4].join("    "),
+        regs[4..].join("    ")
+    )
+}
+
+fn listing<P: LC3>(processor: &P, range: MemRange, symbols: &SymbolTable) -> Vec<DisasmLine> {
+    let words = (range.start..=range.end).map(|addr| processor.mem(addr));
+    disassemble(range.start, words, Some(symbols))
+}
+
+fn run(
+    machine: MachineArgs,
+    limit: Option<u64>,
+    show: Vec<MemRange>,
+    trace: Option<(PathBuf, TraceStyle)>,
+    profile: Option<(PathBuf, ProfileStyle)>,
+    save: Option<PathBuf>,
+) -> Result<ExitCode> {
+    let Machine { mut lc3, symbols } = Machine::load(&machine)?;
+
+    let (result, lc3) = match profile {
+        None => (execute(&mut lc3, limit, trace)?, lc3),
+        Some((path, style)) => {
+            let mut profiler = Profiler::new(lc3);
+            let result = execute(&mut profiler, limit, trace)?;
+            let (lc3, profile) = profiler.into_inner();
+
+            let file = File::create(&path)
+                .with_context(|| format!("failed to create {}", path.display()))?;
+            let mut output = BufWriter::new(file);
+            match style {
+                ProfileStyle::Text => profile.write_text(&mut output, Some(&symbols)),
+                ProfileStyle::Folded => profile.write_folded(&mut output, Some(&symbols)),
+            }
+            .and_then(|_| output.flush())
+            .with_context(|| format!("failed to write {}", path.display()))?;
+            (result, lc3)
+        }
+    };
+
+    // The console owns stdout, so the report goes to stderr
+    eprintln!();
+    eprintln!("{}", format_state(&lc3));
+    for line in show
+        .into_iter()
+        .flat_map(|range| listing(&lc3, range, &symbols))
+    {
+        eprintln!("{line}");
+    }
+
+    if let Some(path) = save {
+        let file =
+            File::create(&path).with_context(|| format!("failed to create {}", path.display()))?;
+        let mut output = BufWriter::new(file);
+        save_state(&lc3, &mut output)
+            .and_then(|_| output.flush())
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+
+    match result {
+        Ok(true) => Ok(ExitCode::SUCCESS),
+        Ok(false) => {
+            eprintln!("Stopped after {} instructions", limit.unwrap_or_default());
+            Ok(ExitCode::from(2))
+        }
+        Err(e) => Err(anyhow!(e).context(format!("execution failed at x{:04X}", lc3.pc()))),
+    }
+}
+
+/// Runs `lc3` on the console, tracing to a file if asked.
+fn execute<P: LC3>(
+    lc3: &mut P,
+    limit: Option<u64>,
+    trace: Option<(PathBuf, TraceStyle)>,
+) -> Result<Result<bool, ExecutionFailure>> {
+    let Some((path, style)) = trace else {
+        return Ok(run_on(&mut ConsoleIO::stdio(), lc3, limit));
+    };
+
+    let file =
+        File::create(&path).with_context(|| format!("failed to create {}", path.display()))?;
+    let output = BufWriter::new(file);
+
+    let (result, mut output) = match style {
+        TraceStyle::Binary => {
+            let mut tracer = Tracer::new(ConsoleIO::stdio(), BinaryWriter::new(output)?);
+            let result = run_on(&mut tracer, lc3, limit);
+            (result, tracer.into_inner().1.into_inner())
+        }
+        TraceStyle::Text => {
+            let mut tracer = Tracer::new(ConsoleIO::stdio(), TextWriter::new(output));
+            let result = run_on(&mut tracer, lc3, limit);
+            (result, tracer.into_inner().1.into_inner())
+        }
+    };
+    if let Err(ExecutionFailure::Trace(kind)) = result {
+        return Err(io::Error::from(kind))
+            .with_context(|| format!("failed to write {}", path.display()));
+    }
+    output
+        .flush()
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(result)
+}
+
+/// Runs until HALT or `limit` instructions, returning false at the limit.
+fn run_on<H: SyncHarness, P: LC3>(
+    harness: &mut H,
+    lc3: &mut P,
+    limit: Option<u64>,
+) -> Result<bool, ExecutionFailure> {
+    match limit {
+        Some(limit) => lim_step_continue(harness, lc3, limit),
+        None => step_continue(harness, lc3).map(|_| true),
+    }
+}
+
+fn show_trace(file: PathBuf, diff_file: Option<PathBuf>) -> Result<ExitCode> {
+    let read = |path: &Path| -> Result<Vec<StepRecord>> {
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        BinaryReader::new(BufReader::new(file))
+            .and_then(|reader| reader.collect())
+            .with_context(|| format!("failed to read {}", path.display()))
+    };
+    let records = read(&file)?;
+
+    let Some(diff_file) = diff_file else {
+        for record in records {
+            println!("{record}");
+        }
+        return Ok(ExitCode::SUCCESS);
+    };
+
+    match diff(records, read(&diff_file)?) {
+        None => {
+            println!("Traces match");
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Divergence { step, left, right }) => {
+            println!("Traces differ after {step} matching steps");
+            let show = |record: Option<StepRecord>| {
+                record.map_or("(trace ended)".to_string(), |record| record.to_string())
+            };
+            println!("< {}", show(left));
+            println!("> {}", show(right));
+            Ok(ExitCode::from(1))
+        }
+    }
+}
+
+fn asm(file: PathBuf, output: Option<PathBuf>) -> Result<()> {
+    let source =
+        fs::read_to_string(&file).with_context(|| format!("failed to read {}", file.display()))?;
+    let assembled = assemble_named(&file.display().to_string(), &source)?;
+
+    let obj_path = output.unwrap_or_else(|| file.with_extension("obj"));
+    let sym_path = obj_path.with_extension("sym");
+
+    let obj: Vec<u8> = assembled
+        .blocks
+        .iter()
+        .flat_map(|block| block.obj())
+        .collect();
+    fs::write(&obj_path, obj).with_context(|| format!("failed to write {}", obj_path.display()))?;
+    fs::write(&sym_path, assembled.symbols.to_sym_string())
+        .with_context(|| format!("failed to write {}", sym_path.display()))?;
+
+    Ok(())
+}
+
+fn disasm(file: PathBuf, sym: Option<PathBuf>) -> Result<()> {
+    let (origin, words) = read_obj(&file)?;
+    let symbols = match sym {
+        Some(sym) => Some(read_sym(&sym)?),
+        None => read_sibling_sym(&file)?,
+    };
+
+    print!("{}", disassemble_source(origin, words, symbols.as_ref()));
+    Ok(())
+}
+
+fn dump(machine: MachineArgs, ranges: Vec<MemRange>) -> Result<()> {
+    let Machine { lc3, symbols } = Machine::load(&machine)?;
+
+    let lines = if ranges.is_empty() {
+        lc3.sparse_iter()
+            .flat_map(|loc| disassemble(loc.loc, [loc.value], Some(&symbols)))
+            .collect()
+    } else {
+        ranges
+            .into_iter()
+            .flat_map(|range| listing(&lc3, range, &symbols))
+            .collect::<Vec<_>>()
+    };
+    for line in lines {
+        println!("{line}");
+    }
+
+    Ok(())
+}
+
+fn debug(machine: MachineArgs) -> Result<ExitCode> {
+    let Machine { lc3, symbols } = Machine::load(&machine)?;
+
+    // Ctrl-C stops a running program instead of the debugger
+    ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::Relaxed))?;
+
+    // Shares stdin with the console, so only lock it for one line at a time
+    let commands = std::iter::from_fn(|| {
+        let mut line = String::new();
+        match stdin().read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) => Some(Ok(line)),
+            Err(e) => Some(Err(e)),
+        }
+    });
+
+    Repl::new(lc3, ConsoleIO::stdio(), symbols).run(commands, stdout())?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn tui(machine: MachineArgs) -> Result<ExitCode> {
+    let Machine { lc3, symbols } = Machine::load(&machine)?;
+
+    let mut terminal = ratatui::init();
+    let result = App::new(lc3, symbols).run(&mut terminal);
+    ratatui::restore();
+
+    result?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn gdb(machine: MachineArgs, port: u16, stdio: bool) -> Result<ExitCode> {
+    let Machine { lc3, .. } = Machine::load(&machine)?;
+
+    if stdio {
+        let mut stub = GdbStub::new(lc3, ConsoleIO::new(io::empty(), stderr()));
+        stub.serve(stdin(), stdout())?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+        .with_context(|| format!("failed to listen on port {port}"))?;
+    eprintln!("Waiting for GDB on {}", listener.local_addr()?);
+    let (stream, peer) = listener.accept()?;
+    eprintln!("GDB connected from {peer}");
+
+    let mut stub = GdbStub::new(lc3, ConsoleIO::stdio());
+    stub.serve(stream.try_clone()?, stream)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn dap() -> Result<ExitCode> {
+    DapServer::new(stdout()).serve(stdin())?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn main() -> ExitCode {
+    let result = match Cli::parse().command {
+        Command::Run {
+            machine,
+            limit,
+            show,
+            trace,
+            trace_format,
+            profile,
+            profile_format,
+            save_state,
+        } => run(
+            machine,
+            limit,
+            show,
+            trace.map(|path| (path, trace_format)),
+            profile.map(|path| (path, profile_format)),
+            save_state,
+        ),
+        Command::Asm { file, output } => asm(file, output).map(|_| ExitCode::SUCCESS),
+        Command::Disasm { file, sym } => disasm(file, sym).map(|_| ExitCode::SUCCESS),
+        Command::Dump { machine, ranges } => dump(machine, ranges).map(|_| ExitCode::SUCCESS),
+        Command::Debug { machine } => debug(machine),
+        Command::Tui { machine } => tui(machine),
+        Command::Gdb {
+            machine,
+            port,
+            stdio,
+        } => gdb(machine, port, stdio),
+        Command::Dap => dap(),
+        Command::Trace { file, diff } => show_trace(file, diff),
+    };
+
+    match result {
+        Ok(code) => code,
+        // Assembler diagnostics are already fully rendered
+        Err(e) => {
+            match e.downcast_ref::<AsmErrors>() {
+                Some(errors) => eprintln!("{errors}"),
+                None => eprintln!("error: {e:#}"),
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,568 @@
+//! Full-screen terminal front end.
+//!
+//! Shows the registers, the disassembly around the cursor, a memory view
+//! and the console output. The cursor follows the PC whenever the program
+//! stops. While a program runs, typed keys go to its keyboard.
+
+use std::{collections::VecDeque, io, time::Duration};
+
+use ratatui::{
+    backend::Backend,
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Paragraph, Wrap},
+    Frame, Terminal,
+};
+
+use lc3sim_project::{
+    assembler::SymbolTable,
+    debugger::{Breakpoint, Debugger, Flow, StopReason},
+    defs::{LC3MemAddr, LC3Word, RegAddr},
+    disassembler::disassemble,
+    executors::LC3,
+    harnesses::{console::ConsoleIO, ExecutionFailure},
+};
+
+use crate::debug::Run;
+
+/// Steps taken between redraws while running.
+const CHUNK: u32 = 10_000;
+
+/// Words in each row of the memory view.
+const ROW_WORDS: u16 = 8;
+
+/// How long to wait for a key while stopped, before redrawing anyway.
+const IDLE_POLL: Duration = Duration::from_millis(250);
+
+const KEYS: &str =
+    "s step  n next  f finish  c continue  r run to cursor  b breakpoint  m memory  q quit";
+
+/// Debugger state behind the screen.
+pub struct App<P> {
+    debugger: Debugger<P>,
+    console: ConsoleIO<VecDeque<u8>, Vec<u8>>,
+    symbols: SymbolTable,
+    /// Selected address in the disassembly.
+    cursor: LC3MemAddr,
+    /// First address in the memory view.
+    mem_top: LC3MemAddr,
+    /// Everything the program has displayed.
+    output: String,
+    run: Option<Run>,
+    /// Stop when the PC reaches this, for run to cursor.
+    target: Option<LC3MemAddr>,
+    /// Whether the run is paused until a key is typed.
+    waiting: bool,
+    status: String,
+    quit: bool,
+}
+
+impl<P: LC3> App<P> {
+    pub fn new(lc3: P, symbols: SymbolTable) -> Self {
+        let pc = lc3.pc();
+        Self {
+            debugger: Debugger::new(lc3),
+            console: ConsoleIO::new(VecDeque::new(), Vec::new()),
+            symbols,
+            cursor: pc,
+            mem_top: pc - pc % ROW_WORDS,
+            output: String::new(),
+            run: None,
+            target: None,
+            waiting: false,
+            status: String::new(),
+            quit: false,
+        }
+    }
+
+    /// Draws and handles keys until the user quits.
+    pub fn run<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> io::Result<()> {
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let timeout = if self.busy() {
+                Duration::ZERO
+            } else {
+                IDLE_POLL
+            };
+            if event::poll(timeout)? {
+                if let Event::Key(key) = event::read()? {
+                    self.key(key);
+                }
+            }
+            self.advance();
+        }
+        Ok(())
+    }
+
+    /// Whether the program is running and not waiting for input.
+    fn busy(&self) -> bool {
+        self.run.is_some() && !self.waiting
+    }
+
+    /// Handles one key press.
+    fn key(&mut self, key: KeyEvent) {
+        if key.kind == KeyEventKind::Release {
+            return;
+        }
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return;
+        }
+
+        // A running program gets every key but Esc
+        if self.run.is_some() {
+            let byte = match key.code {
+                KeyCode::Esc => return self.stop("Paused"),
+                KeyCode::Enter => b'\n',
+                KeyCode::Backspace => 0x08,
+                KeyCode::Tab => b'\t',
+                KeyCode::Char(c) if c.is_ascii() => c as u8,
+                _ => return,
+            };
+            self.console.input_mut().push_back(byte);
+            self.waiting = false;
+            return;
+        }
+
+        self.status.clear();
+        match key.code {
+            KeyCode::Char('s') => self.resume(Run::In),
+            KeyCode::Char('n') => self.resume(Run::Over { depth: 0 }),
+            KeyCode::Char('f') => self.resume(Run::Out { depth: 0 }),
+            KeyCode::Char('c') => self.resume(Run::Continue),
+            KeyCode::Char('r') => {
+                self.target = Some(self.cursor);
+                self.resume(Run::Continue);
+            }
+            KeyCode::Char('b') => self.toggle_breakpoint(),
+            KeyCode::Char('m') => self.mem_top = self.cursor - self.cursor % ROW_WORDS,
+            KeyCode::Char('.') | KeyCode::Home => self.cursor = self.lc3().pc(),
+            KeyCode::Up | KeyCode::Char('k') => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => self.cursor = self.cursor.saturating_add(1),
+            KeyCode::PageUp => self.mem_top = self.mem_top.saturating_sub(ROW_WORDS * 8),
+            KeyCode::PageDown => {
+                self.mem_top = self
+                    .mem_top
+                    .saturating_add(ROW_WORDS * 8)
+                    .min(LC3MemAddr::MAX - (ROW_WORDS - 1))
+            }
+            KeyCode::Char('[') => self.mem_top = self.mem_top.saturating_sub(ROW_WORDS),
+            KeyCode::Char(']') => {
+                self.mem_top = self
+                    .mem_top
+                    .saturating_add(ROW_WORDS)
+                    .min(LC3MemAddr::MAX - (ROW_WORDS - 1))
+            }
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            _ => {}
+        }
+    }
+
+    fn toggle_breakpoint(&mut self) {
+        let cursor = self.cursor;
+        let existing = self
+            .debugger
+            .breakpoints()
+            .find(|(_, breakpoint)| breakpoint.addr() == cursor)
+            .map(|(id, _)| id);
+        match existing {
+            Some(id) => {
+                self.debugger.remove_breakpoint(id);
+            }
+            None => {
+                self.debugger.add_breakpoint(Breakpoint::new(cursor));
+            }
+        }
+    }
+
+    fn resume(&mut self, run: Run) {
+        if self.lc3().is_halted() {
+            self.status = "The program has halted".to_string();
+            return;
+        }
+        self.run = Some(run);
+    }
+
+    /// Runs the program for a while, if it is running.
+    fn advance(&mut self) {
+        let Some(mut run) = self.run.filter(|_| !self.waiting) else {
+            return;
+        };
+
+        let mut stop = None;
+        for _ in 0..CHUNK {
+            let addr = self.lc3().pc();
+            let inst = self.lc3().cur_inst();
+            match self.debugger.step(&mut self.console) {
+                StopReason::Stepped => {}
+                StopReason::Failed(ExecutionFailure::NoKeyboard) => {
+                    self.waiting = true;
+                    break;
+                }
+                StopReason::Breakpoint { addr, .. } => {
+                    stop = Some(format!("Breakpoint at {}", self.describe(addr)));
+                    break;
+                }
+                reason => {
+                    stop = Some(reason.to_string());
+                    break;
+                }
+            }
+
+            let pc = self.lc3().pc();
+            if self.target == Some(pc) || run.done(Flow::of(inst, addr, pc)) {
+                stop = Some(String::new());
+                break;
+            }
+        }
+        self.run = Some(run);
+
+        let output = std::mem::take(self.console.output_mut());
+        self.output += &String::from_utf8_lossy(&output);
+
+        if let Some(status) = stop {
+            self.stop(&status);
+        }
+    }
+
+    /// Stops the program, moving the cursor to the PC.
+    fn stop(&mut self, status: &str) {
+        self.run = None;
+        self.target = None;
+        self.waiting = false;
+        self.cursor = self.lc3().pc();
+        self.status = status.to_string();
+    }
+
+    /// Lays out and draws every pane.
+    fn draw(&self, frame: &mut Frame) {
+        let [main, bottom, status] = Layout::vertical([
+            Constraint::Min(8),
+            Constraint::Length(10),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [registers, code] =
+            Layout::horizontal([Constraint::Length(24), Constraint::Min(30)]).areas(main);
+        let [memory, console] =
+            Layout::horizontal([Constraint::Length(58), Constraint::Min(20)]).areas(bottom);
+
+        self.draw_registers(frame, registers);
+        self.draw_code(frame, code);
+        self.draw_memory(frame, memory);
+        self.draw_console(frame, console);
+
+        let state = if self.waiting {
+            "Waiting for input, Esc to pause"
+        } else if self.run.is_some() {
+            "Running, Esc to pause"
+        } else if self.status.is_empty() {
+            KEYS
+        } else {
+            &self.status
+        };
+        frame.render_widget(Line::from(state).reversed(), status);
+    }
+
+    fn draw_registers(&self, frame: &mut Frame, area: Rect) {
+        let row = |name: String, value: LC3Word| {
+            Line::from(format!("{name:<4}x{value:04X} {:>7}", value as i16))
+        };
+        let mut lines: Vec<Line> = (0..8)
+            .map(|reg| {
+                let reg = RegAddr::panic_from_u8(reg);
+                row(reg.to_string(), self.lc3().reg(reg))
+            })
+            .collect();
+        lines.push(row("PC".to_string(), self.lc3().pc()));
+        lines.push(row("PSR".to_string(), self.lc3().processor_status_reg()));
+
+        // The set condition code stands out
+        let flag = |name: &'static str, set: bool| {
+            let style = if set {
+                Style::new().add_modifier(Modifier::BOLD | Modifier::REVERSED)
+            } else {
+                Style::new().add_modifier(Modifier::DIM)
+            };
+            Span::styled(name, style)
+        };
+        lines.push(Line::default());
+        lines.push(Line::from(vec![
+            "CC  ".into(),
+            flag("N", self.lc3().negative_cond()),
+            " ".into(),
+            flag("Z", self.lc3().zero_cond()),
+            " ".into(),
+            flag("P", self.lc3().positive_cond()),
+        ]));
+        let mode = if self.lc3().privileged() {
+            "Supervisor"
+        } else {
+            "User"
+        };
+        lines.push(Line::from(format!(
+            "{mode}, priority {}",
+            self.lc3().priority()
+        )));
+        if self.lc3().is_halted() {
+            lines.push(Line::from("Halted".bold()));
+        }
+
+        let block = Block::bordered().title("Registers");
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    fn draw_code(&self, frame: &mut Frame, area: Rect) {
+        let height = area.height.saturating_sub(2);
+        let start = self
+            .cursor
+            .saturating_sub(height / 2)
+            .min(LC3MemAddr::MAX - height.saturating_sub(1));
+        let end = u32::from(start) + u32::from(height);
+        let words = (u32::from(start)..end).map(|addr| self.lc3().mem(addr as LC3MemAddr));
+
+        let pc = self.lc3().pc();
+        let lines: Vec<Line> = disassemble(start, words, Some(&self.symbols))
+            .into_iter()
+            .map(|line| {
+                let breakpoint = self.has_breakpoint(line.addr);
+                let marker = match (line.addr == pc, breakpoint) {
+                    (true, true) => "B>",
+                    (true, false) => "=>",
+                    (false, true) => "B ",
+                    (false, false) => "  ",
+                };
+                let mut style = Style::new();
+                if line.addr == pc {
+                    style = style.add_modifier(Modifier::BOLD);
+                }
+                if breakpoint {
+                    style = style.red();
+                }
+                if line.addr == self.cursor {
+                    style = style.add_modifier(Modifier::REVERSED);
+                }
+                Line::styled(format!("{marker} {line}"), style)
+            })
+            .collect();
+
+        let block = Block::bordered().title("Disassembly");
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    fn draw_memory(&self, frame: &mut Frame, area: Rect) {
+        let rows = area.height.saturating_sub(2);
+        let lines: Vec<Line> = (0..rows)
+            .map_while(|row| self.mem_top.checked_add(row * ROW_WORDS))
+            .map(|start| {
+                let words: Vec<LC3Word> = (start..=start.saturating_add(ROW_WORDS - 1))
+                    .map(|addr| self.lc3().mem(addr))
+                    .collect();
+                let hex: Vec<String> = words.iter().map(|word| format!("{word:04X}")).collect();
+                let text: String = words.iter().map(|word| printable(*word)).collect();
+                Line::from(format!("x{start:04X}  {}  {text}", hex.join(" ")))
+            })
+            .collect();
+
+        let block = Block::bordered().title("Memory");
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    fn draw_console(&self, frame: &mut Frame, area: Rect) {
+        // Only the last lines that fit are shown
+        let rows = usize::from(area.height.saturating_sub(2));
+        let lines: Vec<&str> = self.output.split('\n').collect();
+        let shown = lines[lines.len().saturating_sub(rows)..].join("\n");
+
+        let block = Block::bordered().title("Console");
+        let console = Paragraph::new(shown)
+            .block(block)
+            .wrap(Wrap { trim: false });
+        frame.render_widget(console, area);
+    }
+
+    fn lc3(&self) -> &P {
+        self.debugger.processor()
+    }
+
+    fn has_breakpoint(&self, addr: LC3MemAddr) -> bool {
+        self.debugger
+            .breakpoints()
+            .any(|(_, breakpoint)| breakpoint.addr() == addr)
+    }
+
+    /// `addr`, with its label if it has one.
+    fn describe(&self, addr: LC3MemAddr) -> String {
+        match self.symbols.label(addr) {
+            Some(label) => format!("x{addr:04X} ({label})"),
+            None => format!("x{addr:04X}"),
+        }
+    }
+}
+
+/// The character in the low byte of `word`, or `.` if it is not printable.
+fn printable(word: LC3Word) -> char {
+    match u8::try_from(word) {
+        Ok(byte) if byte.is_ascii_graphic() || byte == b' ' => char::from(byte),
+        _ => '.',
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use ratatui::backend::TestBackend;
+
+    use lc3sim_project::{assembler::assemble, executors::core::CoreLC3};
+
+    const PROGRAM: &str = r#"
+                .ORIG x3000
+                JSR TWICE
+                GETC
+                OUT
+                HALT
+        TWICE   ADD R1, R1, #1
+                ADD R1, R1, #1
+                RET
+                .END
+    "#;
+
+    fn app() -> App<CoreLC3> {
+        let assembled = assemble(PROGRAM).unwrap();
+        let mut lc3 = CoreLC3::new();
+        lc3.set_native_os(true);
+        assembled.populate(&mut lc3);
+        lc3.set_pc(0x3000);
+        App::new(lc3, assembled.symbols)
+    }
+
+    fn press(app: &mut App<CoreLC3>, code: KeyCode) {
+        app.key(KeyEvent::from(code));
+        while app.busy() {
+            app.advance();
+        }
+    }
+
+    /// Text of every row on screen.
+    fn screen(app: &App<CoreLC3>) -> Vec<String> {
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..buffer.area.height)
+            .map(|y| {
+                (0..buffer.area.width)
+                    .map(|x| buffer[(x, y)].symbol())
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn on_screen(app: &App<CoreLC3>, text: &str) -> bool {
+        screen(app).iter().any(|row| row.contains(text))
+    }
+
+    #[test]
+    fn stepping() {
+        let mut app = app();
+        press(&mut app, KeyCode::Char('s'));
+        assert_eq!(app.lc3().pc(), 0x3004);
+        press(&mut app, KeyCode::Char('f'));
+        assert_eq!(app.lc3().pc(), 0x3001);
+        assert_eq!(app.lc3().reg(RegAddr::One), 2);
+        assert_eq!(app.cursor, 0x3001);
+    }
+
+    #[test]
+    fn next_over_calls() {
+        let mut app = app();
+        press(&mut app, KeyCode::Char('n'));
+        assert_eq!(app.lc3().pc(), 0x3001);
+        assert_eq!(app.lc3().reg(RegAddr::One), 2);
+    }
+
+    #[test]
+    fn breakpoints_and_run_to_cursor() {
+        let mut app = app();
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Char('b'));
+        assert!(on_screen(&app, "B  x3005"));
+
+        press(&mut app, KeyCode::Char('c'));
+        assert_eq!(app.lc3().pc(), 0x3005);
+        assert_eq!(app.status, "Breakpoint at x3005");
+        assert!(on_screen(&app, "B> x3005"));
+
+        // Toggled off, then run to the RET
+        press(&mut app, KeyCode::Char('b'));
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Char('r'));
+        assert_eq!(app.lc3().pc(), 0x3006);
+        assert!(on_screen(&app, "=> x3006"));
+    }
+
+    #[test]
+    fn console_input() {
+        let mut app = app();
+        press(&mut app, KeyCode::Char('c'));
+        assert!(app.waiting);
+        assert!(on_screen(&app, "Waiting for input"));
+
+        // Typed keys go to the program, not the debugger
+        press(&mut app, KeyCode::Char('q'));
+        assert!(!app.quit);
+        assert_eq!(app.output, "q");
+        assert_eq!(app.status, "Halted");
+        assert!(on_screen(&app, "│q"));
+
+        press(&mut app, KeyCode::Char('c'));
+        assert_eq!(app.status, "The program has halted");
+        press(&mut app, KeyCode::Char('q'));
+        assert!(app.quit);
+    }
+
+    #[test]
+    fn pause() {
+        let mut app = app();
+        app.key(KeyEvent::from(KeyCode::Char('c')));
+        app.advance();
+        app.key(KeyEvent::from(KeyCode::Esc));
+        assert_eq!(app.run, None);
+        assert_eq!(app.status, "Paused");
+        assert!(!app.quit);
+    }
+
+    #[test]
+    fn panes() {
+        let mut app = app();
+        app.debugger.processor_mut().set_reg(RegAddr::Three, 0xFFFE);
+        let rows = screen(&app);
+        assert!(rows[0].contains("Registers"));
+        assert!(rows[0].contains("Disassembly"));
+        assert!(on_screen(&app, "R3  xFFFE      -2"));
+        assert!(on_screen(&app, "=> x3000  x4803"));
+        assert!(on_screen(&app, "JSR TWICE"));
+        assert!(on_screen(&app, "Supervisor, priority 0"));
+        assert!(on_screen(&app, "x3000  4803 F020 F021 F025"));
+
+        app.debugger
+            .processor_mut()
+            .set_mem(0x4000, u16::from(b'h'));
+        app.debugger
+            .processor_mut()
+            .set_mem(0x4001, u16::from(b'i'));
+        press(&mut app, KeyCode::PageDown);
+        assert_eq!(app.mem_top, 0x3040);
+        app.cursor = 0x4001;
+        press(&mut app, KeyCode::Char('m'));
+        assert!(on_screen(&app, "x4000  0068 0069 0000"));
+        assert!(on_screen(&app, "hi......"));
+    }
+}
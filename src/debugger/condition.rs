@@ -0,0 +1,220 @@
+//! Conditions on the machine state, for conditional stops.
+
+use std::fmt;
+
+use crate::{
+    defs::{LC3MemAddr, LC3Word, RegAddr, SignedLC3Word},
+    executors::LC3,
+};
+
+/// A word of machine state, or a constant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operand {
+    Reg(RegAddr),
+    Pc,
+    Psr,
+    /// Memory word, read without side effects.
+    Mem(LC3MemAddr),
+    Const(LC3Word),
+}
+
+impl Operand {
+    pub fn value<P: LC3 + ?Sized>(&self, processor: &P) -> LC3Word {
+        match *self {
+            Self::Reg(reg) => processor.reg(reg),
+            Self::Pc => processor.pc(),
+            Self::Psr => processor.processor_status_reg(),
+            Self::Mem(addr) => processor.mem(addr),
+            Self::Const(value) => value,
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Reg(reg) => write!(f, "{reg}"),
+            Self::Pc => write!(f, "PC"),
+            Self::Psr => write!(f, "PSR"),
+            Self::Mem(addr) => write!(f, "[x{addr:04X}]"),
+            Self::Const(value) => write!(f, "x{value:04X}"),
+        }
+    }
+}
+
+/// Comparison between two operands.
+///
+/// The orderings treat words as two's complement, as the condition codes
+/// do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Cmp {
+    pub fn holds(self, lhs: LC3Word, rhs: LC3Word) -> bool {
+        let (signed_lhs, signed_rhs) = (lhs as SignedLC3Word, rhs as SignedLC3Word);
+        match self {
+            Self::Eq => lhs == rhs,
+            Self::Ne => lhs != rhs,
+            Self::Lt => signed_lhs < signed_rhs,
+            Self::Le => signed_lhs <= signed_rhs,
+            Self::Gt => signed_lhs > signed_rhs,
+            Self::Ge => signed_lhs >= signed_rhs,
+        }
+    }
+}
+
+impl fmt::Display for Cmp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Eq => "==",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+        })
+    }
+}
+
+/// A single bit of processor state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Flag {
+    Negative,
+    Zero,
+    Positive,
+    /// Supervisor mode.
+    Privileged,
+}
+
+impl Flag {
+    pub fn is_set<P: LC3 + ?Sized>(self, processor: &P) -> bool {
+        match self {
+            Self::Negative => processor.negative_cond(),
+            Self::Zero => processor.zero_cond(),
+            Self::Positive => processor.positive_cond(),
+            Self::Privileged => processor.privileged(),
+        }
+    }
+}
+
+impl fmt::Display for Flag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Negative => "N",
+            Self::Zero => "Z",
+            Self::Positive => "P",
+            Self::Privileged => "supervisor",
+        })
+    }
+}
+
+/// Test of the machine state, such as `R0 == x000A` or the zero flag.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Condition {
+    Compare {
+        lhs: Operand,
+        cmp: Cmp,
+        rhs: Operand,
+    },
+    Flag(Flag),
+    Not(Box<Condition>),
+    /// True if every condition is, including when there are none.
+    All(Vec<Condition>),
+    /// True if any condition is.
+    Any(Vec<Condition>),
+}
+
+impl Condition {
+    pub fn compare(lhs: Operand, cmp: Cmp, rhs: Operand) -> Self {
+        Self::Compare { lhs, cmp, rhs }
+    }
+
+    /// Whether this holds for `processor` as it is now.
+    pub fn holds<P: LC3 + ?Sized>(&self, processor: &P) -> bool {
+        match self {
+            Self::Compare { lhs, cmp, rhs } => {
+                cmp.holds(lhs.value(processor), rhs.value(processor))
+            }
+            Self::Flag(flag) => flag.is_set(processor),
+            Self::Not(condition) => !condition.holds(processor),
+            Self::All(conditions) => conditions.iter().all(|c| c.holds(processor)),
+            Self::Any(conditions) => conditions.iter().any(|c| c.holds(processor)),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |f: &mut fmt::Formatter<'_>, conditions: &[Condition], op: &str| {
+            write!(f, "(")?;
+            for (idx, condition) in conditions.iter().enumerate() {
+                if idx > 0 {
+                    write!(f, " {op} ")?;
+                }
+                write!(f, "{condition}")?;
+            }
+            write!(f, ")")
+        };
+
+        match self {
+            Self::Compare { lhs, cmp, rhs } => write!(f, "{lhs} {cmp} {rhs}"),
+            Self::Flag(flag) => write!(f, "{flag}"),
+            Self::Not(condition) => write!(f, "!({condition})"),
+            Self::All(conditions) => join(f, conditions, "&&"),
+            Self::Any(conditions) => join(f, conditions, "||"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::executors::core::CoreLC3;
+
+    #[test]
+    fn compare() {
+        let mut lc3 = CoreLC3::new();
+        lc3.set_reg(RegAddr::Zero, 0xFFFF);
+        lc3.set_mem(0x4000, 10);
+
+        let r0 = Operand::Reg(RegAddr::Zero);
+        let mem = Operand::Mem(0x4000);
+        assert!(Condition::compare(r0, Cmp::Lt, mem).holds(&lc3));
+        assert!(Condition::compare(r0, Cmp::Eq, Operand::Const(0xFFFF)).holds(&lc3));
+        assert!(!Condition::compare(mem, Cmp::Le, Operand::Const(9)).holds(&lc3));
+        assert!(Condition::compare(Operand::Pc, Cmp::Ne, Operand::Psr).holds(&lc3));
+        assert!(!Condition::compare(r0, Cmp::Ge, mem).holds(&lc3));
+    }
+
+    #[test]
+    fn combine() {
+        let lc3 = CoreLC3::new();
+        let zero = Condition::Flag(Flag::Zero);
+        let negative = Condition::Flag(Flag::Negative);
+
+        assert!(zero.holds(&lc3));
+        assert!(Condition::Flag(Flag::Privileged).holds(&lc3));
+        assert!(Condition::Not(Box::new(negative.clone())).holds(&lc3));
+        assert!(Condition::Any(vec![negative.clone(), zero.clone()]).holds(&lc3));
+        assert!(!Condition::All(vec![negative, zero]).holds(&lc3));
+        assert!(Condition::All(Vec::new()).holds(&lc3));
+    }
+
+    #[test]
+    fn display() {
+        let compare = Condition::compare(Operand::Mem(0xFE00), Cmp::Ge, Operand::Pc);
+        let condition = Condition::Any(vec![
+            compare,
+            Condition::Not(Box::new(Condition::Flag(Flag::Zero))),
+        ]);
+        assert_eq!(condition.to_string(), "([xFE00] >= PC || !(Z))");
+    }
+}
@@ -0,0 +1,738 @@
+//! Breakpoints and watchpoints, shared by every front end.
+//!
+//! [`Debugger`] steps a processor on a [`SyncHarness`] and stops with a
+//! [`StopReason`] instead of running to HALT like
+//! [`step_continue`](crate::harnesses::sync::step_continue).
+
+use std::{collections::BTreeMap, fmt, ops::RangeInclusive};
+
+use crate::{
+    defs::{LC3MemAddr, LC3Word},
+    executors::{Change, StepFailure, LC3},
+    harnesses::{sync::SyncHarness, ExecutionFailure},
+    instruction::{IJump, InstructionEnum},
+};
+
+pub mod condition;
+
+pub use condition::{Cmp, Condition, Flag, Operand};
+
+/// Identifies a breakpoint of a [`Debugger`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BreakpointId(pub u32);
+
+/// Identifies a watchpoint of a [`Debugger`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct WatchpointId(pub u32);
+
+/// Stop when the PC reaches an address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    addr: LC3MemAddr,
+    condition: Option<Condition>,
+    ignore: u64,
+    temporary: bool,
+    enabled: bool,
+    hits: u64,
+}
+
+impl Breakpoint {
+    pub fn new(addr: LC3MemAddr) -> Self {
+        Self {
+            addr,
+            condition: None,
+            ignore: 0,
+            temporary: false,
+            enabled: true,
+            hits: 0,
+        }
+    }
+
+    /// Only counts hits when `condition` holds on arrival.
+    pub fn with_condition(mut self, condition: Condition) -> Self {
+        self.condition = Some(condition);
+        self
+    }
+
+    /// Keeps going for the first `count` hits.
+    pub fn with_ignore_count(mut self, count: u64) -> Self {
+        self.ignore = count;
+        self
+    }
+
+    /// Removes the breakpoint once it stops.
+    pub fn temporary(mut self) -> Self {
+        self.temporary = true;
+        self
+    }
+
+    pub fn addr(&self) -> LC3MemAddr {
+        self.addr
+    }
+
+    pub fn condition(&self) -> Option<&Condition> {
+        self.condition.as_ref()
+    }
+
+    pub fn ignore_count(&self) -> u64 {
+        self.ignore
+    }
+
+    pub fn is_temporary(&self) -> bool {
+        self.temporary
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Times the PC reached the address with the condition holding,
+    /// including ignored hits.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+}
+
+/// Memory access a watchpoint stops on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// Reads and writes.
+    Access,
+    /// Any change in value, including a device changing its own registers.
+    Change,
+}
+
+impl WatchKind {
+    fn matches(self, access: &Access) -> bool {
+        matches!(
+            (self, access),
+            (Self::Read | Self::Access, Access::Read { .. })
+                | (Self::Write | Self::Access, Access::Write { .. })
+                | (Self::Change, Access::Change { .. })
+        )
+    }
+}
+
+/// Stop when an instruction accesses a range of memory.
+///
+/// Reads are the words loaded by LD, LDI and LDR, and the pointers followed
+/// by LDI and STI. Writes are every store, including to device registers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    range: RangeInclusive<LC3MemAddr>,
+    kind: WatchKind,
+    condition: Option<Condition>,
+    ignore: u64,
+    enabled: bool,
+    hits: u64,
+}
+
+impl Watchpoint {
+    pub fn new(range: RangeInclusive<LC3MemAddr>, kind: WatchKind) -> Self {
+        Self {
+            range,
+            kind,
+            condition: None,
+            ignore: 0,
+            enabled: true,
+            hits: 0,
+        }
+    }
+
+    /// Only counts hits when `condition` holds after the access.
+    pub fn with_condition(mut self, condition: Condition) -> Self {
+        self.condition = Some(condition);
+        self
+    }
+
+    /// Keeps going for the first `count` hits.
+    pub fn with_ignore_count(mut self, count: u64) -> Self {
+        self.ignore = count;
+        self
+    }
+
+    pub fn range(&self) -> &RangeInclusive<LC3MemAddr> {
+        &self.range
+    }
+
+    pub fn kind(&self) -> WatchKind {
+        self.kind
+    }
+
+    pub fn condition(&self) -> Option<&Condition> {
+        self.condition.as_ref()
+    }
+
+    pub fn ignore_count(&self) -> u64 {
+        self.ignore
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Times a matching access happened with the condition holding,
+    /// including ignored hits.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+}
+
+/// A memory access seen by a watchpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Access {
+    Read {
+        addr: LC3MemAddr,
+        value: LC3Word,
+    },
+    Write {
+        addr: LC3MemAddr,
+        value: LC3Word,
+    },
+    Change {
+        addr: LC3MemAddr,
+        old: LC3Word,
+        new: LC3Word,
+    },
+}
+
+impl Access {
+    pub fn addr(&self) -> LC3MemAddr {
+        match *self {
+            Self::Read { addr, .. } | Self::Write { addr, .. } | Self::Change { addr, .. } => addr,
+        }
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read { addr, value } => write!(f, "read x{value:04X} from x{addr:04X}"),
+            Self::Write { addr, value } => write!(f, "wrote x{value:04X} to x{addr:04X}"),
+            Self::Change { addr, old, new } => {
+                write!(f, "x{addr:04X} changed from x{old:04X} to x{new:04X}")
+            }
+        }
+    }
+}
+
+/// Why [`Debugger`] handed control back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StopReason {
+    /// [`Debugger::step`] finished its instruction.
+    Stepped,
+    /// [`Debugger::run_for`] took every step it was allowed.
+    Limit,
+    Breakpoint {
+        id: BreakpointId,
+        addr: LC3MemAddr,
+    },
+    Watchpoint {
+        id: WatchpointId,
+        access: Access,
+    },
+    Halted,
+    Failed(ExecutionFailure),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stepped => write!(f, "Stepped"),
+            Self::Limit => write!(f, "Reached the step limit"),
+            Self::Breakpoint { id, addr } => write!(f, "Breakpoint {} at x{addr:04X}", id.0),
+            Self::Watchpoint { id, access } => write!(f, "Watchpoint {}: {access}", id.0),
+            Self::Halted => write!(f, "Halted"),
+            Self::Failed(e) => write!(f, "Error: {e}"),
+        }
+    }
+}
+
+/// Effect of one instruction on the call stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Flow {
+    Call,
+    Return,
+    Other,
+}
+
+impl Flow {
+    /// Classifies `inst`, executed at `addr`, by the PC it left behind.
+    ///
+    /// JSR, JSRR and TRAP call, unless the PC stayed put or moved on to the
+    /// next instruction, as a native TRAP does. RET and RTI return.
+    pub fn of(inst: Option<InstructionEnum>, addr: LC3MemAddr, pc: LC3MemAddr) -> Self {
+        let in_place = pc == addr.wrapping_add(1) || pc == addr;
+        match inst {
+            Some(InstructionEnum::IJumpSubRoutine(_) | InstructionEnum::Trap(_)) if !in_place => {
+                Self::Call
+            }
+            Some(InstructionEnum::IJump(IJump::Ret | IJump::InterRet)) => Self::Return,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// Steps a processor, stopping at breakpoints and watchpoints.
+///
+/// Breakpoints are checked after each step, so resuming from one moves past
+/// it. Watchpoints are checked against the accesses of each step, and stop
+/// before breakpoints at the address reached.
+#[derive(Debug, Clone)]
+pub struct Debugger<P> {
+    lc3: P,
+    breakpoints: BTreeMap<BreakpointId, Breakpoint>,
+    watchpoints: BTreeMap<WatchpointId, Watchpoint>,
+    next_id: u32,
+}
+
+impl<P: LC3> Debugger<P> {
+    /// Starts recording `lc3`'s changes, to see its writes.
+    pub fn new(mut lc3: P) -> Self {
+        lc3.set_recording(true);
+        Self {
+            lc3,
+            breakpoints: BTreeMap::new(),
+            watchpoints: BTreeMap::new(),
+            next_id: 1,
+        }
+    }
+
+    pub fn processor(&self) -> &P {
+        &self.lc3
+    }
+
+    /// Changes made through this between steps are not watched.
+    pub fn processor_mut(&mut self) -> &mut P {
+        &mut self.lc3
+    }
+
+    /// Stops recording and returns the wrapped processor.
+    pub fn into_inner(mut self) -> P {
+        self.lc3.set_recording(false);
+        self.lc3
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> BreakpointId {
+        let id = BreakpointId(self.take_id());
+        self.breakpoints.insert(id, breakpoint);
+        id
+    }
+
+    pub fn remove_breakpoint(&mut self, id: BreakpointId) -> Option<Breakpoint> {
+        self.breakpoints.remove(&id)
+    }
+
+    pub fn breakpoint(&self, id: BreakpointId) -> Option<&Breakpoint> {
+        self.breakpoints.get(&id)
+    }
+
+    pub fn breakpoint_mut(&mut self, id: BreakpointId) -> Option<&mut Breakpoint> {
+        self.breakpoints.get_mut(&id)
+    }
+
+    /// Every breakpoint, in the order added.
+    pub fn breakpoints(&self) -> impl Iterator<Item = (BreakpointId, &Breakpoint)> {
+        self.breakpoints
+            .iter()
+            .map(|(id, breakpoint)| (*id, breakpoint))
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> WatchpointId {
+        let id = WatchpointId(self.take_id());
+        self.watchpoints.insert(id, watchpoint);
+        id
+    }
+
+    pub fn remove_watchpoint(&mut self, id: WatchpointId) -> Option<Watchpoint> {
+        self.watchpoints.remove(&id)
+    }
+
+    pub fn watchpoint(&self, id: WatchpointId) -> Option<&Watchpoint> {
+        self.watchpoints.get(&id)
+    }
+
+    pub fn watchpoint_mut(&mut self, id: WatchpointId) -> Option<&mut Watchpoint> {
+        self.watchpoints.get_mut(&id)
+    }
+
+    /// Every watchpoint, in the order added.
+    pub fn watchpoints(&self) -> impl Iterator<Item = (WatchpointId, &Watchpoint)> {
+        self.watchpoints
+            .iter()
+            .map(|(id, watchpoint)| (*id, watchpoint))
+    }
+
+    /// Executes one instruction.
+    pub fn step<H: SyncHarness>(&mut self, harness: &mut H) -> StopReason {
+        self.advance(harness).unwrap_or(StopReason::Stepped)
+    }
+
+    /// Runs until something stops it.
+    pub fn run<H: SyncHarness>(&mut self, harness: &mut H) -> StopReason {
+        loop {
+            if let Some(stop) = self.advance(harness) {
+                return stop;
+            }
+        }
+    }
+
+    /// Runs until something stops it, or for at most `limit` steps.
+    pub fn run_for<H: SyncHarness>(&mut self, harness: &mut H, limit: u64) -> StopReason {
+        for _ in 0..limit {
+            if let Some(stop) = self.advance(harness) {
+                return stop;
+            }
+        }
+        StopReason::Limit
+    }
+
+    /// Takes one step, returning why to stop after it, if anything.
+    fn advance<H: SyncHarness>(&mut self, harness: &mut H) -> Option<StopReason> {
+        // Changes made between steps are not the program's
+        self.lc3.take_changes();
+
+        let reads = self.reads();
+        let before: Vec<(WatchpointId, Vec<LC3Word>)> = self
+            .watchpoints
+            .iter()
+            .filter(|(_, watch)| watch.enabled && watch.kind == WatchKind::Change)
+            .map(|(id, watch)| (*id, watch.range.clone().map(|a| self.lc3.mem(a)).collect()))
+            .collect();
+
+        match harness.step(&mut self.lc3) {
+            Ok(()) => {}
+            Err(ExecutionFailure::LC3(StepFailure::Halted)) => return Some(StopReason::Halted),
+            Err(e) => return Some(StopReason::Failed(e)),
+        }
+
+        let mut accesses: Vec<Access> = reads
+            .into_iter()
+            .map(|addr| Access::Read {
+                addr,
+                value: self.lc3.mem(addr),
+            })
+            .collect();
+        accesses.extend(
+            self.lc3
+                .take_changes()
+                .into_iter()
+                .filter_map(|change| match change {
+                    Change::Mem { addr, new, .. } => Some(Access::Write { addr, value: new }),
+                    Change::Device { addr, value } => Some(Access::Write { addr, value }),
+                    _ => None,
+                }),
+        );
+        let changed = |id: WatchpointId, watch: &Watchpoint| {
+            let (_, old) = before.iter().find(|(watched, _)| *watched == id)?;
+            watch
+                .range
+                .clone()
+                .zip(old)
+                .find(|(addr, old)| self.lc3.mem(*addr) != **old)
+                .map(|(addr, old)| Access::Change {
+                    addr,
+                    old: *old,
+                    new: self.lc3.mem(addr),
+                })
+        };
+
+        let mut stop = None;
+        for (id, watch) in self.watchpoints.iter_mut().filter(|(_, w)| w.enabled) {
+            let access = if watch.kind == WatchKind::Change {
+                changed(*id, watch)
+            } else {
+                accesses
+                    .iter()
+                    .find(|access| {
+                        watch.kind.matches(access) && watch.range.contains(&access.addr())
+                    })
+                    .copied()
+            };
+            let Some(access) = access else {
+                continue;
+            };
+
+            if watch.condition.as_ref().is_none_or(|c| c.holds(&self.lc3)) {
+                watch.hits += 1;
+                if watch.hits > watch.ignore && stop.is_none() {
+                    stop = Some(StopReason::Watchpoint { id: *id, access });
+                }
+            }
+        }
+        if stop.is_some() {
+            return stop;
+        }
+
+        if self.lc3.is_halted() {
+            return Some(StopReason::Halted);
+        }
+
+        let pc = self.lc3.pc();
+        for (id, breakpoint) in self.breakpoints.iter_mut() {
+            if !breakpoint.enabled || breakpoint.addr != pc {
+                continue;
+            }
+            if breakpoint
+                .condition
+                .as_ref()
+                .is_none_or(|c| c.holds(&self.lc3))
+            {
+                breakpoint.hits += 1;
+                if breakpoint.hits > breakpoint.ignore && stop.is_none() {
+                    stop = Some(StopReason::Breakpoint { id: *id, addr: pc });
+                }
+            }
+        }
+        if let Some(StopReason::Breakpoint { id, .. }) = stop {
+            if self.breakpoints[&id].temporary {
+                self.breakpoints.remove(&id);
+            }
+        }
+        stop
+    }
+
+    /// Addresses the next step reads data from.
+    fn reads(&self) -> Vec<LC3MemAddr> {
+        // A violation raises an exception instead
+        if self.lc3.access_violation().is_some() {
+            return Vec::new();
+        }
+
+        let (pointer, source) = match self.lc3.cur_inst() {
+            Some(InstructionEnum::ILoad(load)) => {
+                (load.pointer_addr(&self.lc3), load.source_addr(&self.lc3))
+            }
+            Some(InstructionEnum::IStore(store)) => (store.pointer_addr(&self.lc3), None),
+            _ => (None, None),
+        };
+        pointer.into_iter().chain(source).collect()
+    }
+
+    fn take_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        defs::{RegAddr, DISPLAY_DATA_REGISTER, KEYBOARD_DATA_REGISTER},
+        executors::core::{load_program, CoreLC3},
+        harnesses::scripted::ScriptedIO,
+        instruction::Instruction,
+    };
+
+    /// Sums 5 down to 1 into SUMS, then echoes a key by polling.
+    const PROGRAM: &str = r#"
+                .ORIG x3000
+                LEA R3, SUMS
+                AND R2, R2, #0
+                ADD R2, R2, #5
+        LOOP    ADD R1, R1, R2
+                STR R1, R3, #0
+                ADD R3, R3, #1
+                ADD R2, R2, #-1
+                BRp LOOP
+        POLL    LDI R4, KBSR
+                BRzp POLL
+                LDI R0, KBDR
+                STI R0, DDR
+                HALT
+        KBSR    .FILL xFE00
+        KBDR    .FILL xFE02
+        DDR     .FILL xFE06
+        SUMS    .BLKW 5
+                .END
+    "#;
+
+    const LOOP: LC3MemAddr = 0x3003;
+    const SUMS: LC3MemAddr = 0x3010;
+
+    fn debugger() -> Debugger<CoreLC3> {
+        Debugger::new(load_program(PROGRAM, true))
+    }
+
+    #[test]
+    fn flow() {
+        let flow = |word, addr, pc| Flow::of(InstructionEnum::parse(word), addr, pc);
+
+        // JSR and a TRAP into the OS call
+        assert_eq!(flow(0x4805, 0x3000, 0x3006), Flow::Call);
+        assert_eq!(flow(0xF025, 0x3000, 0x0520), Flow::Call);
+        // A native TRAP doesn't, whether it returned or waits for input
+        assert_eq!(flow(0xF021, 0x3000, 0x3001), Flow::Other);
+        assert_eq!(flow(0xF020, 0x3000, 0x3000), Flow::Other);
+        // RET and RTI return
+        assert_eq!(flow(0xC1C0, 0x3010, 0x3001), Flow::Return);
+        assert_eq!(flow(0x8000, 0x0600, 0x3001), Flow::Return);
+        assert_eq!(flow(0x1261, 0x3000, 0x3001), Flow::Other);
+        assert_eq!(flow(0xD000, 0x3000, 0x3001), Flow::Other);
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut debugger = debugger();
+        let mut io = ScriptedIO::new("x");
+        let id = debugger.add_breakpoint(Breakpoint::new(LOOP));
+
+        assert_eq!(
+            debugger.run(&mut io),
+            StopReason::Breakpoint { id, addr: LOOP }
+        );
+        assert_eq!(debugger.processor().reg(RegAddr::Two), 5);
+        assert_eq!(debugger.step(&mut io), StopReason::Stepped);
+
+        // Resuming moves past the breakpoint
+        assert_eq!(
+            debugger.run(&mut io),
+            StopReason::Breakpoint { id, addr: LOOP }
+        );
+        assert_eq!(debugger.processor().reg(RegAddr::Two), 4);
+        assert_eq!(debugger.breakpoint(id).unwrap().hits(), 2);
+
+        debugger.breakpoint_mut(id).unwrap().set_enabled(false);
+        assert_eq!(debugger.run(&mut io), StopReason::Halted);
+        assert_eq!(io.output(), b"x");
+    }
+
+    #[test]
+    fn conditions_and_hit_counts() {
+        let mut debugger = debugger();
+        let mut io = ScriptedIO::new("x");
+
+        // The second pass with R2 at most 3
+        let r2 = Operand::Reg(RegAddr::Two);
+        let id = debugger.add_breakpoint(
+            Breakpoint::new(LOOP)
+                .with_condition(Condition::compare(r2, Cmp::Le, Operand::Const(3)))
+                .with_ignore_count(1),
+        );
+        assert_eq!(
+            debugger.run(&mut io),
+            StopReason::Breakpoint { id, addr: LOOP }
+        );
+        assert_eq!(debugger.processor().reg(RegAddr::Two), 2);
+        assert_eq!(debugger.breakpoint(id).unwrap().hits(), 2);
+
+        // Temporary breakpoints go once they stop
+        let once = debugger.add_breakpoint(Breakpoint::new(LOOP + 1).temporary());
+        assert_eq!(
+            debugger.run(&mut io),
+            StopReason::Breakpoint {
+                id: once,
+                addr: LOOP + 1
+            }
+        );
+        assert_eq!(debugger.breakpoint(once), None);
+        assert_eq!(debugger.breakpoints().count(), 1);
+    }
+
+    #[test]
+    fn watch_writes_and_changes() {
+        let mut debugger = debugger();
+        let mut io = ScriptedIO::new("x");
+
+        let write = debugger.add_watchpoint(Watchpoint::new(SUMS + 2..=SUMS + 4, WatchKind::Write));
+        let stop = debugger.run(&mut io);
+        assert_eq!(
+            stop,
+            StopReason::Watchpoint {
+                id: write,
+                access: Access::Write {
+                    addr: SUMS + 2,
+                    value: 5 + 4 + 3
+                }
+            }
+        );
+        assert_eq!(stop.to_string(), "Watchpoint 1: wrote x000C to x3012");
+        debugger.remove_watchpoint(write);
+
+        // Only once the running sum passes 13
+        let sums = Operand::Mem(SUMS + 3);
+        let change =
+            debugger.add_watchpoint(
+                Watchpoint::new(SUMS..=SUMS + 4, WatchKind::Change)
+                    .with_condition(Condition::compare(sums, Cmp::Gt, Operand::Const(13))),
+            );
+        assert_eq!(
+            debugger.run(&mut io),
+            StopReason::Watchpoint {
+                id: change,
+                access: Access::Change {
+                    addr: SUMS + 3,
+                    old: 0,
+                    new: 14
+                }
+            }
+        );
+        assert_eq!(debugger.watchpoint(change).unwrap().hits(), 1);
+    }
+
+    #[test]
+    fn watch_device_registers() {
+        let mut debugger = debugger();
+        let mut io = ScriptedIO::new("x");
+
+        let read = debugger.add_watchpoint(Watchpoint::new(
+            KEYBOARD_DATA_REGISTER..=KEYBOARD_DATA_REGISTER,
+            WatchKind::Read,
+        ));
+        let write = debugger.add_watchpoint(Watchpoint::new(
+            DISPLAY_DATA_REGISTER..=DISPLAY_DATA_REGISTER,
+            WatchKind::Access,
+        ));
+        assert_eq!(
+            debugger.run(&mut io),
+            StopReason::Watchpoint {
+                id: read,
+                access: Access::Read {
+                    addr: KEYBOARD_DATA_REGISTER,
+                    value: u16::from(b'x')
+                }
+            }
+        );
+        assert_eq!(
+            debugger.run(&mut io),
+            StopReason::Watchpoint {
+                id: write,
+                access: Access::Write {
+                    addr: DISPLAY_DATA_REGISTER,
+                    value: u16::from(b'x')
+                }
+            }
+        );
+        assert_eq!(debugger.run(&mut io), StopReason::Halted);
+    }
+
+    #[test]
+    fn limits_and_failures() {
+        let mut debugger = debugger();
+        assert_eq!(
+            debugger.run_for(&mut ScriptedIO::new(""), 3),
+            StopReason::Limit
+        );
+        assert_eq!(debugger.processor().pc(), LOOP);
+
+        // Polling with no key left
+        assert_eq!(
+            debugger.run(&mut ScriptedIO::new("")),
+            StopReason::Failed(ExecutionFailure::NoKeyboard)
+        );
+        assert_eq!(debugger.into_inner().pc(), 0x3008);
+    }
+}
@@ -21,6 +21,12 @@ pub const DEV_REG_ADDR: LC3Word = 0xFE00;
 /// Initial supervisor stack pointer value.
 pub const SUPERVISOR_SP_INIT: LC3Word = USER_SPACE - 1;
 
+/// Vector for the privilege mode violation exception.
+pub const PRIVILEGE_EXCEPTION: LC3Word = 0x0000;
+/// Vector for the illegal opcode exception.
+pub const ILLEGAL_OPCODE_EXCEPTION: LC3Word = 0x0001;
+/// Vector for the access control violation exception.
+pub const ACCESS_CONTROL_EXCEPTION: LC3Word = 0x0002;
 /// Vector for a keyboard I/O interrupt.
 pub const KEYBOARD_INTERRUPT: LC3Word = 0x0080;
 /// Priority for an I/O interrupt
@@ -127,6 +133,12 @@ impl From<RegAddr> for usize {
     }
 }
 
+impl std::fmt::Display for RegAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "R{}", u8::from(*self))
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, EnumDiscriminants)]
 pub enum Op {
     ADD,
@@ -152,6 +164,7 @@ pub enum Op {
     IN,
     PUTSP,
     HALT,
+    JMPT,
     ILLEGAL,
 }
 
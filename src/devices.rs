@@ -0,0 +1,453 @@
+//! Memory-mapped device registers.
+//!
+//! Loads and stores to a mapped address go to its [`Device`] rather than to
+//! memory. The keyboard and display are always mapped, at
+//! [`KEYBOARD_STATUS_REGISTER`] through [`DISPLAY_DATA_REGISTER`]. Other
+//! devices can be mapped anywhere else from [`DEV_REG_ADDR`], except the
+//! [`MACHINE_CONTROL_REGISTER`], which the executor handles itself.
+
+use std::{fmt, mem, ops::RangeInclusive};
+
+use thiserror::Error;
+
+use crate::defs::{
+    LC3MemAddr, LC3Word, DEV_REG_ADDR, DISPLAY_DATA_REGISTER, DISPLAY_STATUS_REGISTER, IO_PRIORITY,
+    KEYBOARD_DATA_REGISTER, KEYBOARD_INTERRUPT, KEYBOARD_STATUS_REGISTER, MACHINE_CONTROL_REGISTER,
+};
+
+/// Status register bit set when the device is ready.
+pub const READY_BIT: LC3Word = 1 << 15;
+/// Status register bit set when the device may interrupt.
+pub const INTERRUPT_ENABLE_BIT: LC3Word = 1 << 14;
+
+/// Interrupt requested by a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Interrupt {
+    /// Offset into the interrupt vector table.
+    pub vector: LC3Word,
+    /// Priority in [0, 7] the service routine runs at.
+    pub priority: u8,
+}
+
+/// Registers of a memory-mapped device.
+pub trait Device: fmt::Debug + Send + DeviceClone {
+    /// Value of the register at `addr`, without side effects.
+    fn peek(&self, addr: LC3MemAddr) -> LC3Word;
+
+    /// Value of the register at `addr`, as read by a load instruction.
+    fn read(&mut self, addr: LC3MemAddr) -> LC3Word {
+        self.peek(addr)
+    }
+
+    /// Stores `value` to the register at `addr`.
+    fn write(&mut self, addr: LC3MemAddr, value: LC3Word);
+
+    /// Interrupt this device is currently requesting, if any.
+    fn interrupt(&self) -> Option<Interrupt> {
+        None
+    }
+}
+
+/// Object safe [`Clone`] for boxed [`Device`]s.
+///
+/// Implemented for every [`Device`] that is [`Clone`].
+pub trait DeviceClone {
+    fn clone_box(&self) -> Box<dyn Device>;
+}
+
+impl<T: Device + Clone + 'static> DeviceClone for T {
+    fn clone_box(&self) -> Box<dyn Device> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Device> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+fn status(ready: bool, interrupt_enable: bool) -> LC3Word {
+    let ready = if ready { READY_BIT } else { 0 };
+    let interrupt_enable = if interrupt_enable {
+        INTERRUPT_ENABLE_BIT
+    } else {
+        0
+    };
+    ready | interrupt_enable
+}
+
+/// Keyboard, at KBSR and KBDR.
+///
+/// Reading KBDR clears the ready bit in KBSR. Requests
+/// [`KEYBOARD_INTERRUPT`] while a key is waiting and interrupts are enabled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Keyboard {
+    data: LC3Word,
+    ready: bool,
+    interrupt_enable: bool,
+}
+
+impl Keyboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Latches `key` into KBDR and sets the ready bit.
+    ///
+    /// Replaces any key that was not read yet, as the hardware does.
+    pub fn press(&mut self, key: u8) {
+        self.data = key.into();
+        self.ready = true;
+    }
+
+    /// True if a key is waiting in KBDR.
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
+    /// True if the program set the interrupt enable bit in KBSR.
+    pub fn interrupt_enabled(&self) -> bool {
+        self.interrupt_enable
+    }
+}
+
+impl Device for Keyboard {
+    fn peek(&self, addr: LC3MemAddr) -> LC3Word {
+        match addr {
+            KEYBOARD_STATUS_REGISTER => status(self.ready, self.interrupt_enable),
+            KEYBOARD_DATA_REGISTER => self.data,
+            _ => 0,
+        }
+    }
+
+    fn read(&mut self, addr: LC3MemAddr) -> LC3Word {
+        let value = self.peek(addr);
+        if addr == KEYBOARD_DATA_REGISTER {
+            self.ready = false;
+        }
+        value
+    }
+
+    /// Only the interrupt enable bit of KBSR is writable.
+    fn write(&mut self, addr: LC3MemAddr, value: LC3Word) {
+        if addr == KEYBOARD_STATUS_REGISTER {
+            self.interrupt_enable = (value & INTERRUPT_ENABLE_BIT) != 0;
+        }
+    }
+
+    fn interrupt(&self) -> Option<Interrupt> {
+        (self.ready && self.interrupt_enable).then_some(Interrupt {
+            vector: KEYBOARD_INTERRUPT,
+            priority: IO_PRIORITY,
+        })
+    }
+}
+
+/// Display, at DSR and DDR.
+///
+/// Always ready. Each write to DDR appends its low byte to the output, which
+/// is held until [`Self::take_output`]. The ISA assigns the display no
+/// interrupt vector, so it never interrupts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Display {
+    output: Vec<u8>,
+    interrupt_enable: bool,
+}
+
+impl Display {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Characters written since the last [`Self::take_output`].
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Removes and returns the pending output.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        mem::take(&mut self.output)
+    }
+
+    /// True if the program set the interrupt enable bit in DSR.
+    pub fn interrupt_enabled(&self) -> bool {
+        self.interrupt_enable
+    }
+}
+
+impl Device for Display {
+    fn peek(&self, addr: LC3MemAddr) -> LC3Word {
+        match addr {
+            DISPLAY_STATUS_REGISTER => status(true, self.interrupt_enable),
+            DISPLAY_DATA_REGISTER => self.output.last().copied().unwrap_or_default().into(),
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, addr: LC3MemAddr, value: LC3Word) {
+        match addr {
+            DISPLAY_STATUS_REGISTER => {
+                self.interrupt_enable = (value & INTERRUPT_ENABLE_BIT) != 0;
+            }
+            DISPLAY_DATA_REGISTER => self.output.push(value as u8),
+            _ => (),
+        }
+    }
+}
+
+/// Failure to map a device.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum MapError {
+    #[error("x{:04X}..=x{:04X} is not in the device register space", .0.start(), .0.end())]
+    OutsideDeviceSpace(RangeInclusive<LC3MemAddr>),
+    #[error("x{:04X}..=x{:04X} overlaps another device", .0.start(), .0.end())]
+    Overlaps(RangeInclusive<LC3MemAddr>),
+}
+
+/// Every memory-mapped device, dispatched by address.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceBus {
+    pub keyboard: Keyboard,
+    pub display: Display,
+    mapped: Vec<(RangeInclusive<LC3MemAddr>, Box<dyn Device>)>,
+}
+
+/// Addresses that can never be mapped to another device.
+const RESERVED: [LC3MemAddr; 5] = [
+    KEYBOARD_STATUS_REGISTER,
+    KEYBOARD_DATA_REGISTER,
+    DISPLAY_STATUS_REGISTER,
+    DISPLAY_DATA_REGISTER,
+    MACHINE_CONTROL_REGISTER,
+];
+
+impl DeviceBus {
+    /// Bus with only the keyboard and display.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maps `device` to every address in `range`.
+    pub fn map<D: Device + 'static>(
+        &mut self,
+        range: RangeInclusive<LC3MemAddr>,
+        device: D,
+    ) -> Result<(), MapError> {
+        if range.is_empty() || *range.start() < DEV_REG_ADDR {
+            return Err(MapError::OutsideDeviceSpace(range));
+        }
+
+        let overlaps_reserved = RESERVED.iter().any(|addr| range.contains(addr));
+        let overlaps_mapped = self
+            .mapped
+            .iter()
+            .any(|(other, _)| range.start() <= other.end() && other.start() <= range.end());
+        if overlaps_reserved || overlaps_mapped {
+            return Err(MapError::Overlaps(range));
+        }
+
+        self.mapped.push((range, Box::new(device)));
+        Ok(())
+    }
+
+    /// True if a device handles `addr`.
+    pub fn is_mapped(&self, addr: LC3MemAddr) -> bool {
+        self.device(addr).is_some()
+    }
+
+    /// Device register at `addr` without side effects, or `None` if
+    /// unmapped.
+    pub fn peek(&self, addr: LC3MemAddr) -> Option<LC3Word> {
+        self.device(addr).map(|device| device.peek(addr))
+    }
+
+    /// Device register at `addr` as read by a load, or `None` if unmapped.
+    pub fn read(&mut self, addr: LC3MemAddr) -> Option<LC3Word> {
+        self.device_mut(addr).map(|device| device.read(addr))
+    }
+
+    /// Writes the device register at `addr`, returning false if unmapped.
+    pub fn write(&mut self, addr: LC3MemAddr, value: LC3Word) -> bool {
+        self.device_mut(addr)
+            .map(|device| device.write(addr, value))
+            .is_some()
+    }
+
+    /// Highest priority interrupt any device is requesting.
+    ///
+    /// Ties go to the keyboard, then to the earliest mapped device.
+    pub fn pending_interrupt(&self) -> Option<Interrupt> {
+        [self.keyboard.interrupt(), self.display.interrupt()]
+            .into_iter()
+            .chain(self.mapped.iter().map(|(_, device)| device.interrupt()))
+            .flatten()
+            .reduce(|best, next| {
+                if next.priority > best.priority {
+                    next
+                } else {
+                    best
+                }
+            })
+    }
+
+    fn device(&self, addr: LC3MemAddr) -> Option<&dyn Device> {
+        match addr {
+            KEYBOARD_STATUS_REGISTER | KEYBOARD_DATA_REGISTER => Some(&self.keyboard),
+            DISPLAY_STATUS_REGISTER | DISPLAY_DATA_REGISTER => Some(&self.display),
+            _ => self
+                .mapped
+                .iter()
+                .find(|(range, _)| range.contains(&addr))
+                .map(|(_, device)| &**device),
+        }
+    }
+
+    fn device_mut(&mut self, addr: LC3MemAddr) -> Option<&mut (dyn Device + 'static)> {
+        match addr {
+            KEYBOARD_STATUS_REGISTER | KEYBOARD_DATA_REGISTER => Some(&mut self.keyboard),
+            DISPLAY_STATUS_REGISTER | DISPLAY_DATA_REGISTER => Some(&mut self.display),
+            _ => self
+                .mapped
+                .iter_mut()
+                .find(|(range, _)| range.contains(&addr))
+                .map(|(_, device)| &mut **device),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Counts reads, at a single address.
+    #[derive(Debug, Clone, Default)]
+    struct Counter(LC3Word);
+
+    impl Device for Counter {
+        fn peek(&self, _: LC3MemAddr) -> LC3Word {
+            self.0
+        }
+
+        fn read(&mut self, _: LC3MemAddr) -> LC3Word {
+            self.0 += 1;
+            self.0
+        }
+
+        fn write(&mut self, _: LC3MemAddr, value: LC3Word) {
+            self.0 = value;
+        }
+    }
+
+    /// Always requests the same interrupt.
+    #[derive(Debug, Clone)]
+    struct Alarm(Interrupt);
+
+    impl Device for Alarm {
+        fn peek(&self, _: LC3MemAddr) -> LC3Word {
+            0
+        }
+
+        fn write(&mut self, _: LC3MemAddr, _: LC3Word) {}
+
+        fn interrupt(&self) -> Option<Interrupt> {
+            Some(self.0)
+        }
+    }
+
+    #[test]
+    fn keyboard() {
+        let mut bus = DeviceBus::new();
+        assert_eq!(bus.read(KEYBOARD_STATUS_REGISTER), Some(0));
+
+        bus.keyboard.press(b'a');
+        assert_eq!(bus.peek(KEYBOARD_STATUS_REGISTER), Some(READY_BIT));
+        assert_eq!(bus.peek(KEYBOARD_DATA_REGISTER), Some(b'a'.into()));
+        assert!(bus.keyboard.is_ready());
+
+        // Only a load clears the ready bit
+        assert_eq!(bus.read(KEYBOARD_DATA_REGISTER), Some(b'a'.into()));
+        assert_eq!(bus.read(KEYBOARD_STATUS_REGISTER), Some(0));
+
+        assert!(bus.write(KEYBOARD_STATUS_REGISTER, 0xFFFF));
+        assert!(bus.keyboard.interrupt_enabled());
+        assert_eq!(
+            bus.peek(KEYBOARD_STATUS_REGISTER),
+            Some(INTERRUPT_ENABLE_BIT)
+        );
+    }
+
+    #[test]
+    fn interrupts() {
+        let mut bus = DeviceBus::new();
+        bus.keyboard.press(b'a');
+        assert_eq!(bus.pending_interrupt(), None);
+
+        bus.write(KEYBOARD_STATUS_REGISTER, INTERRUPT_ENABLE_BIT);
+        bus.write(DISPLAY_STATUS_REGISTER, INTERRUPT_ENABLE_BIT);
+        let keyboard = Interrupt {
+            vector: KEYBOARD_INTERRUPT,
+            priority: IO_PRIORITY,
+        };
+        assert_eq!(bus.pending_interrupt(), Some(keyboard));
+
+        // Higher priority devices win
+        let urgent = Interrupt {
+            vector: 0x0090,
+            priority: 6,
+        };
+        bus.map(0xFE20..=0xFE20, Alarm(urgent)).unwrap();
+        assert_eq!(bus.pending_interrupt(), Some(urgent));
+
+        // Servicing the key withdraws its request
+        let mut bus = DeviceBus::new();
+        bus.keyboard.press(b'a');
+        bus.write(KEYBOARD_STATUS_REGISTER, INTERRUPT_ENABLE_BIT);
+        bus.read(KEYBOARD_DATA_REGISTER);
+        assert_eq!(bus.pending_interrupt(), None);
+    }
+
+    #[test]
+    fn display() {
+        let mut bus = DeviceBus::new();
+        assert_eq!(bus.read(DISPLAY_STATUS_REGISTER), Some(READY_BIT));
+
+        assert!(bus.write(DISPLAY_DATA_REGISTER, 0x0168));
+        assert!(bus.write(DISPLAY_DATA_REGISTER, b'i'.into()));
+        assert_eq!(bus.display.output(), b"hi");
+        assert_eq!(bus.display.take_output(), b"hi");
+        assert!(bus.display.output().is_empty());
+    }
+
+    #[test]
+    fn custom_devices() {
+        let mut bus = DeviceBus::new();
+        bus.map(0xFE10..=0xFE11, Counter(5)).unwrap();
+
+        assert!(!bus.is_mapped(0xFE0F));
+        assert_eq!(bus.peek(0xFE11), Some(5));
+        assert_eq!(bus.read(0xFE10), Some(6));
+        assert!(bus.write(0xFE11, 1));
+        assert_eq!(bus.peek(0xFE10), Some(1));
+        assert!(!bus.write(0xFE12, 1));
+
+        // Clones are independent
+        let mut clone = bus.clone();
+        clone.read(0xFE10);
+        assert_eq!(bus.peek(0xFE10), Some(1));
+        assert_eq!(clone.peek(0xFE10), Some(2));
+
+        assert_eq!(
+            bus.map(0x3000..=0x3001, Counter(0)),
+            Err(MapError::OutsideDeviceSpace(0x3000..=0x3001))
+        );
+        assert_eq!(
+            bus.map(0xFE11..=0xFE12, Counter(0)),
+            Err(MapError::Overlaps(0xFE11..=0xFE12))
+        );
+        assert_eq!(
+            bus.map(0xFFF0..=0xFFFF, Counter(0)),
+            Err(MapError::Overlaps(0xFFF0..=0xFFFF))
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! Turns machine code back into assembly listings and source.
+
+use std::fmt;
+
+use crate::{
+    assembler::SymbolTable,
+    defs::{LC3MemAddr, LC3Word},
+    instruction::{IBranch, Instruction, InstructionEnum},
+};
+
+/// Width of the label column in listings and generated source.
+const LABEL_WIDTH: usize = 12;
+
+/// One word of a disassembly listing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisasmLine {
+    pub addr: LC3MemAddr,
+    pub word: LC3Word,
+    /// First label defined at [`Self::addr`], if any.
+    pub label: Option<String>,
+    /// Assembly text for [`Self::word`].
+    pub text: String,
+}
+
+/// Listing format, e.g. `x3000  x1261  LOOP        ADD R1, R1, #1`.
+impl fmt::Display for DisasmLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "x{:04X}  x{:04X}  {:<LABEL_WIDTH$}{}",
+            self.addr,
+            self.word,
+            self.label.as_deref().unwrap_or_default(),
+            self.text
+        )
+    }
+}
+
+/// Assembly text for `word`, located at `addr`.
+///
+/// PC-relative operands are shown as the target's label from `symbols`, if
+/// any, otherwise as the target address. Words that are not instructions fall
+/// back to `.FILL`, as do branches that are never taken, since those are
+/// almost always data.
+pub fn disassemble_word(word: LC3Word, addr: LC3MemAddr, symbols: Option<&SymbolTable>) -> String {
+    match InstructionEnum::parse(word) {
+        Some(InstructionEnum::IBranch(IBranch { cond_codes, .. }))
+            if !(cond_codes.negative || cond_codes.zero || cond_codes.positive) =>
+        {
+            fill(word)
+        }
+        Some(instr) => instr.display_at(addr, symbols).to_string(),
+        None => fill(word),
+    }
+}
+
+fn fill(word: LC3Word) -> String {
+    format!(".FILL x{word:04X}")
+}
+
+/// Disassemble each of `words`, starting at `origin`.
+pub fn disassemble<I: IntoIterator<Item = LC3Word>>(
+    origin: LC3MemAddr,
+    words: I,
+    symbols: Option<&SymbolTable>,
+) -> Vec<DisasmLine> {
+    words
+        .into_iter()
+        .zip(origin..=LC3MemAddr::MAX)
+        .map(|(word, addr)| DisasmLine {
+            addr,
+            word,
+            label: symbols.and_then(|symbols| symbols.label(addr).map(str::to_string)),
+            text: disassemble_word(word, addr, symbols),
+        })
+        .collect()
+}
+
+/// Assembly source for `words`, starting at `origin`.
+///
+/// The source is wrapped in `.ORIG` and `.END`. It reassembles to `words` as
+/// long as every PC-relative target has a label in `symbols`.
+pub fn disassemble_source<I: IntoIterator<Item = LC3Word>>(
+    origin: LC3MemAddr,
+    words: I,
+    symbols: Option<&SymbolTable>,
+) -> String {
+    let mut source = format!("{:LABEL_WIDTH$}.ORIG x{origin:04X}\n", "");
+    for line in disassemble(origin, words, symbols) {
+        let label = line.label.unwrap_or_default();
+        source += &format!("{label:<LABEL_WIDTH$}{}\n", line.text);
+    }
+    source + &format!("{:LABEL_WIDTH$}.END\n", "")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::assembler::assemble;
+
+    #[test]
+    fn disassemble_words() {
+        let symbols: SymbolTable = [("LOOP", 0x3000)].into_iter().collect();
+
+        // BRnz to 0x3000, from 0x3002
+        assert_eq!(disassemble_word(0x0DFD, 0x3002, None), "BRnz x3000");
+        assert_eq!(
+            disassemble_word(0x0DFD, 0x3002, Some(&symbols)),
+            "BRnz LOOP"
+        );
+        assert_eq!(disassemble_word(0x1025, 0x3002, None), "ADD R0, R0, #5");
+
+        // Not instructions
+        assert_eq!(disassemble_word(0x0000, 0x3000, None), ".FILL x0000");
+        assert_eq!(disassemble_word(0x0061, 0x3000, None), ".FILL x0061");
+        assert_eq!(disassemble_word(0xD000, 0x3000, None), ".FILL xD000");
+        assert_eq!(disassemble_word(0xF1FF, 0x3000, None), ".FILL xF1FF");
+
+        // Every TRAP vector
+        assert_eq!(disassemble_word(0xF0FF, 0x3000, None), "TRAP xFF");
+    }
+
+    #[test]
+    fn listing() {
+        let symbols: SymbolTable = [("START", 0x3000)].into_iter().collect();
+        let lines = disassemble(0x3000, [0x5260, 0xF025], Some(&symbols));
+
+        assert_eq!(
+            lines.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                "x3000  x5260  START       AND R1, R1, #0",
+                "x3001  xF025              HALT",
+            ]
+        );
+    }
+
+    #[test]
+    fn source_round_trip() {
+        let source = r#"
+                    .ORIG   x3000
+            START   LEA     R0, TEXT
+                    PUTS
+                    AND     R1, R1, #0
+            LOOP    ADD     R1, R1, #-1
+                    BRzp    LOOP
+                    LD      R2, DATA
+                    STI     R2, PTR
+                    JSR     SUB
+                    HALT
+            SUB     LDR     R3, R2, #-4
+                    RET
+            DATA    .FILL   #-1
+            PTR     .FILL   xFE06
+            TEXT    .STRINGZ "Hi"
+                    .END
+            "#;
+        let assembled = assemble(source).unwrap();
+        let block = &assembled.blocks[0];
+
+        let disassembled =
+            disassemble_source(block.origin, block.words.clone(), Some(&assembled.symbols));
+        let reassembled = assemble(&disassembled).unwrap();
+
+        assert_eq!(reassembled.blocks, assembled.blocks);
+        assert!(disassembled.contains("            BRzp LOOP\n"));
+    }
+}
@@ -5,10 +5,12 @@ use crate::{
         LC3MemAddr, LC3Word, RegAddr, ADDR_SPACE_SIZE, MACHINE_CONTROL_REGISTER, NUM_REGS,
         OS_SUPER_STACK, STACK_REG, SUPERVISOR_SP_INIT,
     },
-    instruction::{Instruction, InstructionEnum},
+    devices::DeviceBus,
+    instruction::{Instruction, InstructionEnum, Trap},
+    os::service_trap,
 };
 
-use super::{LC3MemLoc, StepFailure, LC3};
+use super::{loads_keyboard, Change, LC3MemLoc, StepFailure, LC3};
 
 #[derive(Debug, Clone, Copy)]
 struct ConditionReg {
@@ -28,15 +30,23 @@ pub struct CoreLC3 {
     pc: LC3MemAddr,
     halted: bool,
     mpr_disabled: bool,
+    strict: bool,
+    native_os: bool,
+    /// Address of the native IN that has written its prompt.
+    prompted: Option<LC3MemAddr>,
+    /// Changes not yet taken, while recording.
+    changes: Option<Vec<Change>>,
+    devices: DeviceBus,
 }
 
 impl CoreLC3 {
     pub fn new() -> Self {
         Self {
             mem: Box::new([0; ADDR_SPACE_SIZE]),
+            // Z, like PennSim, so an unconditional branch is always taken
             conds: ConditionReg {
                 negative: false,
-                zero: false,
+                zero: true,
                 positive: false,
             },
             priority: 0,
@@ -46,8 +56,105 @@ impl CoreLC3 {
             pc: OS_SUPER_STACK,
             halted: false,
             mpr_disabled: false,
+            strict: false,
+            native_os: false,
+            prompted: None,
+            changes: None,
+            devices: DeviceBus::new(),
+        }
+    }
+}
+
+impl CoreLC3 {
+    /// True if failures are returned instead of entering exceptions.
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Sets whether [`LC3::step`] returns failures that have an
+    /// [`StepFailure::exception_vector`] instead of entering the exception.
+    ///
+    /// Strict mode suits programs run without an OS to handle exceptions.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict
+    }
+
+    /// True if the standard TRAPs are serviced natively.
+    pub fn is_native_os(&self) -> bool {
+        self.native_os
+    }
+
+    /// Sets whether the standard TRAPs are serviced natively, instead of
+    /// through the trap vector table.
+    ///
+    /// Native TRAPs act like the [`LC3OS`] routines, in a single step and
+    /// without leaving user mode. GETC and IN repeat until a key arrives.
+    /// Other TRAPs still go through the table.
+    ///
+    /// [`LC3OS`]: crate::os::LC3OS
+    pub fn set_native_os(&mut self, native_os: bool) {
+        self.native_os = native_os;
+        self.prompted = None;
+    }
+
+    /// Executes the current instruction, without exceptions or interrupts.
+    fn execute_current(&mut self) -> Result<(), StepFailure> {
+        if let Some(addr) = self.access_violation() {
+            return Err(StepFailure::AccessViolation(addr));
+        }
+
+        let inst = self
+            .cur_inst()
+            .ok_or(StepFailure::InvalidInstruction(self.mem(self.pc())))?;
+
+        if let (true, InstructionEnum::Trap(trap)) = (self.native_os, inst) {
+            let mut prompted = self.prompted;
+            let serviced = service_trap(self, trap, &mut prompted);
+            self.prompted = prompted;
+            if serviced {
+                return Ok(());
+            }
+        }
+
+        inst.execute(self)?;
+
+        if !matches!(
+            inst,
+            InstructionEnum::IBranch(_)
+                | InstructionEnum::IJump(_)
+                | InstructionEnum::IJumpSubRoutine(_)
+                | InstructionEnum::Trap(_)
+        ) {
+            self.set_pc(self.pc + 1);
+        }
+
+        Ok(())
+    }
+
+    fn record(&mut self, change: Change) {
+        let Some(changes) = &mut self.changes else {
+            return;
+        };
+
+        // Merge the parts of a PSR update into one change
+        if let (Change::Psr { new, .. }, Some(Change::Psr { new: last, .. })) =
+            (change, changes.last_mut())
+        {
+            *last = new;
+        } else {
+            changes.push(change);
         }
     }
+
+    /// Makes a change to the PSR with `update`, recording it.
+    fn update_psr(&mut self, update: impl FnOnce(&mut Self)) {
+        let old = self.processor_status_reg();
+        update(self);
+        self.record(Change::Psr {
+            old,
+            new: self.processor_status_reg(),
+        });
+    }
 }
 
 impl Default for CoreLC3 {
@@ -61,6 +168,10 @@ impl LC3 for CoreLC3 {
         self.pc
     }
     fn set_pc(&mut self, pc: LC3MemAddr) {
+        self.record(Change::Pc {
+            old: self.pc,
+            new: pc,
+        });
         self.pc = pc
     }
 
@@ -74,6 +185,11 @@ impl LC3 for CoreLC3 {
         }
     }
     fn set_reg(&mut self, addr: RegAddr, value: LC3Word) {
+        self.record(Change::Reg {
+            reg: addr,
+            old: self.reg(addr),
+            new: value,
+        });
         let reg_addr = usize::from(addr);
 
         if self.privileged && reg_addr == STACK_REG.into() {
@@ -84,21 +200,41 @@ impl LC3 for CoreLC3 {
     }
 
     fn mem(&self, addr: LC3MemAddr) -> LC3Word {
-        self.mem[addr as usize]
+        self.devices.peek(addr).unwrap_or(self.mem[addr as usize])
+    }
+    fn load(&mut self, addr: LC3MemAddr) -> LC3Word {
+        self.devices.read(addr).unwrap_or(self.mem[addr as usize])
     }
     fn set_mem(&mut self, addr: LC3MemAddr, value: LC3Word) {
+        if self.devices.write(addr, value) {
+            self.record(Change::Device { addr, value });
+            return;
+        }
+
+        self.record(Change::Mem {
+            addr,
+            old: self.mem[addr as usize],
+            new: value,
+        });
         self.mem[addr as usize] = value;
         if addr == MACHINE_CONTROL_REGISTER {
             self.mpr_disabled = (value & (1 << 15)) == 0;
         }
     }
 
+    fn devices(&self) -> &DeviceBus {
+        &self.devices
+    }
+    fn devices_mut(&mut self) -> &mut DeviceBus {
+        &mut self.devices
+    }
+
     fn priority(&self) -> u8 {
         self.priority
     }
     fn set_priority(&mut self, priority: u8) {
         if priority < 8 {
-            self.priority = priority
+            self.update_psr(|lc3| lc3.priority = priority)
         }
     }
 
@@ -106,7 +242,19 @@ impl LC3 for CoreLC3 {
         self.privileged
     }
     fn set_privileged(&mut self, priviledged: bool) {
-        self.privileged = priviledged
+        self.update_psr(|lc3| lc3.privileged = priviledged)
+    }
+
+    fn saved_stack_reg(&self) -> LC3Word {
+        if self.privileged {
+            self.regs[usize::from(STACK_REG)]
+        } else {
+            self.supervisor_sp
+        }
+    }
+
+    fn clock_enabled(&self) -> bool {
+        !self.mpr_disabled
     }
 
     fn positive_cond(&self) -> bool {
@@ -120,33 +268,41 @@ impl LC3 for CoreLC3 {
     }
 
     fn flag_positive(&mut self) {
-        self.conds = ConditionReg {
-            negative: false,
-            zero: false,
-            positive: true,
-        }
+        self.update_psr(|lc3| {
+            lc3.conds = ConditionReg {
+                negative: false,
+                zero: false,
+                positive: true,
+            }
+        })
     }
     fn flag_zero(&mut self) {
-        self.conds = ConditionReg {
-            negative: false,
-            zero: true,
-            positive: false,
-        }
+        self.update_psr(|lc3| {
+            lc3.conds = ConditionReg {
+                negative: false,
+                zero: true,
+                positive: false,
+            }
+        })
     }
     fn flag_negative(&mut self) {
-        self.conds = ConditionReg {
-            negative: true,
-            zero: false,
-            positive: false,
-        }
+        self.update_psr(|lc3| {
+            lc3.conds = ConditionReg {
+                negative: true,
+                zero: false,
+                positive: false,
+            }
+        })
     }
 
     fn clear_flags(&mut self) {
-        self.conds = ConditionReg {
-            negative: false,
-            zero: false,
-            positive: false,
-        }
+        self.update_psr(|lc3| {
+            lc3.conds = ConditionReg {
+                negative: false,
+                zero: false,
+                positive: false,
+            }
+        })
     }
 
     type FullIter<'a> = std::iter::Cloned<std::slice::Iter<'a, LC3Word>>;
@@ -160,10 +316,18 @@ impl LC3 for CoreLC3 {
     }
 
     fn halt(&mut self) {
+        self.record(Change::Halted {
+            old: self.halted,
+            new: true,
+        });
         self.halted = true;
     }
 
     fn unhalt(&mut self) {
+        self.record(Change::Halted {
+            old: self.halted,
+            new: false,
+        });
         self.halted = false;
     }
 
@@ -171,7 +335,19 @@ impl LC3 for CoreLC3 {
         self.halted
     }
 
-    /// Executes the current instruction.
+    /// Also true for a native GETC or IN waiting on a key.
+    fn reads_keyboard(&self) -> bool {
+        match self.cur_inst() {
+            Some(InstructionEnum::Trap(Trap::Getc | Trap::In)) if self.native_os => true,
+            _ => loads_keyboard(self),
+        }
+    }
+
+    /// Executes the current instruction, then initiates any pending device
+    /// interrupt that outranks the new priority.
+    ///
+    /// Failures with an exception vector enter the exception, unless
+    /// [`Self::is_strict`].
     ///
     /// Does not handle memory map updates.
     fn step(&mut self) -> Result<(), StepFailure> {
@@ -180,22 +356,35 @@ impl LC3 for CoreLC3 {
         } else if self.mpr_disabled {
             Err(StepFailure::ClockDisabled)
         } else {
-            let inst = self
-                .cur_inst()
-                .ok_or(StepFailure::InvalidInstruction(self.mem(self.pc())))?;
-
-            inst.execute(self)?;
+            match self.execute_current() {
+                Err(failure) if !self.strict => {
+                    let vector = failure.exception_vector().ok_or(failure)?;
+                    // The saved PC is past the faulting instruction
+                    self.set_pc(self.pc.wrapping_add(1));
+                    self.interrupt(vector, None);
+                }
+                result => result?,
+            }
 
-            if !matches!(inst, InstructionEnum::IBranch(_))
-                && !matches!(inst, InstructionEnum::IJump(_))
-            {
-                self.pc += 1;
+            if !self.halted {
+                self.check_interrupts();
             }
 
             Ok(())
         }
     }
 
+    fn set_recording(&mut self, recording: bool) {
+        self.changes = recording.then(Vec::new);
+    }
+
+    fn take_changes(&mut self) -> Vec<Change> {
+        self.changes
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
     fn populate<I: IntoIterator<Item = LC3Word>>(&mut self, start: LC3MemAddr, words: I) {
         let mem_iter_mut = self.mem[start.into()..].iter_mut();
         for (word, loc) in words.into_iter().zip(mem_iter_mut) {
@@ -251,12 +440,201 @@ impl DoubleEndedIterator for CoreLC3SparseIter<'_> {
 
 impl FusedIterator for CoreLC3SparseIter<'_> {}
 
+/// Test fixture: a new processor with `source` assembled into it, and the PC
+/// at [`USER_SPACE`].
+///
+/// `native_os` services the standard TRAPs natively.
+///
+/// [`USER_SPACE`]: crate::defs::USER_SPACE
+#[cfg(test)]
+pub(crate) fn load_program(source: &str, native_os: bool) -> CoreLC3 {
+    let mut lc3 = CoreLC3::new();
+    lc3.set_native_os(native_os);
+    crate::assembler::assemble(source)
+        .unwrap()
+        .populate(&mut lc3);
+    lc3.set_pc(crate::defs::USER_SPACE);
+    lc3
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::{defs::RegAddr, instruction::*};
+    use crate::{
+        defs::{RegAddr, IR_VEC_TBL},
+        instruction::*,
+    };
+
+    #[test]
+    fn recording() {
+        let mut processor = CoreLC3::new();
+        processor.populate(IR_VEC_TBL, [0x1000]);
+        processor.set_pc(0x3000);
+        processor.set_privileged(false);
+        processor.set_mem(0x3000, IJump::InterRet.into());
+        let before = processor.clone();
+
+        processor.set_recording(true);
+        processor.step().unwrap();
+        let changes = processor.take_changes();
+
+        // Entering the exception, with the PSR updates merged
+        let stack = SUPERVISOR_SP_INIT - 2;
+        assert_eq!(
+            changes,
+            [
+                Change::Pc {
+                    old: 0x3000,
+                    new: 0x3001
+                },
+                Change::Psr {
+                    old: 0x8002,
+                    new: 0x0002
+                },
+                Change::Mem {
+                    addr: stack + 1,
+                    old: 0,
+                    new: 0x8002
+                },
+                Change::Mem {
+                    addr: stack,
+                    old: 0,
+                    new: 0x3001
+                },
+                Change::Reg {
+                    reg: RegAddr::Six,
+                    old: SUPERVISOR_SP_INIT,
+                    new: stack
+                },
+                Change::Pc {
+                    old: 0x3001,
+                    new: 0x1000
+                },
+            ]
+        );
+        assert!(processor.take_changes().is_empty());
+
+        for change in changes.iter().rev() {
+            change.revert(&mut processor);
+        }
+        assert_eq!(processor.pc(), before.pc());
+        assert_eq!(
+            processor.processor_status_reg(),
+            before.processor_status_reg()
+        );
+        assert!(processor.iter().eq(before.iter()));
+        assert_eq!(processor.reg(RegAddr::Six), before.reg(RegAddr::Six));
+
+        // Stopping drops untaken changes
+        processor.set_pc(0x3000);
+        processor.set_recording(false);
+        assert!(processor.take_changes().is_empty());
+    }
 
     //TODO: rewrite all of these in a more intelligent fashion
+    #[test]
+    fn exceptions() {
+        const HANDLERS: [LC3Word; 2] = [0x1000, 0x1100];
+
+        let mut processor = CoreLC3::new();
+        processor.populate(IR_VEC_TBL, HANDLERS);
+        processor.set_pc(0x3000);
+        processor.set_privileged(false);
+        processor.flag_zero();
+
+        // RTI in user mode is a privilege mode violation
+        processor.set_mem(0x3000, IJump::InterRet.into());
+        processor.step().unwrap();
+        assert_eq!(processor.pc(), HANDLERS[0]);
+        assert!(processor.privileged());
+
+        // The saved PC and PSR are on the supervisor stack
+        let stack = processor.reg(STACK_REG);
+        assert_eq!(processor.mem(stack), 0x3001);
+        assert_eq!(processor.mem(stack + 1), 0x8002);
+
+        // The reserved opcode is illegal
+        processor.set_mem(HANDLERS[0], 0xD000);
+        processor.step().unwrap();
+        assert_eq!(processor.pc(), HANDLERS[1]);
+
+        // Other invalid words cannot be executed at all
+        processor.set_mem(HANDLERS[1], 0xC001 | (1 << 9));
+        assert_eq!(
+            processor.step(),
+            Err(StepFailure::InvalidInstruction(0xC201))
+        );
+    }
+
+    #[test]
+    fn access_control() {
+        let mut processor = CoreLC3::new();
+        processor.set_strict(true);
+        processor.set_privileged(false);
+
+        // Fetching outside user space
+        processor.set_pc(0x0200);
+        assert_eq!(processor.step(), Err(StepFailure::AccessViolation(0x0200)));
+
+        // Loading the trap vector table
+        processor.set_pc(0x3000);
+        processor.set_reg(RegAddr::One, 0x0025);
+        let ldr = ILoad::parse(0x6040).unwrap();
+        processor.set_mem(0x3000, ldr.into());
+        assert_eq!(processor.step(), Err(StepFailure::AccessViolation(0x0025)));
+
+        // Storing through a pointer to a device register
+        processor.set_mem(0x3000, 0xB000);
+        processor.set_mem(0x3001, 0xFE06);
+        assert_eq!(processor.step(), Err(StepFailure::AccessViolation(0xFE06)));
+        assert_eq!(processor.devices().display.output(), b"");
+
+        // Supervisor mode can access everything
+        processor.set_privileged(true);
+        processor.step().unwrap();
+        assert_eq!(processor.devices().display.output(), b"\0");
+
+        // Outside strict mode, violations enter the exception
+        processor.set_strict(false);
+        processor.set_privileged(false);
+        processor.set_mem(IR_VEC_TBL + 2, 0x1200);
+        processor.set_pc(0x3000);
+        processor.step().unwrap();
+        assert_eq!(processor.pc(), 0x1200);
+        assert!(processor.privileged());
+    }
+
+    #[test]
+    fn native_os_keeps_privilege() {
+        let mut processor = CoreLC3::new();
+        processor.set_strict(true);
+        processor.set_native_os(true);
+        assert!(processor.privileged());
+
+        // OUT in user mode, then a store through a pointer to the trap
+        // vector table
+        processor.populate(0x3000, [0xF021, 0xB000, 0x0010]);
+        processor.set_pc(0x3000);
+        processor.set_privileged(false);
+        processor.step().unwrap();
+        assert!(!processor.privileged());
+        assert_eq!(processor.step(), Err(StepFailure::AccessViolation(0x0010)));
+    }
+
+    #[test]
+    fn strict_exceptions() {
+        let mut processor = CoreLC3::new();
+        processor.set_strict(true);
+        processor.set_pc(0x3000);
+        processor.set_mem(0x3000, 0xD000);
+
+        assert_eq!(
+            processor.step(),
+            Err(StepFailure::InvalidInstruction(0xD000))
+        );
+        assert_eq!(processor.pc(), 0x3000);
+    }
+
     #[test]
     fn instr_add_imm() {
         let mut processor = CoreLC3 {
@@ -273,6 +651,11 @@ mod test {
             pc: 0x0000,
             halted: false,
             mpr_disabled: false,
+            strict: false,
+            native_os: false,
+            prompted: None,
+            changes: None,
+            devices: DeviceBus::new(),
         };
 
         let test_instr = IAdd::Imm(InstrRegSignedImm {
@@ -300,6 +683,11 @@ mod test {
             pc: 0x0000,
             halted: false,
             mpr_disabled: false,
+            strict: false,
+            native_os: false,
+            prompted: None,
+            changes: None,
+            devices: DeviceBus::new(),
         };
 
         let test_instr = IAdd::Reg(InstrRegReg {
@@ -327,6 +715,11 @@ mod test {
             pc: 0x0000,
             halted: false,
             mpr_disabled: false,
+            strict: false,
+            native_os: false,
+            prompted: None,
+            changes: None,
+            devices: DeviceBus::new(),
         };
 
         let test_instr = IAnd::Imm(InstrRegImm {
@@ -354,6 +747,11 @@ mod test {
             pc: 0x0000,
             halted: false,
             mpr_disabled: false,
+            strict: false,
+            native_os: false,
+            prompted: None,
+            changes: None,
+            devices: DeviceBus::new(),
         };
 
         let test_instr = IAnd::Reg(InstrRegReg {
@@ -390,6 +788,11 @@ mod test {
             pc: 0x0000,
             halted: false,
             mpr_disabled: false,
+            strict: false,
+            native_os: false,
+            prompted: None,
+            changes: None,
+            devices: DeviceBus::new(),
         };
 
         let test_instr = INot(InstrRegOnly {
@@ -416,6 +819,11 @@ mod test {
             pc: 0x0000,
             halted: false,
             mpr_disabled: false,
+            strict: false,
+            native_os: false,
+            prompted: None,
+            changes: None,
+            devices: DeviceBus::new(),
         };
 
         //there are more clever ways to write this, I don't feel like writing them
@@ -557,6 +965,11 @@ mod test {
             pc: 0x0000,
             halted: false,
             mpr_disabled: false,
+            strict: false,
+            native_os: false,
+            prompted: None,
+            changes: None,
+            devices: DeviceBus::new(),
         };
 
         for i in 0..8 {
@@ -589,13 +1002,18 @@ mod test {
             pc: 0x3000,
             halted: false,
             mpr_disabled: false,
+            strict: false,
+            native_os: false,
+            prompted: None,
+            changes: None,
+            devices: DeviceBus::new(),
         };
 
         // JSR
         let test_instr = IJumpSubRoutine::Offset(InstrPCOffset11 { pc_offset: 0x0006 });
         test_instr.execute(&mut processor).unwrap();
-        assert_eq!(processor.pc, 0x3006);
-        assert_eq!(processor.regs[7], 0x3000);
+        assert_eq!(processor.pc, 0x3007);
+        assert_eq!(processor.regs[7], 0x3001);
 
         processor.pc = 0x3000;
         processor.regs[1] = 0x000A;
@@ -605,7 +1023,7 @@ mod test {
         let test_instr = IJumpSubRoutine::Reg(RegAddr::One);
         test_instr.execute(&mut processor).unwrap();
         assert_eq!(processor.pc, 0x000A);
-        assert_eq!(processor.regs[7], 0x3000);
+        assert_eq!(processor.regs[7], 0x3001);
     }
 
     #[test]
@@ -626,6 +1044,11 @@ mod test {
             pc: 0x3000,
             halted: false,
             mpr_disabled: false,
+            strict: false,
+            native_os: false,
+            prompted: None,
+            changes: None,
+            devices: DeviceBus::new(),
         };
         let test_instr: ILoad = ILoad::Std(InstrPCOffset9 {
             target_reg: const { RegAddr::panic_from_u8(0) },
@@ -685,6 +1108,11 @@ mod test {
             pc: 0x3000,
             halted: false,
             mpr_disabled: false,
+            strict: false,
+            native_os: false,
+            prompted: None,
+            changes: None,
+            devices: DeviceBus::new(),
         };
 
         // ST
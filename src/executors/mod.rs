@@ -1,14 +1,25 @@
-use std::io::Read;
+use std::io::{BufReader, Read};
 
 use thiserror::Error;
 
 use crate::{
-    defs::{LC3MemAddr, LC3Word, RegAddr, STACK_REG},
-    instruction::{Instruction, InstructionEnum, InstructionErr, InsufficientPerms},
+    defs::{
+        LC3MemAddr, LC3Word, RegAddr, ACCESS_CONTROL_EXCEPTION, DEV_REG_ADDR,
+        ILLEGAL_OPCODE_EXCEPTION, IR_VEC_TBL, KEYBOARD_DATA_REGISTER, KEYBOARD_STATUS_REGISTER,
+        PRIVILEGE_EXCEPTION, STACK_REG, USER_SPACE,
+    },
+    devices::{DeviceBus, Interrupt},
+    instruction::{
+        get_opcode, Instruction, InstructionEnum, InstructionErr, InsufficientPerms,
+        RESERVED_OPCODE,
+    },
     util::format_word_bits,
 };
 
 pub mod core;
+pub mod profile;
+pub mod reverse;
+pub mod state;
 
 /// LC3 Memory Address.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -17,10 +28,81 @@ pub struct LC3MemLoc {
     pub value: LC3Word,
 }
 
+/// A single change to the machine state, as recorded by
+/// [`LC3::set_recording`].
+///
+/// Each change holds the value before and after, so it can be replayed
+/// forwards or reverted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Change {
+    Pc {
+        old: LC3MemAddr,
+        new: LC3MemAddr,
+    },
+    /// Register write, to the stack pointer bank active at the time.
+    Reg {
+        reg: RegAddr,
+        old: LC3Word,
+        new: LC3Word,
+    },
+    /// Write to a memory word, including the MCR.
+    Mem {
+        addr: LC3MemAddr,
+        old: LC3Word,
+        new: LC3Word,
+    },
+    /// Write taken by a device, which keeps no value to revert.
+    Device {
+        addr: LC3MemAddr,
+        value: LC3Word,
+    },
+    /// Privilege, priority or condition code change, as whole PSRs.
+    Psr {
+        old: LC3Word,
+        new: LC3Word,
+    },
+    Halted {
+        old: bool,
+        new: bool,
+    },
+}
+
+impl Change {
+    /// Makes this change on `processor`. Device writes are not repeated.
+    pub fn apply<P: LC3 + ?Sized>(&self, processor: &mut P) {
+        self.set(processor, false)
+    }
+
+    /// Undoes this change on `processor`, which must have every later change
+    /// reverted already. Device writes are not undone.
+    pub fn revert<P: LC3 + ?Sized>(&self, processor: &mut P) {
+        self.set(processor, true)
+    }
+
+    fn set<P: LC3 + ?Sized>(&self, processor: &mut P, revert: bool) {
+        let pick = |old, new| if revert { old } else { new };
+        match *self {
+            Self::Pc { old, new } => processor.set_pc(pick(old, new)),
+            Self::Reg { reg, old, new } => processor.set_reg(reg, pick(old, new)),
+            Self::Mem { addr, old, new } => processor.set_mem(addr, pick(old, new)),
+            Self::Device { .. } => (),
+            Self::Psr { old, new } => processor.set_processor_status_reg(pick(old, new)),
+            Self::Halted { old, new } => {
+                let halted = if revert { old } else { new };
+                if halted {
+                    processor.halt()
+                } else {
+                    processor.unhalt()
+                }
+            }
+        }
+    }
+}
+
 /// Failure occured during a machine step.
 ///
-/// [`Self::InvalidInstruction`] and [`Self::InsufficientPerms`] enter an
-/// exception.
+/// Failures with an [`Self::exception_vector`] enter an exception instead,
+/// unless the executor is in a strict mode.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Error)]
 pub enum StepFailure {
     #[error(
@@ -31,12 +113,33 @@ pub enum StepFailure {
     InsufficientPerms(InsufficientPerms),
     #[error("{max_addr} is the largest possible LC3 address, PC cannot advance further.", max_addr = LC3MemAddr::MAX)]
     LastAddress,
+    #[error("User mode cannot access x{0:04X}")]
+    AccessViolation(LC3MemAddr),
     #[error("The machine must be unhalted to progress")]
     Halted,
     #[error("The MCR control bit was cleared, disabling the clock")]
     ClockDisabled,
 }
 
+impl StepFailure {
+    /// Exception this failure raises on the LC3, if any.
+    ///
+    /// RTI in user mode raises [`PRIVILEGE_EXCEPTION`], the reserved opcode
+    /// raises [`ILLEGAL_OPCODE_EXCEPTION`] and an access violation raises
+    /// [`ACCESS_CONTROL_EXCEPTION`]. Other invalid words have no
+    /// architectural meaning and always fail.
+    pub fn exception_vector(&self) -> Option<LC3Word> {
+        match self {
+            Self::InsufficientPerms(_) => Some(PRIVILEGE_EXCEPTION),
+            Self::AccessViolation(_) => Some(ACCESS_CONTROL_EXCEPTION),
+            Self::InvalidInstruction(word) if get_opcode(*word) == RESERVED_OPCODE => {
+                Some(ILLEGAL_OPCODE_EXCEPTION)
+            }
+            _ => None,
+        }
+    }
+}
+
 impl From<InstructionErr> for StepFailure {
     fn from(value: InstructionErr) -> Self {
         match value {
@@ -46,9 +149,9 @@ impl From<InstructionErr> for StepFailure {
 }
 
 // LC3 condition mask/shift consts
-const PRIV_MASK: LC3Word = 1 << 15;
+pub(crate) const PRIV_MASK: LC3Word = 1 << 15;
 const PRIORITY_SHIFT: LC3Word = 8;
-const PRIORITY_MASK: LC3Word = 111 << PRIORITY_SHIFT;
+const PRIORITY_MASK: LC3Word = 0b111 << PRIORITY_SHIFT;
 const NEGATIVE_MASK: LC3Word = 1 << 2;
 const ZERO_MASK: LC3Word = 1 << 1;
 const POSITIVE_MASK: LC3Word = 1;
@@ -63,9 +166,21 @@ pub trait LC3 {
     fn reg(&self, addr: RegAddr) -> LC3Word;
     fn set_reg(&mut self, addr: RegAddr, value: LC3Word);
 
+    /// Word at `addr`, without side effects.
     fn mem(&self, addr: LC3MemAddr) -> LC3Word;
+    /// Word at `addr`, as read by a load instruction.
+    ///
+    /// Unlike [`Self::mem`], this triggers device side effects, such as
+    /// reading KBDR clearing the keyboard's ready bit.
+    fn load(&mut self, addr: LC3MemAddr) -> LC3Word {
+        self.mem(addr)
+    }
     fn set_mem(&mut self, addr: LC3MemAddr, value: LC3Word);
 
+    /// Memory-mapped devices.
+    fn devices(&self) -> &DeviceBus;
+    fn devices_mut(&mut self) -> &mut DeviceBus;
+
     /// Current priority in [0, 7].
     ///
     /// 0 is the lowest priority, 7 is the highest.
@@ -78,6 +193,13 @@ pub trait LC3 {
     /// Sets to supervisor mode if true; to user mode if false.
     fn set_privileged(&mut self, priviledged: bool);
 
+    /// R6 of the inactive mode: the saved user stack pointer in supervisor
+    /// mode, or the saved supervisor stack pointer in user mode.
+    fn saved_stack_reg(&self) -> LC3Word;
+
+    /// False once a write to the MCR clears its clock enable bit.
+    fn clock_enabled(&self) -> bool;
+
     /// Returns the current processor status register value.
     fn processor_status_reg(&self) -> LC3Word {
         let privilege = if self.privileged() { 0 } else { PRIV_MASK };
@@ -104,7 +226,7 @@ pub trait LC3 {
         self.set_priority(((status_reg & PRIORITY_MASK) >> PRIORITY_SHIFT) as u8);
 
         if (status_reg & NEGATIVE_MASK) != 0 {
-            self.flag_positive();
+            self.flag_negative();
         } else if (status_reg & ZERO_MASK) != 0 {
             self.flag_zero();
         } else if (status_reg & POSITIVE_MASK) != 0 {
@@ -114,6 +236,39 @@ pub trait LC3 {
         }
     }
 
+    /// First address the instruction at [`Self::pc`] would access without
+    /// permission, starting with its own fetch.
+    ///
+    /// User mode may only access [`USER_SPACE`] up to [`DEV_REG_ADDR`].
+    /// Supervisor mode may access everything.
+    fn access_violation(&self) -> Option<LC3MemAddr> {
+        if self.privileged() {
+            return None;
+        }
+
+        let data = match self.cur_inst() {
+            Some(InstructionEnum::ILoad(load)) => [load.pointer_addr(self), load.source_addr(self)],
+            Some(InstructionEnum::IStore(store)) => {
+                [store.pointer_addr(self), Some(store.dest_addr(self))]
+            }
+            _ => [None, None],
+        };
+
+        [Some(self.pc())]
+            .into_iter()
+            .chain(data)
+            .flatten()
+            .find(|addr| !(USER_SPACE..DEV_REG_ADDR).contains(addr))
+    }
+
+    /// Whether the next step reads the keyboard.
+    ///
+    /// By default, whether the instruction at [`Self::pc`] loads from KBSR or
+    /// KBDR. Harnesses use this to supply input only when it is wanted.
+    fn reads_keyboard(&self) -> bool {
+        loads_keyboard(self)
+    }
+
     /// Return the instruction at [`Self::pc`], if any.
     fn cur_inst(&self) -> Option<InstructionEnum> {
         InstructionEnum::parse(self.mem(self.pc()))
@@ -158,8 +313,19 @@ pub trait LC3 {
     /// Processes the instruction at [`Self::pc`].
     fn step(&mut self) -> Result<(), StepFailure>;
 
+    /// Starts or stops recording every [`Change`] made through this trait.
+    ///
+    /// Loading with [`Self::populate`] is not recorded. Stopping discards
+    /// changes not yet taken.
+    fn set_recording(&mut self, recording: bool);
+    /// Takes the changes recorded since the last call, oldest first.
+    fn take_changes(&mut self) -> Vec<Change>;
+
     /// Initiates the interrupt service routine for `vector`.
     ///
+    /// Pushes the PSR then the PC onto the supervisor stack, and jumps to the
+    /// address at `vector` in the interrupt vector table.
+    ///
     /// `set_priority` is `Some` on I/O device interrupts, `None` on exceptions.
     fn interrupt(&mut self, vector: LC3Word, set_priority: Option<u8>) {
         let psr = self.processor_status_reg();
@@ -171,23 +337,48 @@ pub trait LC3 {
         }
 
         // PSR and PC stack pushes
-        let stack_reg = self.reg(STACK_REG);
-        self.set_mem(stack_reg, psr);
-        self.set_mem(stack_reg - 1, self.pc());
-        self.set_reg(STACK_REG, stack_reg - 2);
+        let stack_reg = self.reg(STACK_REG).wrapping_sub(2);
+        self.set_mem(stack_reg.wrapping_add(1), psr);
+        self.set_mem(stack_reg, self.pc());
+        self.set_reg(STACK_REG, stack_reg);
+
+        self.set_pc(self.mem(IR_VEC_TBL.wrapping_add(vector)));
+    }
 
-        self.set_pc(0x0100 + vector);
+    /// Initiates the highest priority pending device interrupt, if it
+    /// outranks [`Self::priority`].
+    ///
+    /// Returns true if an interrupt was initiated.
+    fn check_interrupts(&mut self) -> bool {
+        match self.devices().pending_interrupt() {
+            Some(Interrupt { vector, priority }) if priority > self.priority() => {
+                self.interrupt(vector, Some(priority));
+                true
+            }
+            _ => false,
+        }
     }
 
     /// Fill the lines from `start` with `words`.
     fn populate<I: IntoIterator<Item = LC3Word>>(&mut self, start: LC3MemAddr, words: I);
 }
 
+/// Whether the instruction at the PC loads from KBSR or KBDR.
+pub(crate) fn loads_keyboard<P: LC3 + ?Sized>(processor: &P) -> bool {
+    match processor.cur_inst() {
+        Some(InstructionEnum::ILoad(load)) => matches!(
+            load.source_addr(processor),
+            Some(KEYBOARD_STATUS_REGISTER | KEYBOARD_DATA_REGISTER)
+        ),
+        _ => false,
+    }
+}
+
 /// Populates the processor from a binary provider.
 ///
 /// Invalid binary data is silently discarded.
 pub fn populate_from_bin<P: LC3, R: Read>(processor: &mut P, bin: R) {
-    let mut bytes = bin.bytes();
+    let mut bytes = BufReader::new(bin).bytes();
 
     let mut next_pair = || {
         let first = bytes.next()?.ok()?;
@@ -0,0 +1,667 @@
+//! Execution profiling for any [`LC3`].
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Write},
+};
+
+use crate::{
+    assembler::SymbolTable,
+    debugger::Flow,
+    defs::{LC3MemAddr, LC3Word, RegAddr, ADDR_SPACE_SIZE},
+    devices::DeviceBus,
+    instruction::{InstructionClass, InstructionEnum},
+};
+
+use super::{Change, StepFailure, LC3};
+
+/// Rows in each table of [`Profile::write_text`].
+const TOP: usize = 10;
+
+/// Profiles every step of the wrapped processor.
+///
+/// Only steps are counted, not interrupts or changes made between them.
+#[derive(Debug, Clone)]
+pub struct Profiler<P> {
+    inner: P,
+    profile: Profile,
+}
+
+impl<P: LC3> Profiler<P> {
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            profile: Profile::default(),
+        }
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    /// Returns the wrapped processor and its profile.
+    pub fn into_inner(self) -> (P, Profile) {
+        (self.inner, self.profile)
+    }
+}
+
+/// Subroutine in the calling context tree: one entry point, reached by one
+/// chain of calls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Context {
+    entry: LC3MemAddr,
+    /// Index of the calling context, the root's own for the root.
+    parent: usize,
+    /// Index of each context called from this one, by entry point.
+    children: BTreeMap<LC3MemAddr, usize>,
+    calls: u64,
+    /// Instructions executed in this context, excluding its calls.
+    steps: u64,
+}
+
+/// Calls and time spent in one subroutine, over every chain of calls.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SubroutineStats {
+    pub calls: u64,
+    /// Instructions executed in the subroutine itself.
+    pub self_steps: u64,
+    /// Instructions executed in the subroutine and everything it called.
+    pub total_steps: u64,
+}
+
+/// What a [`Profiler`] has seen.
+///
+/// Calls are JSR, JSRR and TRAP instructions that leave the PC anywhere but
+/// the next instruction, so natively serviced TRAPs are not calls. RET and
+/// RTI return from the innermost call they land just after, and are ignored
+/// when they land after none. The first instruction profiled is the entry
+/// point of the root subroutine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    counts: Vec<u64>,
+    classes: BTreeMap<InstructionClass, u64>,
+    illegal: u64,
+    total: u64,
+    /// Calling context tree, the root first.
+    contexts: Vec<Context>,
+    current: usize,
+    /// Calling context and return address of every unfinished call,
+    /// outermost first.
+    stack: Vec<(usize, LC3MemAddr)>,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; ADDR_SPACE_SIZE],
+            classes: BTreeMap::new(),
+            illegal: 0,
+            total: 0,
+            contexts: Vec::new(),
+            current: 0,
+            stack: Vec::new(),
+        }
+    }
+}
+
+impl Profile {
+    /// Counts `inst`, executed at `addr`, which left the PC at `pc`.
+    fn record(&mut self, addr: LC3MemAddr, inst: Option<InstructionEnum>, pc: LC3MemAddr) {
+        if self.contexts.is_empty() {
+            self.contexts.push(Context {
+                entry: addr,
+                parent: 0,
+                children: BTreeMap::new(),
+                calls: 0,
+                steps: 0,
+            });
+        }
+
+        self.counts[usize::from(addr)] += 1;
+        self.total += 1;
+        match inst {
+            Some(inst) => *self.classes.entry(inst.into()).or_default() += 1,
+            None => self.illegal += 1,
+        }
+        self.contexts[self.current].steps += 1;
+
+        match Flow::of(inst, addr, pc) {
+            Flow::Call => {
+                let callee = self.callee(pc);
+                self.contexts[callee].calls += 1;
+                self.stack.push((self.current, addr.wrapping_add(1)));
+                self.current = callee;
+            }
+            Flow::Return => {
+                if let Some(depth) = self.stack.iter().rposition(|(_, ret)| *ret == pc) {
+                    self.current = self.stack[depth].0;
+                    self.stack.truncate(depth);
+                }
+            }
+            Flow::Other => {}
+        }
+    }
+
+    /// Context called at `entry` from the current one, added if new.
+    fn callee(&mut self, entry: LC3MemAddr) -> usize {
+        if let Some(&idx) = self.contexts[self.current].children.get(&entry) {
+            return idx;
+        }
+        let idx = self.contexts.len();
+        self.contexts.push(Context {
+            entry,
+            parent: self.current,
+            children: BTreeMap::new(),
+            calls: 0,
+            steps: 0,
+        });
+        self.contexts[self.current].children.insert(entry, idx);
+        idx
+    }
+
+    /// Instructions executed.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Times the instruction at `addr` executed.
+    pub fn count(&self, addr: LC3MemAddr) -> u64 {
+        self.counts[usize::from(addr)]
+    }
+
+    /// Every executed address and its count, most executed first.
+    pub fn hot_spots(&self) -> Vec<(LC3MemAddr, u64)> {
+        let mut spots: Vec<_> = (0..=LC3MemAddr::MAX)
+            .map(|addr| (addr, self.count(addr)))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        spots.sort_by(|(a_addr, a), (b_addr, b)| b.cmp(a).then(a_addr.cmp(b_addr)));
+        spots
+    }
+
+    /// Instructions executed in each class.
+    pub fn classes(&self) -> &BTreeMap<InstructionClass, u64> {
+        &self.classes
+    }
+
+    /// Words executed that are not instructions, raising exceptions.
+    pub fn illegal(&self) -> u64 {
+        self.illegal
+    }
+
+    /// Number of calls from each caller's entry point to each callee's.
+    pub fn call_graph(&self) -> BTreeMap<(LC3MemAddr, LC3MemAddr), u64> {
+        let mut edges = BTreeMap::new();
+        for context in self.contexts.iter().skip(1) {
+            let caller = self.contexts[context.parent].entry;
+            *edges.entry((caller, context.entry)).or_default() += context.calls;
+        }
+        edges
+    }
+
+    /// Statistics of every subroutine, by entry point.
+    ///
+    /// Recursive calls count towards the total only once.
+    pub fn subroutines(&self) -> BTreeMap<LC3MemAddr, SubroutineStats> {
+        let mut stats: BTreeMap<LC3MemAddr, SubroutineStats> = BTreeMap::new();
+        let inclusive = self.inclusive_steps();
+        for (idx, context) in self.contexts.iter().enumerate() {
+            let entry = stats.entry(context.entry).or_default();
+            entry.calls += context.calls;
+            entry.self_steps += context.steps;
+            if !self.ancestors(idx).any(|up| up.entry == context.entry) {
+                entry.total_steps += inclusive[idx];
+            }
+        }
+        stats
+    }
+
+    /// Entry points of every chain of calls that executed instructions, from
+    /// the root, with the instructions executed at its end.
+    pub fn stacks(&self) -> Vec<(Vec<LC3MemAddr>, u64)> {
+        self.contexts
+            .iter()
+            .enumerate()
+            .filter(|(_, context)| context.steps > 0)
+            .map(|(idx, context)| {
+                let mut stack: Vec<_> = self.ancestors(idx).map(|up| up.entry).collect();
+                stack.reverse();
+                stack.push(context.entry);
+                (stack, context.steps)
+            })
+            .collect()
+    }
+
+    /// Writes a summary of the hottest addresses, instruction classes and
+    /// subroutines, and the call graph.
+    pub fn write_text<W: Write>(
+        &self,
+        mut out: W,
+        symbols: Option<&SymbolTable>,
+    ) -> io::Result<()> {
+        let name = |addr| name(addr, symbols);
+        let percent = |count: u64| 100.0 * count as f64 / self.total.max(1) as f64;
+
+        writeln!(out, "{} instructions executed", self.total)?;
+
+        writeln!(out, "\nHottest addresses")?;
+        for (addr, count) in self.hot_spots().into_iter().take(TOP) {
+            let label = symbols.and_then(|symbols| symbols.label(addr));
+            let label = label.unwrap_or_default();
+            writeln!(
+                out,
+                "  x{addr:04X} {label:<12} {count:>10} {:>6.2}%",
+                percent(count)
+            )?;
+        }
+
+        writeln!(out, "\nInstruction classes")?;
+        let mut classes: Vec<_> = self
+            .classes
+            .iter()
+            .map(|(c, n)| (c.to_string(), *n))
+            .collect();
+        if self.illegal > 0 {
+            classes.push(("(illegal)".to_string(), self.illegal));
+        }
+        classes.sort_by(|(a_name, a), (b_name, b)| b.cmp(a).then(a_name.cmp(b_name)));
+        for (class, count) in classes {
+            writeln!(out, "  {class:<18} {count:>10} {:>6.2}%", percent(count))?;
+        }
+
+        writeln!(out, "\nSubroutines")?;
+        writeln!(
+            out,
+            "  {:<18} {:>10} {:>10} {:>10}",
+            "name", "calls", "self", "total"
+        )?;
+        let mut subroutines: Vec<_> = self.subroutines().into_iter().collect();
+        subroutines.sort_by(|(a_addr, a), (b_addr, b)| {
+            b.total_steps.cmp(&a.total_steps).then(a_addr.cmp(b_addr))
+        });
+        for (entry, stats) in subroutines {
+            writeln!(
+                out,
+                "  {:<18} {:>10} {:>10} {:>10}",
+                name(entry),
+                stats.calls,
+                stats.self_steps,
+                stats.total_steps
+            )?;
+        }
+
+        writeln!(out, "\nCalls")?;
+        for ((caller, callee), calls) in self.call_graph() {
+            writeln!(
+                out,
+                "  {:<18} -> {:<18} {calls:>10}",
+                name(caller),
+                name(callee)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes every stack in the folded format of flamegraph tools: entry
+    /// points separated by `;`, then a space and a count, one per line.
+    pub fn write_folded<W: Write>(
+        &self,
+        mut out: W,
+        symbols: Option<&SymbolTable>,
+    ) -> io::Result<()> {
+        // Several contexts can fold to the same names
+        let mut folded: HashMap<String, u64> = HashMap::new();
+        let mut order = Vec::new();
+        for (stack, steps) in self.stacks() {
+            let names: Vec<_> = stack.iter().map(|addr| name(*addr, symbols)).collect();
+            let line = names.join(";");
+            if !folded.contains_key(&line) {
+                order.push(line.clone());
+            }
+            *folded.entry(line).or_default() += steps;
+        }
+
+        for line in order {
+            writeln!(out, "{line} {}", folded[&line])?;
+        }
+        Ok(())
+    }
+
+    /// Instructions executed in each context and everything it called.
+    fn inclusive_steps(&self) -> Vec<u64> {
+        let mut inclusive: Vec<u64> = self.contexts.iter().map(|c| c.steps).collect();
+        // Children always come after their parents
+        for idx in (1..self.contexts.len()).rev() {
+            inclusive[self.contexts[idx].parent] += inclusive[idx];
+        }
+        inclusive
+    }
+
+    /// Contexts calling the one at `idx`, innermost first.
+    fn ancestors(&self, idx: usize) -> impl Iterator<Item = &Context> {
+        let mut idx = idx;
+        std::iter::from_fn(move || {
+            if idx == 0 {
+                return None;
+            }
+            idx = self.contexts[idx].parent;
+            Some(&self.contexts[idx])
+        })
+    }
+}
+
+/// `addr`'s label, or the address itself.
+fn name(addr: LC3MemAddr, symbols: Option<&SymbolTable>) -> String {
+    match symbols.and_then(|symbols| symbols.label(addr)) {
+        Some(label) => label.to_string(),
+        None => format!("x{addr:04X}"),
+    }
+}
+
+impl<P: LC3> LC3 for Profiler<P> {
+    fn pc(&self) -> LC3MemAddr {
+        self.inner.pc()
+    }
+    fn set_pc(&mut self, pc: LC3MemAddr) {
+        self.inner.set_pc(pc)
+    }
+
+    fn reg(&self, addr: RegAddr) -> LC3Word {
+        self.inner.reg(addr)
+    }
+    fn set_reg(&mut self, addr: RegAddr, value: LC3Word) {
+        self.inner.set_reg(addr, value)
+    }
+
+    fn mem(&self, addr: LC3MemAddr) -> LC3Word {
+        self.inner.mem(addr)
+    }
+    fn load(&mut self, addr: LC3MemAddr) -> LC3Word {
+        self.inner.load(addr)
+    }
+    fn set_mem(&mut self, addr: LC3MemAddr, value: LC3Word) {
+        self.inner.set_mem(addr, value)
+    }
+
+    fn devices(&self) -> &DeviceBus {
+        self.inner.devices()
+    }
+    fn devices_mut(&mut self) -> &mut DeviceBus {
+        self.inner.devices_mut()
+    }
+
+    fn priority(&self) -> u8 {
+        self.inner.priority()
+    }
+    fn set_priority(&mut self, priority: u8) {
+        self.inner.set_priority(priority)
+    }
+
+    fn privileged(&self) -> bool {
+        self.inner.privileged()
+    }
+    fn set_privileged(&mut self, priviledged: bool) {
+        self.inner.set_privileged(priviledged)
+    }
+
+    fn saved_stack_reg(&self) -> LC3Word {
+        self.inner.saved_stack_reg()
+    }
+
+    fn clock_enabled(&self) -> bool {
+        self.inner.clock_enabled()
+    }
+
+    fn reads_keyboard(&self) -> bool {
+        self.inner.reads_keyboard()
+    }
+    fn cur_inst(&self) -> Option<InstructionEnum> {
+        self.inner.cur_inst()
+    }
+
+    fn positive_cond(&self) -> bool {
+        self.inner.positive_cond()
+    }
+    fn zero_cond(&self) -> bool {
+        self.inner.zero_cond()
+    }
+    fn negative_cond(&self) -> bool {
+        self.inner.negative_cond()
+    }
+
+    fn flag_positive(&mut self) {
+        self.inner.flag_positive()
+    }
+    fn flag_zero(&mut self) {
+        self.inner.flag_zero()
+    }
+    fn flag_negative(&mut self) {
+        self.inner.flag_negative()
+    }
+
+    fn clear_flags(&mut self) {
+        self.inner.clear_flags()
+    }
+
+    type FullIter<'a>
+        = P::FullIter<'a>
+    where
+        Self: 'a;
+    fn iter(&self) -> Self::FullIter<'_> {
+        self.inner.iter()
+    }
+
+    type SparseIter<'a>
+        = P::SparseIter<'a>
+    where
+        Self: 'a;
+    fn sparse_iter(&self) -> Self::SparseIter<'_> {
+        self.inner.sparse_iter()
+    }
+
+    fn halt(&mut self) {
+        self.inner.halt()
+    }
+    fn unhalt(&mut self) {
+        self.inner.unhalt()
+    }
+    fn is_halted(&self) -> bool {
+        self.inner.is_halted()
+    }
+
+    /// Steps the wrapped processor, counting the instruction.
+    fn step(&mut self) -> Result<(), StepFailure> {
+        let addr = self.inner.pc();
+        let inst = self.inner.cur_inst();
+        self.inner.step()?;
+        self.profile.record(addr, inst, self.inner.pc());
+        Ok(())
+    }
+
+    fn set_recording(&mut self, recording: bool) {
+        self.inner.set_recording(recording)
+    }
+    fn take_changes(&mut self) -> Vec<Change> {
+        self.inner.take_changes()
+    }
+
+    fn populate<I: IntoIterator<Item = LC3Word>>(&mut self, start: LC3MemAddr, words: I) {
+        self.inner.populate(start, words)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        assembler::assemble,
+        executors::core::{load_program, CoreLC3},
+        harnesses::{scripted::ScriptedIO, sync::step_continue},
+        instruction::Instruction,
+        os::load_lc3os,
+    };
+
+    /// Doubles R1 three times through a helper, then prints a line.
+    const PROGRAM: &str = r#"
+                .ORIG x3000
+        MAIN    AND R1, R1, #0
+                ADD R1, R1, #1
+                AND R2, R2, #0
+                ADD R2, R2, #3
+        LOOP    JSR DOUBLE
+                ADD R2, R2, #-1
+                BRp LOOP
+                LEA R0, MSG
+                PUTS
+                HALT
+        DOUBLE  ST R7, SAVE
+                JSR ADDR1
+                LD R7, SAVE
+                RET
+        ADDR1   ADD R1, R1, R1
+                RET
+        SAVE    .BLKW 1
+        MSG     .STRINGZ "!"
+                .END
+    "#;
+
+    fn load(os: bool) -> (Profiler<CoreLC3>, SymbolTable) {
+        let mut lc3 = load_program(PROGRAM, !os);
+        if os {
+            load_lc3os(&mut lc3);
+        }
+        let symbols = assemble(PROGRAM).unwrap().symbols;
+        (Profiler::new(lc3), symbols)
+    }
+
+    fn run(os: bool) -> (Profile, SymbolTable) {
+        let (mut profiler, symbols) = load(os);
+        step_continue(&mut ScriptedIO::new(""), &mut profiler).unwrap();
+        assert_eq!(profiler.reg(RegAddr::One), 8);
+        (profiler.into_inner().1, symbols)
+    }
+
+    #[test]
+    fn counts() {
+        let (profile, symbols) = run(false);
+        let addr = |label| symbols.get(label).unwrap();
+
+        // 4 setup, 3 passes of 3, 3 calls of 4 + 2, LEA, PUTS and HALT
+        assert_eq!(profile.total(), 4 + 9 + 18 + 3);
+        assert_eq!(profile.count(addr("LOOP")), 3);
+        assert_eq!(profile.count(addr("ADDR1")), 3);
+        assert_eq!(profile.count(addr("MSG")), 0);
+        assert_eq!(profile.hot_spots()[0], (addr("LOOP"), 3));
+
+        let classes = profile.classes();
+        assert_eq!(classes[&InstructionClass::IAdd], 2 + 3 + 3);
+        assert_eq!(classes[&InstructionClass::IJumpSubRoutine], 6);
+        assert_eq!(classes[&InstructionClass::IJump], 6);
+        assert_eq!(classes[&InstructionClass::Trap], 2);
+        assert_eq!(classes.values().sum::<u64>(), profile.total());
+        assert_eq!(profile.illegal(), 0);
+    }
+
+    #[test]
+    fn call_graph() {
+        let (profile, symbols) = run(false);
+        let addr = |label| symbols.get(label).unwrap();
+
+        let edges: Vec<_> = profile.call_graph().into_iter().collect();
+        assert_eq!(
+            edges,
+            [
+                ((addr("MAIN"), addr("DOUBLE")), 3),
+                ((addr("DOUBLE"), addr("ADDR1")), 3),
+            ]
+        );
+
+        let stats = profile.subroutines();
+        assert_eq!(
+            stats[&addr("DOUBLE")],
+            SubroutineStats {
+                calls: 3,
+                self_steps: 12,
+                total_steps: 18,
+            }
+        );
+        assert_eq!(stats[&addr("MAIN")].total_steps, profile.total());
+    }
+
+    #[test]
+    fn os_traps_are_calls() {
+        let (profile, symbols) = run(true);
+        let main = symbols.get("MAIN").unwrap();
+
+        // PUTS and HALT enter the OS, and HALT never returns
+        let calls: Vec<_> = profile
+            .call_graph()
+            .into_iter()
+            .filter(|((caller, _), _)| *caller == main)
+            .map(|((_, callee), calls)| (callee, calls))
+            .collect();
+        assert_eq!(calls.len(), 3);
+        assert!(profile.stacks().iter().any(|(stack, _)| stack.len() == 2
+            && stack[0] == main
+            && stack[1] != symbols.get("DOUBLE").unwrap()));
+    }
+
+    #[test]
+    fn folded() {
+        let (profile, symbols) = run(false);
+        let mut out = Vec::new();
+        profile.write_folded(&mut out, Some(&symbols)).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "MAIN 16\nMAIN;DOUBLE 12\nMAIN;DOUBLE;ADDR1 6\n"
+        );
+
+        let mut out = Vec::new();
+        profile.write_folded(&mut out, None).unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with("x3000 16\n"));
+    }
+
+    #[test]
+    fn text() {
+        let (profile, symbols) = run(false);
+        let mut out = Vec::new();
+        profile.write_text(&mut out, Some(&symbols)).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.starts_with("34 instructions executed\n"), "{text}");
+        assert!(
+            text.contains("  x3004 LOOP                  3   8.82%\n"),
+            "{text}"
+        );
+        assert!(
+            text.contains("  ADD                         8  23.53%\n"),
+            "{text}"
+        );
+        assert!(
+            text.contains("  DOUBLE                      3         12         18\n"),
+            "{text}"
+        );
+        assert!(
+            text.contains("  MAIN               -> DOUBLE                      3\n"),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn unmatched_returns() {
+        let mut profile = Profile::default();
+        let ret = InstructionEnum::parse(0xC1C0);
+        profile.record(0x3000, ret, 0x4000);
+        let jsr = InstructionEnum::parse(0x4800);
+        profile.record(0x4000, jsr, 0x4001);
+
+        // A RET with no call, and a JSR to the next word, change nothing
+        assert_eq!(profile.stacks(), [(vec![0x3000], 2)]);
+        assert!(profile.call_graph().is_empty());
+    }
+}
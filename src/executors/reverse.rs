@@ -0,0 +1,392 @@
+//! Reverse execution for any [`LC3`].
+
+use std::collections::VecDeque;
+
+use crate::{
+    defs::{LC3MemAddr, LC3Word, RegAddr},
+    devices::DeviceBus,
+    instruction::InstructionEnum,
+};
+
+use super::{Change, StepFailure, LC3};
+
+/// Changes kept by [`Reversible::new`], about 8 MiB.
+pub const DEFAULT_CAPACITY: usize = 1 << 20;
+
+/// Journals the changes made by each step, so steps can be undone.
+///
+/// The journal holds at most a fixed number of [`Change`]s, forgetting the
+/// oldest steps to stay under it. Only steps are journaled, not changes
+/// made between them, and devices are not rewound: output stays written and
+/// keys stay read.
+#[derive(Debug, Clone)]
+pub struct Reversible<P> {
+    inner: P,
+    /// Changes of every journaled step, oldest first.
+    changes: VecDeque<Change>,
+    /// Number of changes made by each journaled step, oldest first.
+    steps: VecDeque<usize>,
+    capacity: usize,
+    /// Changes for [`LC3::take_changes`], while recording.
+    recorded: Option<Vec<Change>>,
+}
+
+impl<P: LC3> Reversible<P> {
+    /// Journals `inner` with [`DEFAULT_CAPACITY`].
+    pub fn new(inner: P) -> Self {
+        Self::with_capacity(inner, DEFAULT_CAPACITY)
+    }
+
+    /// Journals `inner`, keeping at most `capacity` changes.
+    pub fn with_capacity(mut inner: P, capacity: usize) -> Self {
+        inner.set_recording(true);
+        Self {
+            inner,
+            changes: VecDeque::new(),
+            steps: VecDeque::new(),
+            capacity,
+            recorded: None,
+        }
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    /// Stops journaling and returns the wrapped processor.
+    pub fn into_inner(mut self) -> P {
+        self.inner.set_recording(false);
+        self.inner
+    }
+
+    /// Number of steps that can be undone.
+    pub fn history_len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Forgets every journaled step.
+    pub fn clear_history(&mut self) {
+        self.changes.clear();
+        self.steps.clear();
+    }
+
+    /// Undoes up to `count` steps, returning how many were undone.
+    pub fn step_back(&mut self, count: usize) -> usize {
+        let mut undone = 0;
+        while undone < count && self.undo() {
+            undone += 1;
+        }
+        undone
+    }
+
+    /// Undoes steps until the PC reaches one of `breakpoints`, undoing at
+    /// least one step.
+    ///
+    /// Returns the breakpoint reached, or `None` after undoing every
+    /// journaled step without reaching one.
+    pub fn reverse_continue<B>(&mut self, breakpoints: B) -> Option<LC3MemAddr>
+    where
+        B: Fn(LC3MemAddr) -> bool,
+    {
+        while self.undo() {
+            if breakpoints(self.inner.pc()) {
+                return Some(self.inner.pc());
+            }
+        }
+        None
+    }
+
+    /// Undoes the newest journaled step, returning false if there is none.
+    fn undo(&mut self) -> bool {
+        let Some(count) = self.steps.pop_back() else {
+            return false;
+        };
+        for change in self.changes.drain(self.changes.len() - count..).rev() {
+            change.revert(&mut self.inner);
+        }
+        // Reverts are never journaled as a step
+        self.forward_changes();
+        true
+    }
+
+    /// Takes the changes recorded by the wrapped processor, passing them on
+    /// to [`LC3::take_changes`] if recording.
+    fn forward_changes(&mut self) -> Vec<Change> {
+        let changes = self.inner.take_changes();
+        if let Some(recorded) = &mut self.recorded {
+            recorded.extend_from_slice(&changes);
+        }
+        changes
+    }
+
+    fn journal(&mut self, changes: Vec<Change>) {
+        if changes.len() > self.capacity {
+            self.clear_history();
+            return;
+        }
+        while self.changes.len() + changes.len() > self.capacity {
+            let oldest = self.steps.pop_front().unwrap_or_default();
+            self.changes.drain(..oldest);
+        }
+        self.steps.push_back(changes.len());
+        self.changes.extend(changes);
+    }
+}
+
+impl<P: LC3> LC3 for Reversible<P> {
+    fn pc(&self) -> LC3MemAddr {
+        self.inner.pc()
+    }
+    fn set_pc(&mut self, pc: LC3MemAddr) {
+        self.inner.set_pc(pc)
+    }
+
+    fn reg(&self, addr: RegAddr) -> LC3Word {
+        self.inner.reg(addr)
+    }
+    fn set_reg(&mut self, addr: RegAddr, value: LC3Word) {
+        self.inner.set_reg(addr, value)
+    }
+
+    fn mem(&self, addr: LC3MemAddr) -> LC3Word {
+        self.inner.mem(addr)
+    }
+    fn load(&mut self, addr: LC3MemAddr) -> LC3Word {
+        self.inner.load(addr)
+    }
+    fn set_mem(&mut self, addr: LC3MemAddr, value: LC3Word) {
+        self.inner.set_mem(addr, value)
+    }
+
+    fn devices(&self) -> &DeviceBus {
+        self.inner.devices()
+    }
+    fn devices_mut(&mut self) -> &mut DeviceBus {
+        self.inner.devices_mut()
+    }
+
+    fn priority(&self) -> u8 {
+        self.inner.priority()
+    }
+    fn set_priority(&mut self, priority: u8) {
+        self.inner.set_priority(priority)
+    }
+
+    fn privileged(&self) -> bool {
+        self.inner.privileged()
+    }
+    fn set_privileged(&mut self, priviledged: bool) {
+        self.inner.set_privileged(priviledged)
+    }
+
+    fn saved_stack_reg(&self) -> LC3Word {
+        self.inner.saved_stack_reg()
+    }
+
+    fn clock_enabled(&self) -> bool {
+        self.inner.clock_enabled()
+    }
+
+    fn reads_keyboard(&self) -> bool {
+        self.inner.reads_keyboard()
+    }
+    fn cur_inst(&self) -> Option<InstructionEnum> {
+        self.inner.cur_inst()
+    }
+
+    fn positive_cond(&self) -> bool {
+        self.inner.positive_cond()
+    }
+    fn zero_cond(&self) -> bool {
+        self.inner.zero_cond()
+    }
+    fn negative_cond(&self) -> bool {
+        self.inner.negative_cond()
+    }
+
+    fn flag_positive(&mut self) {
+        self.inner.flag_positive()
+    }
+    fn flag_zero(&mut self) {
+        self.inner.flag_zero()
+    }
+    fn flag_negative(&mut self) {
+        self.inner.flag_negative()
+    }
+
+    fn clear_flags(&mut self) {
+        self.inner.clear_flags()
+    }
+
+    type FullIter<'a>
+        = P::FullIter<'a>
+    where
+        Self: 'a;
+    fn iter(&self) -> Self::FullIter<'_> {
+        self.inner.iter()
+    }
+
+    type SparseIter<'a>
+        = P::SparseIter<'a>
+    where
+        Self: 'a;
+    fn sparse_iter(&self) -> Self::SparseIter<'_> {
+        self.inner.sparse_iter()
+    }
+
+    fn halt(&mut self) {
+        self.inner.halt()
+    }
+    fn unhalt(&mut self) {
+        self.inner.unhalt()
+    }
+    fn is_halted(&self) -> bool {
+        self.inner.is_halted()
+    }
+
+    /// Steps the wrapped processor, journaling its changes.
+    fn step(&mut self) -> Result<(), StepFailure> {
+        // Changes made since the last step are not undone
+        self.forward_changes();
+
+        let result = self.inner.step();
+        let changes = self.forward_changes();
+        if !changes.is_empty() {
+            self.journal(changes);
+        }
+        result
+    }
+
+    fn set_recording(&mut self, recording: bool) {
+        self.recorded = recording.then(Vec::new);
+    }
+    fn take_changes(&mut self) -> Vec<Change> {
+        self.recorded
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    fn populate<I: IntoIterator<Item = LC3Word>>(&mut self, start: LC3MemAddr, words: I) {
+        self.inner.populate(start, words)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        defs::USER_SPACE,
+        executors::core::load_program,
+        harnesses::{scripted::ScriptedIO, sync::step_continue},
+        trace::{StepRecord, Tracer},
+    };
+
+    /// Sums 1 to 5 into R1, storing each partial sum.
+    const SUMS: &str = r#"
+                .ORIG x3000
+                LEA R3, SUMS
+                AND R2, R2, #0
+                ADD R2, R2, #5
+        LOOP    ADD R1, R1, R2
+                STR R1, R3, #0
+                ADD R3, R3, #1
+                ADD R2, R2, #-1
+                BRp LOOP
+                HALT
+        SUMS    .BLKW 5
+                .END
+    "#;
+
+    /// Every observable part of `processor`.
+    fn state<P: LC3>(processor: &P) -> (LC3MemAddr, LC3Word, [LC3Word; 8], Vec<LC3Word>, bool) {
+        (
+            processor.pc(),
+            processor.processor_status_reg(),
+            std::array::from_fn(|reg| processor.reg(RegAddr::try_from(reg as u8).unwrap())),
+            processor.iter().collect(),
+            processor.is_halted(),
+        )
+    }
+
+    #[test]
+    fn step_back() {
+        let mut lc3 = Reversible::new(load_program(SUMS, true));
+
+        // Remember the state before every step
+        let mut states = vec![state(&lc3)];
+        while !lc3.is_halted() {
+            lc3.step().unwrap();
+            states.push(state(&lc3));
+        }
+        assert_eq!(lc3.history_len(), states.len() - 1);
+        assert_eq!(lc3.mem(0x3009 + 4), 15);
+
+        assert_eq!(lc3.step_back(1), 1);
+        assert_eq!(state(&lc3), states[states.len() - 2]);
+
+        let undone = lc3.step_back(usize::MAX);
+        assert_eq!(undone, states.len() - 2);
+        assert_eq!(state(&lc3), states[0]);
+        assert_eq!(lc3.step_back(1), 0);
+    }
+
+    #[test]
+    fn reverse_continue() {
+        let mut lc3 = Reversible::new(load_program(SUMS, true));
+        step_continue(&mut ScriptedIO::new(""), &mut lc3).unwrap();
+
+        // Back to the last pass through the loop
+        assert_eq!(lc3.reverse_continue(|pc| pc == 0x3003), Some(0x3003));
+        assert_eq!(lc3.reg(RegAddr::Two), 1);
+        assert_eq!(lc3.mem(0x3009 + 4), 0);
+
+        // Continuing moves past the breakpoint it stopped on
+        assert_eq!(lc3.reverse_continue(|pc| pc == 0x3003), Some(0x3003));
+        assert_eq!(lc3.reg(RegAddr::Two), 2);
+
+        assert_eq!(lc3.reverse_continue(|_| false), None);
+        assert_eq!(lc3.pc(), USER_SPACE);
+        assert_eq!(lc3.reg(RegAddr::One), 0);
+    }
+
+    #[test]
+    fn bounded() {
+        // Each step changes the PC and at most two other things
+        let mut lc3 = Reversible::with_capacity(load_program(SUMS, true), 8);
+        step_continue(&mut ScriptedIO::new(""), &mut lc3).unwrap();
+
+        let kept = lc3.history_len();
+        assert!(kept >= 8 / 3);
+        assert!(lc3.changes.len() <= 8);
+
+        // Only the newest steps can be undone
+        assert_eq!(lc3.step_back(usize::MAX), kept);
+        assert_ne!(lc3.pc(), USER_SPACE);
+        assert_eq!(lc3.mem(0x3009 + 3), 14);
+
+        // Too big to journal at all
+        let mut lc3 = Reversible::with_capacity(load_program(SUMS, true), 0);
+        lc3.step().unwrap();
+        assert_eq!(lc3.history_len(), 0);
+    }
+
+    #[test]
+    fn traced() {
+        let mut lc3 = Reversible::new(load_program(SUMS, true));
+        let mut tracer = Tracer::new(ScriptedIO::new(""), Vec::<StepRecord>::new());
+        step_continue(&mut tracer, &mut lc3).unwrap();
+
+        // Both see every step
+        let (_, records) = tracer.into_inner();
+        assert_eq!(records.len(), lc3.history_len());
+        assert!(records.iter().all(|record| !record.changes.is_empty()));
+
+        lc3.step_back(1);
+        assert_eq!(
+            lc3.take_changes().len(),
+            records.last().unwrap().changes.len()
+        );
+    }
+}
@@ -0,0 +1,446 @@
+//! Snapshots of the whole machine state, for any [`LC3`].
+//!
+//! A saved state is [`MAGIC`] and a [`VERSION`] byte, then the PC, PSR, the
+//! saved stack pointer of the inactive mode and R0 to R7, then a flags byte
+//! ([`HALTED`], [`CLOCK_ENABLED`]). Memory follows as a count of runs, each
+//! a start address, a length and that many words. Only nonzero words are
+//! kept, so everything outside the runs is zero. Every word is big endian,
+//! like object files.
+//!
+//! Device state and executor settings, such as [`super::core::CoreLC3::is_strict`],
+//! are not part of a snapshot.
+
+use std::io::{self, ErrorKind, Read, Write};
+
+use thiserror::Error;
+
+use crate::defs::{
+    LC3MemAddr, LC3Word, RegAddr, ADDR_SPACE_SIZE, MACHINE_CONTROL_REGISTER, NUM_REGS, STACK_REG,
+};
+
+use super::{LC3MemLoc, LC3, PRIV_MASK};
+
+/// Start of every saved state.
+pub const MAGIC: [u8; 4] = *b"LC3S";
+/// Current format version.
+pub const VERSION: u8 = 1;
+
+/// Flag set when the processor is halted.
+pub const HALTED: u8 = 1 << 0;
+/// Flag set when the MCR clock is enabled.
+pub const CLOCK_ENABLED: u8 = 1 << 1;
+
+/// MCR clock enable bit.
+const CLOCK_BIT: LC3Word = 1 << 15;
+
+#[derive(Debug, Error)]
+pub enum StateError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Not a saved machine state")]
+    BadMagic,
+    #[error("Saved state format version {0} is not supported")]
+    UnsupportedVersion(u8),
+    #[error("The saved state ends early")]
+    Truncated,
+    #[error("Memory run at x{0:04X} is empty or runs past the end of memory")]
+    BadRun(LC3MemAddr),
+}
+
+/// Everything needed to resume a processor where it left off.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MachineState {
+    pub pc: LC3MemAddr,
+    pub psr: LC3Word,
+    /// R0 to R7, with R6 from the active mode.
+    pub regs: [LC3Word; NUM_REGS],
+    /// See [`LC3::saved_stack_reg`].
+    pub saved_stack_reg: LC3Word,
+    pub halted: bool,
+    /// See [`LC3::clock_enabled`].
+    pub clock_enabled: bool,
+    /// Every nonzero word of memory, in address order.
+    pub memory: Vec<LC3MemLoc>,
+}
+
+impl MachineState {
+    /// Takes a snapshot of `processor`.
+    pub fn capture<P: LC3>(processor: &P) -> Self {
+        Self {
+            pc: processor.pc(),
+            psr: processor.processor_status_reg(),
+            regs: std::array::from_fn(|reg| processor.reg(reg_addr(reg))),
+            saved_stack_reg: processor.saved_stack_reg(),
+            halted: processor.is_halted(),
+            clock_enabled: processor.clock_enabled(),
+            memory: processor.sparse_iter().collect(),
+        }
+    }
+
+    /// Puts `processor` into this state, replacing all of its memory.
+    pub fn restore<P: LC3>(&self, processor: &mut P) {
+        let mut words = vec![0; ADDR_SPACE_SIZE];
+        for loc in &self.memory {
+            words[usize::from(loc.loc)] = loc.value;
+        }
+        let mcr = words[usize::from(MACHINE_CONTROL_REGISTER)];
+
+        // Writing the MCR sets the clock, populating restores its value
+        let enable = if self.clock_enabled { CLOCK_BIT } else { 0 };
+        processor.set_mem(MACHINE_CONTROL_REGISTER, enable);
+        processor.populate(0, words);
+        processor.populate(MACHINE_CONTROL_REGISTER, [mcr]);
+
+        // Fill the inactive stack pointer from its own mode
+        let privileged = self.psr & PRIV_MASK == 0;
+        processor.set_privileged(!privileged);
+        processor.set_reg(STACK_REG, self.saved_stack_reg);
+        processor.set_processor_status_reg(self.psr);
+        for (reg, value) in self.regs.iter().enumerate() {
+            processor.set_reg(reg_addr(reg), *value);
+        }
+
+        processor.set_pc(self.pc);
+        if self.halted {
+            processor.halt();
+        } else {
+            processor.unhalt();
+        }
+    }
+
+    /// Writes this state in the saved state format.
+    pub fn write<W: Write>(&self, mut output: W) -> io::Result<()> {
+        let runs = runs(&self.memory);
+        let run_count = u16::try_from(runs.len())
+            .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "too many memory runs"))?;
+
+        let mut bytes = Vec::with_capacity(32 + 2 * self.memory.len() + 4 * runs.len());
+        bytes.extend(MAGIC);
+        bytes.push(VERSION);
+        push_words(&mut bytes, [self.pc, self.psr, self.saved_stack_reg]);
+        push_words(&mut bytes, self.regs);
+
+        let halted = if self.halted { HALTED } else { 0 };
+        let clock = if self.clock_enabled { CLOCK_ENABLED } else { 0 };
+        bytes.push(halted | clock);
+
+        push_words(&mut bytes, [run_count]);
+        for run in runs {
+            push_words(&mut bytes, [run[0].loc, run.len() as LC3Word]);
+            push_words(&mut bytes, run.iter().map(|loc| loc.value));
+        }
+
+        output.write_all(&bytes)
+    }
+
+    /// Reads a state in the saved state format, checking the version.
+    pub fn read<R: Read>(mut input: R) -> Result<Self, StateError> {
+        let mut header = [0; 5];
+        input.read_exact(&mut header).map_err(|e| match e.kind() {
+            ErrorKind::UnexpectedEof => StateError::BadMagic,
+            _ => e.into(),
+        })?;
+        if header[..4] != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        if header[4] != VERSION {
+            return Err(StateError::UnsupportedVersion(header[4]));
+        }
+
+        let [pc, psr, saved_stack_reg] = read_words(&mut input)?;
+        let regs = read_words(&mut input)?;
+        let [flags] = read_bytes(&mut input)?;
+
+        let [run_count] = read_words(&mut input)?;
+        let mut memory = Vec::new();
+        for _ in 0..run_count {
+            let [start, len] = read_words(&mut input)?;
+            if len == 0 || usize::from(start) + usize::from(len) > ADDR_SPACE_SIZE {
+                return Err(StateError::BadRun(start));
+            }
+            for loc in start..=start + (len - 1) {
+                let [value] = read_words(&mut input)?;
+                memory.push(LC3MemLoc { loc, value });
+            }
+        }
+
+        Ok(Self {
+            pc,
+            psr,
+            regs,
+            saved_stack_reg,
+            halted: flags & HALTED != 0,
+            clock_enabled: flags & CLOCK_ENABLED != 0,
+            memory,
+        })
+    }
+}
+
+/// Writes a snapshot of `processor` to `output`.
+pub fn save_state<P: LC3, W: Write>(processor: &P, output: W) -> io::Result<()> {
+    MachineState::capture(processor).write(output)
+}
+
+/// Reads a snapshot from `input` into `processor`.
+///
+/// The processor is left untouched if the snapshot cannot be read.
+pub fn load_state<P: LC3, R: Read>(processor: &mut P, input: R) -> Result<(), StateError> {
+    MachineState::read(input)?.restore(processor);
+    Ok(())
+}
+
+fn reg_addr(reg: usize) -> RegAddr {
+    RegAddr::try_from(reg as u8).expect("NUM_REGS registers")
+}
+
+/// Splits `memory` into runs of consecutive addresses that fit a length
+/// word.
+fn runs(memory: &[LC3MemLoc]) -> Vec<&[LC3MemLoc]> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    for end in 1..=memory.len() {
+        let split = end == memory.len()
+            || memory[end].loc != memory[end - 1].loc.wrapping_add(1)
+            || end - start == usize::from(LC3Word::MAX);
+        if split {
+            runs.push(&memory[start..end]);
+            start = end;
+        }
+    }
+    runs
+}
+
+fn push_words<I: IntoIterator<Item = LC3Word>>(bytes: &mut Vec<u8>, words: I) {
+    for word in words {
+        bytes.extend(word.to_be_bytes());
+    }
+}
+
+fn read_bytes<R: Read, const N: usize>(input: &mut R) -> Result<[u8; N], StateError> {
+    let mut bytes = [0; N];
+    input.read_exact(&mut bytes).map_err(|e| match e.kind() {
+        ErrorKind::UnexpectedEof => StateError::Truncated,
+        _ => e.into(),
+    })?;
+    Ok(bytes)
+}
+
+fn read_words<R: Read, const N: usize>(input: &mut R) -> Result<[LC3Word; N], StateError> {
+    let mut words = [0; N];
+    for word in &mut words {
+        *word = LC3Word::from_be_bytes(read_bytes(input)?);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        executors::core::{load_program, CoreLC3},
+        harnesses::{scripted::ScriptedIO, sync::step_continue},
+    };
+
+    /// Counts R1 down from 20, pushing each value onto a user stack.
+    const COUNTDOWN: &str = r#"
+                .ORIG x3000
+                LD R6, STACK
+                AND R1, R1, #0
+                ADD R1, R1, #10
+                ADD R1, R1, #10
+        LOOP    ADD R6, R6, #-1
+                STR R1, R6, #0
+                ADD R1, R1, #-1
+                BRp LOOP
+                HALT
+        STACK   .FILL x4000
+                .END
+    "#;
+
+    /// [`COUNTDOWN`] in user mode, partway through the loop.
+    fn running() -> CoreLC3 {
+        let mut lc3 = load_program(COUNTDOWN, true);
+        lc3.set_reg(STACK_REG, 0x2F00);
+        lc3.set_privileged(false);
+        for _ in 0..20 {
+            lc3.step().unwrap();
+        }
+        lc3
+    }
+
+    fn finish(lc3: &mut CoreLC3) -> MachineState {
+        step_continue(&mut ScriptedIO::new(""), lc3).unwrap();
+        MachineState::capture(lc3)
+    }
+
+    #[test]
+    fn round_trip() {
+        let lc3 = running();
+        let state = MachineState::capture(&lc3);
+        assert!(!state.memory.is_empty());
+        assert_eq!(state.saved_stack_reg, 0x2F00);
+        assert_eq!(state.psr & PRIV_MASK, PRIV_MASK);
+
+        let mut bytes = Vec::new();
+        save_state(&lc3, &mut bytes).unwrap();
+        assert_eq!(MachineState::read(bytes.as_slice()).unwrap(), state);
+
+        // Resumes the same on a processor with a different state
+        let mut loaded = CoreLC3::new();
+        loaded.set_native_os(true);
+        loaded.populate(0x5000, [1, 2, 3]);
+        loaded.set_reg(STACK_REG, 0x1234);
+        loaded.halt();
+        load_state(&mut loaded, bytes.as_slice()).unwrap();
+        assert_eq!(MachineState::capture(&loaded), state);
+
+        let mut original = lc3.clone();
+        let finished = finish(&mut original);
+        assert_eq!(finish(&mut loaded), finished);
+        assert!(finished.halted);
+        assert_eq!(original.mem(0x4000 - 1), 20);
+        assert_eq!(original.mem(0x4000 - 20), 1);
+    }
+
+    #[test]
+    fn clock() {
+        let mut lc3 = running();
+
+        // Cleared MCR, but never written
+        let state = MachineState::capture(&lc3);
+        assert_eq!(lc3.mem(MACHINE_CONTROL_REGISTER), 0);
+        assert!(state.clock_enabled);
+        let mut loaded = running();
+        loaded.set_mem(MACHINE_CONTROL_REGISTER, 0);
+        state.restore(&mut loaded);
+        assert!(loaded.clock_enabled());
+        assert_eq!(loaded.mem(MACHINE_CONTROL_REGISTER), 0);
+
+        // Stopped by a write
+        lc3.set_mem(MACHINE_CONTROL_REGISTER, 0x1234);
+        let state = MachineState::capture(&lc3);
+        assert!(!state.clock_enabled);
+        let mut loaded = running();
+        state.restore(&mut loaded);
+        assert!(!loaded.clock_enabled());
+        assert_eq!(loaded.mem(MACHINE_CONTROL_REGISTER), 0x1234);
+    }
+
+    #[test]
+    fn layout() {
+        let memory = [(0x3000, 0x1261), (0x3001, 0xF025), (0xFFFF, 7)]
+            .map(|(loc, value)| LC3MemLoc { loc, value })
+            .to_vec();
+        let state = MachineState {
+            pc: 0x3001,
+            psr: 0x8001,
+            regs: [1, 0, 0, 0, 0, 0, 0xFE00, 0],
+            saved_stack_reg: 0x3000,
+            halted: true,
+            clock_enabled: true,
+            memory,
+        };
+
+        let mut bytes = Vec::new();
+        state.write(&mut bytes).unwrap();
+        assert_eq!(
+            bytes,
+            [
+                b'L',
+                b'C',
+                b'3',
+                b'S',
+                1, // Header
+                0x30,
+                0x01,
+                0x80,
+                0x01,
+                0x30,
+                0x00, // PC, PSR, saved R6
+                0,
+                1,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0xFE,
+                0x00,
+                0,
+                0, // R0 to R7
+                HALTED | CLOCK_ENABLED,
+                0,
+                2, // Runs
+                0x30,
+                0x00,
+                0,
+                2,
+                0x12,
+                0x61,
+                0xF0,
+                0x25, // x3000
+                0xFF,
+                0xFF,
+                0,
+                1,
+                0,
+                7, // xFFFF
+            ]
+        );
+    }
+
+    #[test]
+    fn long_runs() {
+        let memory: Vec<_> = (0..=LC3MemAddr::MAX)
+            .map(|loc| LC3MemLoc { loc, value: 1 })
+            .collect();
+        let runs = runs(&memory);
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[1], &memory[0xFFFF..]);
+
+        let mut lc3 = CoreLC3::new();
+        lc3.populate(0, vec![1; ADDR_SPACE_SIZE]);
+        let mut bytes = Vec::new();
+        save_state(&lc3, &mut bytes).unwrap();
+        let state = MachineState::read(bytes.as_slice()).unwrap();
+        assert_eq!(state.memory, memory);
+    }
+
+    #[test]
+    fn bad_states() {
+        let mut bytes = Vec::new();
+        save_state(&running(), &mut bytes).unwrap();
+
+        assert!(matches!(
+            MachineState::read(&b"LC3T\x01"[..]),
+            Err(StateError::BadMagic)
+        ));
+        assert!(matches!(
+            MachineState::read(&b"LC3S\x02"[..]),
+            Err(StateError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            MachineState::read(&bytes[..bytes.len() - 1]),
+            Err(StateError::Truncated)
+        ));
+
+        // Nothing changes when loading fails
+        let mut lc3 = running();
+        let before = MachineState::capture(&lc3);
+        assert!(load_state(&mut lc3, &bytes[..30]).is_err());
+        assert_eq!(MachineState::capture(&lc3), before);
+
+        let mut overflow = bytes[..28].to_vec();
+        overflow.extend([0, 1, 0xFF, 0xFF, 0, 2]);
+        assert!(matches!(
+            MachineState::read(overflow.as_slice()),
+            Err(StateError::BadRun(0xFFFF))
+        ));
+    }
+}
@@ -0,0 +1,798 @@
+//! GDB remote serial protocol stub, for attaching debuggers to any [`LC3`].
+//!
+//! [`GdbStub`] serves one debugger session over any byte stream, such as a
+//! [`std::net::TcpStream`] or stdin and stdout. It supports register and
+//! memory access, single stepping, continuing with software breakpoints and
+//! interrupting with Ctrl-C, and describes the registers with [`TARGET_XML`].
+//!
+//! GDB addresses memory in bytes, so every address in a packet is a byte
+//! address, twice the LC-3 word address, and lengths count bytes. Words are
+//! sent big endian, like object files, so `m6000,4` reads the two words at
+//! x3000 and x3001, and `m6001,1` reads the low byte of x3000. Writes, and
+//! the addresses of breakpoints, `s` and `c`, must be whole words.
+//!
+//! Register values are sent as they are, so the PC and R7 hold word
+//! addresses. The target description types them as plain integers, rather
+//! than as pointers GDB would read as byte addresses. It names no
+//! architecture, as stock GDB builds have no LC-3 support, so clients need
+//! their own knowledge of the instruction set to disassemble or unwind.
+//!
+//! | Register | Number |
+//! |----------|--------|
+//! | R0 to R7 | 0 to 7 |
+//! | PC       | 8      |
+//! | PSR      | 9      |
+
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use crate::{
+    debugger::{Breakpoint, BreakpointId, Debugger, StopReason},
+    defs::{LC3MemAddr, LC3Word, RegAddr, ADDR_SPACE_SIZE, NUM_REGS},
+    executors::{StepFailure, LC3},
+    harnesses::{sync::SyncHarness, ExecutionFailure},
+};
+
+pub mod packet;
+
+use packet::{frame, from_hex, to_hex, INTERRUPT};
+
+/// Target description, served as `target.xml`.
+pub const TARGET_XML: &str = include_str!("target.xml");
+
+/// Register number of the PC.
+pub const PC_REGNUM: usize = NUM_REGS;
+/// Register number of the PSR.
+pub const PSR_REGNUM: usize = NUM_REGS + 1;
+/// Number of registers in `g` and `G` packets.
+pub const NUM_GDB_REGS: usize = NUM_REGS + 2;
+
+/// Signal numbers, as GDB numbers them.
+pub mod signal {
+    pub const SIGINT: u8 = 2;
+    pub const SIGILL: u8 = 4;
+    pub const SIGTRAP: u8 = 5;
+    pub const SIGABRT: u8 = 6;
+    pub const SIGSEGV: u8 = 11;
+    pub const SIGIO: u8 = 23;
+}
+
+/// Largest packet accepted or sent, in bytes.
+const PACKET_SIZE: usize = 0x1000;
+
+/// Bytes of memory, as GDB addresses them.
+const BYTE_SPACE_SIZE: usize = ADDR_SPACE_SIZE * 2;
+
+/// Steps taken between checks for an interrupt while continuing.
+const POLL_INTERVAL: u64 = 1024;
+
+/// Why the target stopped, as reported to GDB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stop {
+    /// Stopped with a [`signal`].
+    Signal(u8),
+    /// Reached a software breakpoint.
+    Breakpoint,
+    /// The program halted, or stopped its clock through the MCR.
+    Exited,
+}
+
+impl Stop {
+    /// The stop reply packet data.
+    pub fn reply(self) -> String {
+        match self {
+            Self::Signal(signal) => format!("S{signal:02x}"),
+            Self::Breakpoint => format!("T{:02x}swbreak:;", signal::SIGTRAP),
+            Self::Exited => "W00".to_string(),
+        }
+    }
+}
+
+impl From<&StepFailure> for Stop {
+    fn from(value: &StepFailure) -> Self {
+        match value {
+            StepFailure::Halted | StepFailure::ClockDisabled => Self::Exited,
+            StepFailure::InvalidInstruction(_) | StepFailure::InsufficientPerms(_) => {
+                Self::Signal(signal::SIGILL)
+            }
+            StepFailure::LastAddress | StepFailure::AccessViolation(_) => {
+                Self::Signal(signal::SIGSEGV)
+            }
+        }
+    }
+}
+
+impl From<&ExecutionFailure> for Stop {
+    fn from(value: &ExecutionFailure) -> Self {
+        match value {
+            ExecutionFailure::LC3(failure) => failure.into(),
+            ExecutionFailure::NoKeyboard
+            | ExecutionFailure::NoConsole
+            | ExecutionFailure::NoDisplay => Self::Signal(signal::SIGIO),
+            ExecutionFailure::Trace(_) => Self::Signal(signal::SIGABRT),
+        }
+    }
+}
+
+impl From<StopReason> for Stop {
+    fn from(value: StopReason) -> Self {
+        match value {
+            StopReason::Stepped | StopReason::Limit | StopReason::Watchpoint { .. } => {
+                Self::Signal(signal::SIGTRAP)
+            }
+            StopReason::Breakpoint { .. } => Self::Breakpoint,
+            StopReason::Halted => Self::Exited,
+            StopReason::Failed(failure) => (&failure).into(),
+        }
+    }
+}
+
+/// Serves GDB sessions for a processor, stepping it on a harness.
+#[derive(Debug)]
+pub struct GdbStub<P, H> {
+    debugger: Debugger<P>,
+    harness: H,
+    last_stop: Stop,
+}
+
+impl<P: LC3, H: SyncHarness> GdbStub<P, H> {
+    pub fn new(processor: P, harness: H) -> Self {
+        Self {
+            debugger: Debugger::new(processor),
+            harness,
+            last_stop: Stop::Signal(signal::SIGTRAP),
+        }
+    }
+
+    pub fn processor(&self) -> &P {
+        self.debugger.processor()
+    }
+
+    /// The debugger holding GDB's breakpoints.
+    pub fn debugger(&self) -> &Debugger<P> {
+        &self.debugger
+    }
+
+    pub fn into_inner(self) -> (P, H) {
+        (self.debugger.into_inner(), self.harness)
+    }
+
+    /// Serves one session, until GDB kills or detaches from the target or
+    /// `input` ends.
+    ///
+    /// `input` is read on its own thread, to notice interrupts while the
+    /// program runs.
+    pub fn serve<R, W>(&mut self, input: R, output: W) -> io::Result<()>
+    where
+        R: Read + Send + 'static,
+        W: Write,
+    {
+        let mut connection = Connection::new(input, output);
+        while let Some(packet) = connection.read_packet()? {
+            let Ok(packet) = std::str::from_utf8(&packet) else {
+                connection.send(b"")?;
+                continue;
+            };
+            // Killing takes no reply
+            if packet == "k" {
+                return Ok(());
+            }
+            let reply = self.handle(packet, &mut connection);
+            connection.send(reply.as_bytes())?;
+            if packet == "D" {
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+
+    /// Replies to one packet, with an empty reply if it is not supported.
+    fn handle<W: Write>(&mut self, packet: &str, connection: &mut Connection<W>) -> String {
+        let (kind, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+        match kind {
+            "?" => self.last_stop.reply(),
+            "g" => (0..NUM_GDB_REGS)
+                .map(|regnum| to_hex(&self.reg(regnum).to_be_bytes()))
+                .collect(),
+            "G" => match words(args) {
+                Some(values) if values.len() == NUM_GDB_REGS => {
+                    // The PSR picks which stack pointer R6 is
+                    self.set_reg(PSR_REGNUM, values[PSR_REGNUM]);
+                    for (regnum, value) in values.into_iter().enumerate().take(PSR_REGNUM) {
+                        self.set_reg(regnum, value);
+                    }
+                    ok()
+                }
+                _ => error(1),
+            },
+            "p" => match parse_hex(args) {
+                Some(regnum) if regnum < NUM_GDB_REGS => to_hex(&self.reg(regnum).to_be_bytes()),
+                _ => error(1),
+            },
+            "P" => {
+                let parsed = args.split_once('=').and_then(|(regnum, value)| {
+                    Some((parse_hex(regnum)?, words(value)?.first().copied()?))
+                });
+                match parsed {
+                    Some((regnum, value)) if regnum < NUM_GDB_REGS => {
+                        self.set_reg(regnum, value);
+                        ok()
+                    }
+                    _ => error(1),
+                }
+            }
+            "m" => self.read_mem(args).unwrap_or_else(|| error(1)),
+            "M" => self.write_mem(args).map_or_else(|| error(1), |_| ok()),
+            "s" | "c" => {
+                if !args.is_empty() {
+                    match parse_hex(args).and_then(word_addr) {
+                        Some(addr) => self.debugger.processor_mut().set_pc(addr),
+                        None => return error(1),
+                    }
+                }
+                self.last_stop = if kind == "s" {
+                    self.step()
+                } else {
+                    self.resume(connection)
+                };
+                self.last_stop.reply()
+            }
+            "Z" | "z" => self.breakpoint(kind == "Z", args),
+            "D" | "H" | "T" => ok(),
+            "q" | "Q" => self.query(packet),
+            _ => String::new(),
+        }
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        if let Some(request) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            return read_slice(TARGET_XML, request).unwrap_or_else(|| error(1));
+        }
+        match packet.split(':').next().unwrap_or_default() {
+            "qSupported" => {
+                format!("PacketSize={PACKET_SIZE:x};qXfer:features:read+;QStartNoAckMode+;swbreak+")
+            }
+            "QStartNoAckMode" => ok(),
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            "qSymbol" => ok(),
+            _ => String::new(),
+        }
+    }
+
+    fn reg(&self, regnum: usize) -> LC3Word {
+        let lc3 = self.debugger.processor();
+        match regnum {
+            PC_REGNUM => lc3.pc(),
+            PSR_REGNUM => lc3.processor_status_reg(),
+            reg => lc3.reg(reg_addr(reg)),
+        }
+    }
+
+    fn set_reg(&mut self, regnum: usize, value: LC3Word) {
+        let lc3 = self.debugger.processor_mut();
+        match regnum {
+            PC_REGNUM => lc3.set_pc(value),
+            PSR_REGNUM => lc3.set_processor_status_reg(value),
+            reg => lc3.set_reg(reg_addr(reg), value),
+        }
+    }
+
+    /// Reads `addr,length`, without side effects on devices.
+    ///
+    /// Long reads are cut short to fit a packet, which GDB allows.
+    fn read_mem(&self, args: &str) -> Option<String> {
+        let (addr, len) = mem_range(args)?;
+        let len = len.min(PACKET_SIZE / 2).min(BYTE_SPACE_SIZE - addr);
+        let lc3 = self.debugger.processor();
+        let bytes: Vec<u8> = (addr..addr + len)
+            .map(|byte| {
+                let [high, low] = lc3.mem((byte / 2) as LC3MemAddr).to_be_bytes();
+                if byte % 2 == 0 {
+                    high
+                } else {
+                    low
+                }
+            })
+            .collect();
+        Some(to_hex(&bytes))
+    }
+
+    /// Writes `addr,length:data`, which must be whole words.
+    fn write_mem(&mut self, args: &str) -> Option<()> {
+        let (range, data) = args.split_once(':')?;
+        let (addr, len) = mem_range(range)?;
+        let addr = word_addr(addr)?;
+        let values = words(data)?;
+        if len != values.len() * 2 || usize::from(addr) + values.len() > ADDR_SPACE_SIZE {
+            return None;
+        }
+        let lc3 = self.debugger.processor_mut();
+        for (offset, value) in values.into_iter().enumerate() {
+            lc3.set_mem(addr + offset as u16, value);
+        }
+        Some(())
+    }
+
+    fn breakpoint(&mut self, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+        if fields.next() != Some("0") {
+            return String::new();
+        }
+        let Some(addr) = fields.next().and_then(parse_hex).and_then(word_addr) else {
+            return error(1);
+        };
+        let existing: Vec<BreakpointId> = self
+            .debugger
+            .breakpoints()
+            .filter(|(_, breakpoint)| breakpoint.addr() == addr)
+            .map(|(id, _)| id)
+            .collect();
+        if !insert {
+            for id in existing {
+                self.debugger.remove_breakpoint(id);
+            }
+        } else if existing.is_empty() {
+            self.debugger.add_breakpoint(Breakpoint::new(addr));
+        }
+        ok()
+    }
+
+    fn step(&mut self) -> Stop {
+        match self.debugger.step(&mut self.harness) {
+            // A single step is a plain trap, even onto a breakpoint
+            StopReason::Breakpoint { .. } => Stop::Signal(signal::SIGTRAP),
+            reason => reason.into(),
+        }
+    }
+
+    /// Steps until a breakpoint, a failure or an interrupt.
+    fn resume<W: Write>(&mut self, connection: &mut Connection<W>) -> Stop {
+        loop {
+            match self.debugger.run_for(&mut self.harness, POLL_INTERVAL) {
+                StopReason::Limit if connection.interrupted() => {
+                    return Stop::Signal(signal::SIGINT)
+                }
+                StopReason::Limit => {}
+                reason => return reason.into(),
+            }
+        }
+    }
+}
+
+/// Packet level connection to GDB.
+struct Connection<W> {
+    input: Receiver<u8>,
+    /// Bytes received while checking for interrupts.
+    pending: VecDeque<u8>,
+    output: W,
+    acks: bool,
+    /// Last packet sent, to resend on a NAK.
+    last: Vec<u8>,
+}
+
+impl<W: Write> Connection<W> {
+    fn new<R: Read + Send + 'static>(mut input: R, output: W) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buf = [0; 1024];
+            while let Ok(len @ 1..) = input.read(&mut buf) {
+                if buf[..len].iter().any(|byte| sender.send(*byte).is_err()) {
+                    break;
+                }
+            }
+        });
+        Self {
+            input: receiver,
+            pending: VecDeque::new(),
+            output,
+            acks: true,
+            last: Vec::new(),
+        }
+    }
+
+    fn next_byte(&mut self) -> Option<u8> {
+        self.pending.pop_front().or_else(|| self.input.recv().ok())
+    }
+
+    /// Reads the next valid packet's unescaped data, or `None` once input
+    /// ends.
+    fn read_packet(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            // Skip acks and stray interrupts between packets
+            match self.next_byte() {
+                None => return Ok(None),
+                Some(b'$') => {}
+                Some(b'-') if self.acks => {
+                    self.output.write_all(&self.last)?;
+                    self.output.flush()?;
+                    continue;
+                }
+                Some(_) => continue,
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.next_byte() {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+            let (Some(high), Some(low)) = (self.next_byte(), self.next_byte()) else {
+                return Ok(None);
+            };
+
+            let sum = std::str::from_utf8(&[high, low])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            if self.acks {
+                let valid = sum == Some(packet::checksum(&data));
+                self.output.write_all(if valid { b"+" } else { b"-" })?;
+                self.output.flush()?;
+                if !valid {
+                    continue;
+                }
+            }
+
+            let data = packet::unescape(&data);
+            if data == b"QStartNoAckMode" {
+                // Acknowledged above, no acks from the reply on
+                self.send(b"OK")?;
+                self.acks = false;
+                continue;
+            }
+            return Ok(Some(data));
+        }
+    }
+
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        self.last = frame(data);
+        self.output.write_all(&self.last)?;
+        self.output.flush()
+    }
+
+    /// Whether GDB has sent an interrupt, keeping anything else it sent.
+    fn interrupted(&mut self) -> bool {
+        loop {
+            match self.input.try_recv() {
+                Ok(INTERRUPT) => return true,
+                Ok(byte) => self.pending.push_back(byte),
+                Err(TryRecvError::Empty) => return false,
+                // Nothing more to read, so nothing can interrupt
+                Err(TryRecvError::Disconnected) => return false,
+            }
+        }
+    }
+}
+
+fn ok() -> String {
+    "OK".to_string()
+}
+
+fn error(code: u8) -> String {
+    format!("E{code:02x}")
+}
+
+fn reg_addr(reg: usize) -> RegAddr {
+    RegAddr::try_from(reg as u8).expect("checked against NUM_GDB_REGS")
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+/// Big endian words of a hex string.
+fn words(hex: &str) -> Option<Vec<LC3Word>> {
+    let bytes = from_hex(hex)?;
+    if bytes.len() % 2 != 0 {
+        return None;
+    }
+    Some(
+        bytes
+            .chunks_exact(2)
+            .map(|pair| LC3Word::from_be_bytes([pair[0], pair[1]]))
+            .collect(),
+    )
+}
+
+/// Word address of the byte address `addr`, which must start a word.
+fn word_addr(addr: usize) -> Option<LC3MemAddr> {
+    if addr % 2 != 0 {
+        return None;
+    }
+    LC3MemAddr::try_from(addr / 2).ok()
+}
+
+/// Parses `addr,length`, with a byte address inside memory.
+fn mem_range(args: &str) -> Option<(usize, usize)> {
+    let (addr, len) = args.split_once(',')?;
+    let addr = parse_hex(addr)?;
+    if addr >= BYTE_SPACE_SIZE {
+        return None;
+    }
+    Some((addr, parse_hex(len)?))
+}
+
+/// Replies to a `qXfer` read of `offset,length` from `document`.
+fn read_slice(document: &str, request: &str) -> Option<String> {
+    let (offset, len) = request.split_once(',')?;
+    let (offset, len) = (parse_hex(offset)?, parse_hex(len)?);
+    let bytes = document.as_bytes().get(offset..).unwrap_or_default();
+
+    let (marker, chunk) = if bytes.len() > len {
+        ('m', &bytes[..len])
+    } else {
+        ('l', bytes)
+    };
+    Some(format!("{marker}{}", String::from_utf8_lossy(chunk)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        defs::STACK_REG,
+        executors::core::{load_program, CoreLC3},
+        harnesses::scripted::ScriptedIO,
+    };
+
+    /// Counts R1 down from 5, printing a dot each time.
+    const COUNTDOWN: &str = r#"
+                .ORIG x3000
+                AND R1, R1, #0
+                ADD R1, R1, #5
+                LD R0, DOT
+        LOOP    OUT
+                ADD R1, R1, #-1
+                BRp LOOP
+                HALT
+        DOT     .FILL x2E
+                .END
+    "#;
+
+    fn load(source: &str) -> CoreLC3 {
+        let mut lc3 = load_program(source, true);
+        lc3.set_strict(true);
+        lc3
+    }
+
+    /// Serves `input` to a stub for `lc3`, returning the raw output.
+    fn serve_bytes(lc3: CoreLC3, input: Vec<u8>) -> (Vec<u8>, GdbStub<CoreLC3, ScriptedIO>) {
+        let mut stub = GdbStub::new(lc3, ScriptedIO::new(""));
+        let mut output = Vec::new();
+        stub.serve(io::Cursor::new(input), &mut output).unwrap();
+        (output, stub)
+    }
+
+    /// Sends each packet in order, returning the data of every reply.
+    fn session(lc3: CoreLC3, packets: &[&str]) -> (Vec<String>, GdbStub<CoreLC3, ScriptedIO>) {
+        let input = packets
+            .iter()
+            .flat_map(|packet| frame(packet.as_bytes()))
+            .collect();
+        let (output, stub) = serve_bytes(lc3, input);
+        (replies(&output), stub)
+    }
+
+    /// Data of every packet in `output`, skipping acks.
+    fn replies(output: &[u8]) -> Vec<String> {
+        let text = String::from_utf8(output.to_vec()).unwrap();
+        text.split('$')
+            .skip(1)
+            .map(|packet| {
+                let (data, sum) = packet.split_once('#').unwrap();
+                assert_eq!(
+                    u8::from_str_radix(&sum[..2], 16).unwrap(),
+                    packet::checksum(data.as_bytes())
+                );
+                String::from_utf8(packet::unescape(data.as_bytes())).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn registers() {
+        let mut lc3 = load(COUNTDOWN);
+        lc3.set_reg(RegAddr::Seven, 0xBEEF);
+        let (replies, stub) = session(
+            lc3,
+            &["g", "p8", "P1=1234", "p1", "p9", "pa", "P3=12", "Gzz"],
+        );
+
+        // R0 to R7, PC and PSR
+        assert_eq!(
+            replies[0],
+            "0000 0000 0000 0000 0000 0000 2fff beef 3000 0002".replace(' ', "")
+        );
+        assert_eq!(replies[1..5], ["3000", "OK", "1234", "0002"]);
+        assert_eq!(replies[5..], ["E01", "E01", "E01"]);
+        assert_eq!(stub.processor().reg(RegAddr::One), 0x1234);
+    }
+
+    #[test]
+    fn write_all_registers() {
+        // User mode, with R6 given in user mode
+        let values = "0001 0002 0003 0004 0005 0006 4000 0008 3004 8001".replace(' ', "");
+        let (replies, stub) = session(load(COUNTDOWN), &[&format!("G{values}"), "g"]);
+        assert_eq!(replies, ["OK", values.as_str()]);
+
+        let lc3 = stub.processor();
+        assert!(!lc3.privileged());
+        assert_eq!(lc3.reg(STACK_REG), 0x4000);
+        assert_eq!(lc3.saved_stack_reg(), 0x2FFF);
+        assert_eq!(lc3.pc(), 0x3004);
+    }
+
+    #[test]
+    fn memory() {
+        let (replies, stub) = session(
+            load(COUNTDOWN),
+            &[
+                "m6000,4",
+                "m6000,3",
+                "M8000,4:12345678",
+                "m8000,4",
+                "M8000,3:123456",
+                "M8001,2:1234",
+                "m8000",
+                "m1fffe,4",
+                "m20000,2",
+            ],
+        );
+        assert_eq!(replies[..2], ["52601265", "526012"]);
+        assert_eq!(replies[2..4], ["OK", "12345678"]);
+        assert_eq!(replies[4..7], ["E01", "E01", "E01"]);
+        assert_eq!(stub.processor().mem(0x4001), 0x5678);
+
+        // Reads stop at the end of memory
+        assert_eq!(replies[7..], ["0000", "E01"]);
+    }
+
+    #[test]
+    fn consecutive_words() {
+        let (replies, _) = session(load(COUNTDOWN), &["m6000,2", "m6002,2", "m6001,2"]);
+
+        // AND R1, R1, #0 then ADD R1, R1, #5, and the bytes across them
+        assert_eq!(replies, ["5260", "1265", "6012"]);
+    }
+
+    #[test]
+    fn repeated_breakpoint() {
+        let (replies, stub) = session(load(COUNTDOWN), &["Z0,6006,2", "Z0,6006,2"]);
+        assert_eq!(replies, ["OK", "OK"]);
+        assert_eq!(stub.debugger().breakpoints().count(), 1);
+
+        let (replies, stub) = session(load(COUNTDOWN), &["Z0,6006,2", "Z0,6006,2", "z0,6006,2"]);
+        assert_eq!(replies, ["OK", "OK", "OK"]);
+        assert_eq!(stub.debugger().breakpoints().count(), 0);
+    }
+
+    #[test]
+    fn breakpoints() {
+        let (replies, stub) = session(
+            load(COUNTDOWN),
+            &[
+                "?",
+                "Z0,6006,2",
+                "c",
+                "p1",
+                "c",
+                "p1",
+                "z0,6006,2",
+                "Z1,6000,2",
+                "c",
+                "?",
+                "s",
+            ],
+        );
+        assert_eq!(replies[0], "S05");
+        assert_eq!(replies[1..4], ["OK", "T05swbreak:;", "0005"]);
+        assert_eq!(replies[4..6], ["T05swbreak:;", "0004"]);
+
+        // Only software breakpoints are supported
+        assert_eq!(replies[6..8], ["OK", ""]);
+        assert_eq!(replies[8..], ["W00", "W00", "W00"]);
+        assert_eq!(stub.debugger().breakpoints().count(), 0);
+
+        let (lc3, scripted) = stub.into_inner();
+        assert!(lc3.is_halted());
+        assert_eq!(scripted.output_string(), ".....");
+    }
+
+    #[test]
+    fn stepping() {
+        let (replies, stub) = session(load(COUNTDOWN), &["s", "s", "p8", "s600c", "p8"]);
+        assert_eq!(replies, ["S05", "S05", "3002", "W00", "3007"]);
+        assert_eq!(stub.processor().reg(RegAddr::One), 5);
+    }
+
+    #[test]
+    fn stop_replies() {
+        let failures = [
+            (StepFailure::InvalidInstruction(0xD000).into(), "S04"),
+            (StepFailure::AccessViolation(0xFE00).into(), "S0b"),
+            (StepFailure::LastAddress.into(), "S0b"),
+            (StepFailure::Halted.into(), "W00"),
+            (StepFailure::ClockDisabled.into(), "W00"),
+            (ExecutionFailure::NoKeyboard, "S17"),
+            (ExecutionFailure::Trace(io::ErrorKind::StorageFull), "S06"),
+        ];
+        for (failure, reply) in failures {
+            assert_eq!(Stop::from(&failure).reply(), reply, "{failure}");
+        }
+
+        // The reserved opcode, in strict mode
+        let lc3 = load(".ORIG x3000\n.FILL xD000\n.END");
+        let (replies, _) = session(lc3, &["c", "?"]);
+        assert_eq!(replies, ["S04", "S04"]);
+    }
+
+    #[test]
+    fn queries() {
+        let (replies, _) = session(
+            load(COUNTDOWN),
+            &[
+                "qSupported:multiprocess+;swbreak+",
+                "qXfer:features:read:target.xml:0,10",
+                &format!("qXfer:features:read:target.xml:10,{:x}", TARGET_XML.len()),
+                "qAttached",
+                "vMustReplyEmpty",
+            ],
+        );
+        assert!(
+            replies[0].contains("qXfer:features:read+"),
+            "{}",
+            replies[0]
+        );
+        assert_eq!(replies[1], format!("m{}", &TARGET_XML[..0x10]));
+        assert_eq!(replies[2], format!("l{}", &TARGET_XML[0x10..]));
+        assert_eq!(replies[3..], ["1", ""]);
+        assert!(TARGET_XML.contains(r#"<reg name="psr" bitsize="16""#));
+    }
+
+    #[test]
+    fn acks() {
+        let mut input = frame(b"p8");
+        // Corrupt checksum, then a NAK of the next reply
+        input.extend(b"$p8#00");
+        input.extend(frame(b"p1"));
+        input.push(b'-');
+        input.extend(frame(b"QStartNoAckMode"));
+        input.extend(frame(b"p8"));
+
+        let (output, _) = serve_bytes(load(COUNTDOWN), input);
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            ["+$3000#c3", "-", "+$0000#c0$0000#c0", "+$OK#9a", "$3000#c3",].concat()
+        );
+    }
+
+    #[test]
+    fn interrupt() {
+        let lc3 = load(".ORIG x3000\nLOOP BR LOOP\n.END");
+        let mut input = frame(b"c");
+        input.push(INTERRUPT);
+        input.extend(frame(b"p8"));
+
+        let (output, stub) = serve_bytes(lc3, input);
+        assert_eq!(replies(&output), ["S02", "3000"]);
+        assert!(!stub.processor().is_halted());
+    }
+
+    #[test]
+    fn detach() {
+        let (replies, _) = session(load(COUNTDOWN), &["D", "p8"]);
+        assert_eq!(replies, ["OK"]);
+        let (replies, _) = session(load(COUNTDOWN), &["k", "p8"]);
+        assert!(replies.is_empty());
+    }
+}
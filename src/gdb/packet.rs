@@ -0,0 +1,99 @@
+//! Packet framing for the remote serial protocol.
+//!
+//! A packet is `$`, its data, `#` and a two digit hex checksum of the data.
+//! Within the data, `#`, `$`, `}` and `*` are escaped as `}` followed by the
+//! byte XOR `0x20`.
+
+use std::fmt::Write;
+
+/// Sent by GDB to interrupt a running target.
+pub const INTERRUPT: u8 = 0x03;
+
+/// Modulo 256 sum of `data`.
+pub fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// Frames `data` as a packet, escaping it as needed.
+pub fn frame(data: &[u8]) -> Vec<u8> {
+    let data = escape(data);
+    let mut packet = Vec::with_capacity(data.len() + 4);
+    packet.push(b'$');
+    packet.extend_from_slice(&data);
+    packet.push(b'#');
+    packet.extend(format!("{:02x}", checksum(&data)).bytes());
+    packet
+}
+
+/// Escapes the bytes that cannot appear in packet data.
+pub fn escape(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+    for &byte in data {
+        if matches!(byte, b'#' | b'$' | b'}' | b'*') {
+            escaped.extend([b'}', byte ^ 0x20]);
+        } else {
+            escaped.push(byte);
+        }
+    }
+    escaped
+}
+
+/// Reverses [`escape`].
+pub fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut bytes = data.iter();
+    let mut unescaped = Vec::with_capacity(data.len());
+    while let Some(&byte) = bytes.next() {
+        if byte == b'}' {
+            unescaped.extend(bytes.next().map(|next| next ^ 0x20));
+        } else {
+            unescaped.push(byte);
+        }
+    }
+    unescaped
+}
+
+/// Lowercase hex of every byte in `bytes`.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+/// Bytes of a hex string, or `None` if it is not one.
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn framing() {
+        assert_eq!(frame(b"OK"), b"$OK#9a");
+        assert_eq!(frame(b""), b"$#00");
+        assert_eq!(frame(b"a#b"), b"$a}\x03b#43");
+    }
+
+    #[test]
+    fn escaping() {
+        let data = b"$#}*plain";
+        assert_eq!(escape(data), b"}\x04}\x03}]}\x0aplain");
+        assert_eq!(unescape(&escape(data)), data);
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(to_hex(&[0x30, 0x00, 0xFF]), "3000ff");
+        assert_eq!(from_hex("3000ff"), Some(vec![0x30, 0x00, 0xFF]));
+        assert_eq!(from_hex("300"), None);
+        assert_eq!(from_hex("zz"), None);
+    }
+}
@@ -1,13 +1,144 @@
 //! Asynchronous [`LC3`] execution.
 
-use std::future::Future;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
 
-use crate::executors::LC3;
+use crate::executors::{StepFailure, LC3};
 
 use super::ExecutionFailure;
 
+/// Steps taken between yields to the executor in [`step_continue`].
+pub const YIELD_INTERVAL: u64 = 1024;
+
 /// Progress an LC3 program asynchronously, taking control of memory mapping.
+///
+/// The future returned by [`Self::step`] borrows the harness and the
+/// processor, so a harness can await its own I/O in the middle of a step.
+///
+/// # Migrating from `AsyncHarness::Output`
+///
+/// Earlier versions named the future with an `Output` associated type, which
+/// could not borrow either of them. That is a breaking change for
+/// implementors: remove `type Output`, and return `impl Future` from `step`
+/// or write it as an `async fn`. Wrapped synchronous steps keep working as
+/// `ready(...)`. Callers only need to stop naming `H::Output`.
 pub trait AsyncHarness {
-    type Output: Future<Output = Result<(), ExecutionFailure>>;
-    fn step<P: LC3>(&mut self, processor: &mut P) -> Self::Output;
+    fn step<P: LC3>(
+        &mut self,
+        processor: &mut P,
+    ) -> impl Future<Output = Result<(), ExecutionFailure>>;
+}
+
+/// Runs `processor's` program to completion on `harness`.
+///
+/// Yields to the executor every [`YIELD_INTERVAL`] steps, so long running
+/// programs share their thread with other tasks.
+pub async fn step_continue<H: AsyncHarness, P: LC3>(
+    harness: &mut H,
+    processor: &mut P,
+) -> Result<(), ExecutionFailure> {
+    loop {
+        for _ in 0..YIELD_INTERVAL {
+            if let Err(e) = harness.step(processor).await {
+                if e == ExecutionFailure::LC3(StepFailure::Halted) {
+                    return Ok(());
+                } else {
+                    return Err(e);
+                }
+            }
+        }
+        YieldNow(false).await;
+    }
+}
+
+/// Limited run of `processor's` program to completion on `harness`.
+///
+/// Makes at most `limit` steps, yielding like [`step_continue`]. Returns true
+/// if program ran to completition, returns false if the program reached its
+/// limit.
+pub async fn lim_step_continue<H: AsyncHarness, P: LC3>(
+    harness: &mut H,
+    processor: &mut P,
+    limit: u64,
+) -> Result<bool, ExecutionFailure> {
+    for step in 0..limit {
+        if step != 0 && step % YIELD_INTERVAL == 0 {
+            YieldNow(false).await;
+        }
+
+        if let Err(e) = harness.step(processor).await {
+            if e == ExecutionFailure::LC3(StepFailure::Halted) {
+                return Ok(true);
+            } else {
+                return Err(e);
+            }
+        }
+    }
+
+    // Reached the step limit
+    Ok(false)
+}
+
+/// Pending once, immediately asking to be polled again.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures_executor::block_on;
+    use futures_util::{future::poll_fn, pin_mut};
+
+    use crate::{executors::core::load_program, harnesses::simple::IgnoreIO};
+
+    #[test]
+    fn yields_periodically() {
+        // Spins forever
+        let mut lc3 = load_program(".ORIG x3000\nLOOP BR LOOP\n.END", false);
+
+        let mut harness = IgnoreIO;
+        let run = step_continue(&mut harness, &mut lc3);
+        pin_mut!(run);
+
+        // Each poll hands control back instead of running forever
+        block_on(poll_fn(|cx| {
+            for _ in 0..3 {
+                assert!(run.as_mut().poll(cx).is_pending());
+            }
+            Poll::Ready(())
+        }));
+    }
+
+    #[test]
+    fn limited() {
+        let mut lc3 = load_program(".ORIG x3000\nLOOP BR LOOP\n.END", false);
+
+        let limit = YIELD_INTERVAL * 3 + 1;
+        assert_eq!(
+            block_on(lim_step_continue(&mut IgnoreIO, &mut lc3, limit)),
+            Ok(false)
+        );
+
+        lc3.halt();
+        assert_eq!(
+            block_on(lim_step_continue(&mut IgnoreIO, &mut lc3, limit)),
+            Ok(true)
+        );
+    }
 }
@@ -0,0 +1,316 @@
+//! Console I/O through the keyboard and display device registers.
+
+use std::{
+    future::{ready, Future},
+    io::{stdin, stdout, Read, Stdin, Stdout, Write},
+};
+
+use futures_util::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{defs::IO_PRIORITY, executors::LC3};
+
+use super::{r#async::AsyncHarness, sync::SyncHarness, ExecutionFailure};
+
+/// Connects the keyboard to a reader and the display to a writer.
+///
+/// Input is read one byte at a time, only once the program reads the keyboard
+/// or could take a keyboard interrupt with no key waiting, so programs that
+/// never read input never block.
+/// Display output is written and flushed after every step. The OS routines,
+/// including PUTSP's packed strings, work unmodified on top of this.
+///
+/// Running out of input is [`ExecutionFailure::NoKeyboard`], and failing to
+/// write is [`ExecutionFailure::NoConsole`].
+#[derive(Debug)]
+pub struct ConsoleIO<R, W> {
+    input: R,
+    output: W,
+}
+
+impl ConsoleIO<Stdin, Stdout> {
+    /// Console on the process's stdin and stdout.
+    pub fn stdio() -> Self {
+        Self::new(stdin(), stdout())
+    }
+}
+
+impl<R: Read, W: Write> ConsoleIO<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self { input, output }
+    }
+
+    /// Everything written to the display so far, for in-memory writers.
+    pub fn output(&self) -> &W {
+        &self.output
+    }
+
+    /// Input still to be read, for adding keys to in-memory readers.
+    pub fn input_mut(&mut self) -> &mut R {
+        &mut self.input
+    }
+
+    /// For taking what in-memory writers have collected.
+    pub fn output_mut(&mut self) -> &mut W {
+        &mut self.output
+    }
+
+    pub fn into_inner(self) -> (R, W) {
+        (self.input, self.output)
+    }
+
+    /// Reads the next byte of input.
+    pub fn read_key(&mut self) -> Result<u8, ExecutionFailure> {
+        let mut byte = [0];
+        match self.input.read(&mut byte) {
+            Ok(1) => Ok(byte[0]),
+            _ => Err(ExecutionFailure::NoKeyboard),
+        }
+    }
+
+    /// Writes `bytes` to the output and flushes it.
+    pub fn write_all(&mut self, bytes: &[u8]) -> Result<(), ExecutionFailure> {
+        self.output
+            .write_all(bytes)
+            .and_then(|_| self.output.flush())
+            .map_err(|_| ExecutionFailure::NoConsole)
+    }
+
+    /// Reads a byte into the keyboard if the next step wants one.
+    fn feed_keyboard<P: LC3>(&mut self, processor: &mut P) -> Result<(), ExecutionFailure> {
+        if wants_key(processor) {
+            let key = self.read_key()?;
+            processor.devices_mut().keyboard.press(key);
+        }
+        Ok(())
+    }
+
+    fn flush_display<P: LC3>(&mut self, processor: &mut P) -> Result<(), ExecutionFailure> {
+        let output = processor.devices_mut().display.take_output();
+        if output.is_empty() {
+            Ok(())
+        } else {
+            self.write_all(&output)
+        }
+    }
+}
+
+impl<R: Read, W: Write> SyncHarness for ConsoleIO<R, W> {
+    fn step<P: LC3>(&mut self, processor: &mut P) -> Result<(), ExecutionFailure> {
+        self.feed_keyboard(processor)?;
+        let result = processor.step();
+        self.flush_display(processor)?;
+        Ok(result?)
+    }
+}
+
+impl<R: Read, W: Write> AsyncHarness for ConsoleIO<R, W> {
+    fn step<P: LC3>(
+        &mut self,
+        processor: &mut P,
+    ) -> impl Future<Output = Result<(), ExecutionFailure>> {
+        ready(<Self as SyncHarness>::step(self, processor))
+    }
+}
+
+/// Whether the keyboard is empty while the next step reads it, or while a key
+/// would interrupt the program.
+fn wants_key<P: LC3>(processor: &P) -> bool {
+    let keyboard = &processor.devices().keyboard;
+    let interruptible = keyboard.interrupt_enabled() && IO_PRIORITY > processor.priority();
+    !keyboard.is_ready() && (processor.reads_keyboard() || interruptible)
+}
+
+/// [`ConsoleIO`] on async I/O, awaiting input and output instead of blocking.
+///
+/// Programs waiting on the keyboard suspend until input is available, so a
+/// run only occupies its thread while it is computing.
+#[derive(Debug)]
+pub struct AsyncConsoleIO<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> AsyncConsoleIO<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self { input, output }
+    }
+
+    /// Everything written to the display so far, for in-memory writers.
+    pub fn output(&self) -> &W {
+        &self.output
+    }
+
+    pub fn into_inner(self) -> (R, W) {
+        (self.input, self.output)
+    }
+
+    /// Waits for the next byte of input.
+    pub async fn read_key(&mut self) -> Result<u8, ExecutionFailure> {
+        let mut byte = [0];
+        match self.input.read(&mut byte).await {
+            Ok(1) => Ok(byte[0]),
+            _ => Err(ExecutionFailure::NoKeyboard),
+        }
+    }
+
+    /// Writes `bytes` to the output and flushes it.
+    pub async fn write_all(&mut self, bytes: &[u8]) -> Result<(), ExecutionFailure> {
+        match self.output.write_all(bytes).await {
+            Ok(()) => self.output.flush().await,
+            e => e,
+        }
+        .map_err(|_| ExecutionFailure::NoConsole)
+    }
+}
+
+impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> AsyncHarness for AsyncConsoleIO<R, W> {
+    async fn step<P: LC3>(&mut self, processor: &mut P) -> Result<(), ExecutionFailure> {
+        if wants_key(processor) {
+            let key = self.read_key().await?;
+            processor.devices_mut().keyboard.press(key);
+        }
+
+        let result = processor.step();
+
+        let output = processor.devices_mut().display.take_output();
+        if !output.is_empty() {
+            self.write_all(&output).await?;
+        }
+        Ok(result?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        defs::RegAddr,
+        executors::core::load_program,
+        harnesses::{
+            r#async,
+            sync::{lim_step_continue, step_continue},
+        },
+    };
+
+    /// Echoes one key through the device registers, then halts.
+    const ECHO: &str = r#"
+                .ORIG x3000
+        POLL    LDI R0, KBSR
+                BRzp POLL
+                LDI R0, KBDR
+                STI R0, DDR
+                HALT
+        KBSR    .FILL xFE00
+        KBDR    .FILL xFE02
+        DDR     .FILL xFE06
+                .END
+    "#;
+
+    #[test]
+    fn echo() {
+        let mut lc3 = load_program(ECHO, false);
+        let mut console = ConsoleIO::new(&b"ab"[..], Vec::new());
+
+        step_continue(&mut console, &mut lc3).unwrap();
+
+        let (input, output) = console.into_inner();
+        assert_eq!(output, b"a");
+        // Only the key that was read is consumed
+        assert_eq!(input, b"b");
+    }
+
+    /// Echoes keys from a keyboard interrupt until a newline, then halts.
+    const INTERRUPT_ECHO: &str = r#"
+                .ORIG x3000
+                LD R0, ISR_PTR
+                STI R0, KB_VEC
+                LD R0, IE
+                STI R0, KBSR
+        LOOP    LD R0, DONE
+                BRz LOOP
+                HALT
+        ISR     LDI R1, KBDR
+                STI R1, DDR
+                ADD R2, R1, #-10
+                BRnp RETURN
+                STI R2, KBSR
+                ADD R2, R2, #1
+                ST R2, DONE
+        RETURN  RTI
+        ISR_PTR .FILL ISR
+        KB_VEC  .FILL x0180
+        IE      .FILL x4000
+        KBSR    .FILL xFE00
+        KBDR    .FILL xFE02
+        DDR     .FILL xFE06
+        DONE    .FILL 0
+                .END
+    "#;
+
+    #[test]
+    fn interrupt_echo() {
+        let mut lc3 = load_program(INTERRUPT_ECHO, false);
+        let mut console = ConsoleIO::new(&b"ab\nc"[..], Vec::new());
+
+        step_continue(&mut console, &mut lc3).unwrap();
+
+        // Nothing is read while the service routine runs, or once the
+        // interrupt is disabled
+        let (input, output) = console.into_inner();
+        assert_eq!(output, b"ab\n");
+        assert_eq!(input, b"c");
+
+        let mut lc3 = load_program(INTERRUPT_ECHO, false);
+        let mut console = AsyncConsoleIO::new(&b"ab\nc"[..], Vec::new());
+        futures_executor::block_on(r#async::step_continue(&mut console, &mut lc3)).unwrap();
+        assert_eq!(console.into_inner(), (&b"c"[..], b"ab\n".to_vec()));
+    }
+
+    #[test]
+    fn out_of_input() {
+        let mut lc3 = load_program(ECHO, false);
+        let mut console = ConsoleIO::new(&b""[..], Vec::new());
+
+        // The first instruction polls the keyboard
+        assert_eq!(
+            lim_step_continue(&mut console, &mut lc3, 10),
+            Err(ExecutionFailure::NoKeyboard)
+        );
+
+        // Writing never needs input
+        let mut lc3 = load_program(
+            ".ORIG x3000\nSTI R0, DDR\nHALT\nDDR .FILL xFE06\n.END",
+            false,
+        );
+        lc3.set_reg(RegAddr::Zero, b'!'.into());
+        let mut console = ConsoleIO::new(&b""[..], Vec::new());
+        step_continue(&mut console, &mut lc3).unwrap();
+        assert_eq!(console.output(), b"!");
+    }
+
+    #[test]
+    fn async_echo() {
+        let mut lc3 = load_program(ECHO, false);
+        let mut console = AsyncConsoleIO::new(&b"ab"[..], Vec::new());
+
+        // Runs can be spawned onto multithreaded executors
+        fn assert_send<T: Send>(value: T) -> T {
+            value
+        }
+        let run = assert_send(r#async::step_continue(&mut console, &mut lc3));
+        futures_executor::block_on(run).unwrap();
+
+        let (input, output) = console.into_inner();
+        assert_eq!(output, b"a");
+        assert_eq!(input, b"b");
+
+        // Running out of input fails like the blocking console
+        let mut lc3 = load_program(ECHO, false);
+        let mut console = AsyncConsoleIO::new(&b""[..], Vec::new());
+        assert_eq!(
+            futures_executor::block_on(r#async::step_continue(&mut console, &mut lc3)),
+            Err(ExecutionFailure::NoKeyboard)
+        );
+    }
+}
@@ -1,10 +1,14 @@
 //! Structs to progress [`LC3`] programs.
 
+use std::io;
+
 use thiserror::Error;
 
 use crate::executors::StepFailure;
 
 pub mod r#async;
+pub mod console;
+pub mod scripted;
 pub mod simple;
 pub mod sync;
 
@@ -18,4 +22,6 @@ pub enum ExecutionFailure {
     NoConsole,
     #[error("No display is connected, cannot write for visual output")]
     NoDisplay,
+    #[error("Cannot record the step to the trace: {0}")]
+    Trace(io::ErrorKind),
 }
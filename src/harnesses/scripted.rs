@@ -0,0 +1,218 @@
+//! Deterministic console I/O from a pre-recorded script.
+
+use std::{
+    collections::VecDeque,
+    future::{ready, Future},
+};
+
+use crate::{defs::LC3MemAddr, executors::LC3, instruction::InstructionEnum};
+
+use super::{r#async::AsyncHarness, sync::SyncHarness, ExecutionFailure};
+
+/// A key to press on the keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Keystroke {
+    pub key: u8,
+    /// Steps to wait after the keyboard empties before pressing the key.
+    pub delay: u64,
+}
+
+/// A character written to the display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Written {
+    pub byte: u8,
+    /// Address of the instruction that wrote DDR.
+    pub pc: LC3MemAddr,
+    /// Address of the TRAP being serviced when the write happened, if any.
+    pub trap: Option<LC3MemAddr>,
+    /// Number of steps taken before the write.
+    pub step: u64,
+}
+
+/// Feeds the keyboard from a fixed script and records every display write.
+///
+/// The same program and script always produce the same transcript, which
+/// makes this suitable for automated comparisons. Once the script is used up,
+/// reading the keyboard with no key waiting is
+/// [`ExecutionFailure::NoKeyboard`] instead of an endless poll.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScriptedIO {
+    input: VecDeque<Keystroke>,
+    /// Steps the keyboard has been empty with the next key waiting.
+    waited: u64,
+    steps: u64,
+    trap: Option<LC3MemAddr>,
+    transcript: Vec<Written>,
+}
+
+impl ScriptedIO {
+    /// Presses each of `input` as soon as the keyboard is empty.
+    pub fn new<I: AsRef<[u8]>>(input: I) -> Self {
+        Self::with_delay(input, 0)
+    }
+
+    /// Presses each of `input` `delay` steps after the keyboard empties.
+    pub fn with_delay<I: AsRef<[u8]>>(input: I, delay: u64) -> Self {
+        Self::from_keystrokes(input.as_ref().iter().map(|&key| Keystroke { key, delay }))
+    }
+
+    pub fn from_keystrokes<I: IntoIterator<Item = Keystroke>>(input: I) -> Self {
+        Self {
+            input: input.into_iter().collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Number of steps taken so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Keystrokes that have not been pressed yet.
+    pub fn remaining_input(&self) -> impl Iterator<Item = &Keystroke> {
+        self.input.iter()
+    }
+
+    /// Every display write so far, in order.
+    pub fn transcript(&self) -> &[Written] {
+        &self.transcript
+    }
+
+    /// Every byte written to the display so far.
+    pub fn output(&self) -> Vec<u8> {
+        self.transcript.iter().map(|written| written.byte).collect()
+    }
+
+    /// [`Self::output`] as text, replacing invalid UTF-8.
+    pub fn output_string(&self) -> String {
+        String::from_utf8_lossy(&self.output()).into_owned()
+    }
+
+    fn feed_keyboard<P: LC3>(&mut self, processor: &mut P) -> Result<(), ExecutionFailure> {
+        if processor.devices().keyboard.is_ready() {
+            return Ok(());
+        }
+
+        match self.input.front() {
+            Some(next) if self.waited >= next.delay => {
+                processor.devices_mut().keyboard.press(next.key);
+                self.input.pop_front();
+                self.waited = 0;
+            }
+            Some(_) => self.waited += 1,
+            None if processor.reads_keyboard() => return Err(ExecutionFailure::NoKeyboard),
+            None => (),
+        }
+        Ok(())
+    }
+}
+
+impl SyncHarness for ScriptedIO {
+    fn step<P: LC3>(&mut self, processor: &mut P) -> Result<(), ExecutionFailure> {
+        self.feed_keyboard(processor)?;
+
+        let pc = processor.pc();
+        if self.trap.is_none() && matches!(processor.cur_inst(), Some(InstructionEnum::Trap(_))) {
+            self.trap = Some(pc);
+        }
+
+        let result = processor.step();
+
+        for byte in processor.devices_mut().display.take_output() {
+            self.transcript.push(Written {
+                byte,
+                pc,
+                trap: self.trap,
+                step: self.steps,
+            });
+        }
+        if self.trap.map(|trap| trap.wrapping_add(1)) == Some(processor.pc()) {
+            self.trap = None;
+        }
+        self.steps += 1;
+
+        Ok(result?)
+    }
+}
+
+impl AsyncHarness for ScriptedIO {
+    fn step<P: LC3>(
+        &mut self,
+        processor: &mut P,
+    ) -> impl Future<Output = Result<(), ExecutionFailure>> {
+        ready(<Self as SyncHarness>::step(self, processor))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        defs::RegAddr,
+        executors::core::load_program,
+        harnesses::sync::{lim_step_continue, step_continue},
+    };
+
+    /// Counts polls into R1 until a key arrives, then echoes it.
+    const COUNTED_ECHO: &str = r#"
+                .ORIG x3000
+        POLL    ADD R1, R1, #1
+                LDI R0, KBSR
+                BRzp POLL
+                LDI R0, KBDR
+                STI R0, DDR
+                HALT
+        KBSR    .FILL xFE00
+        KBDR    .FILL xFE02
+        DDR     .FILL xFE06
+                .END
+    "#;
+
+    #[test]
+    fn transcript() {
+        let mut lc3 = load_program(COUNTED_ECHO, false);
+        let mut scripted = ScriptedIO::new("ab");
+
+        step_continue(&mut scripted, &mut lc3).unwrap();
+
+        assert_eq!(lc3.reg(RegAddr::One), 1);
+        assert_eq!(scripted.output_string(), "a");
+        assert_eq!(
+            scripted.transcript(),
+            [Written {
+                byte: b'a',
+                pc: 0x3004,
+                trap: None,
+                step: 4,
+            }]
+        );
+        // The next key is waiting, unread
+        assert_eq!(scripted.remaining_input().count(), 0);
+        assert!(lc3.devices().keyboard.is_ready());
+    }
+
+    #[test]
+    fn keyboard_timing() {
+        let mut lc3 = load_program(COUNTED_ECHO, false);
+        let mut scripted = ScriptedIO::with_delay("a", 5);
+
+        step_continue(&mut scripted, &mut lc3).unwrap();
+
+        // Pressed before step 5, first seen by the poll at step 7
+        assert_eq!(lc3.reg(RegAddr::One), 3);
+        assert_eq!(scripted.transcript()[0].step, 10);
+    }
+
+    #[test]
+    fn out_of_input() {
+        let mut lc3 = load_program(COUNTED_ECHO, false);
+        let mut scripted = ScriptedIO::new("");
+
+        assert_eq!(
+            lim_step_continue(&mut scripted, &mut lc3, 100),
+            Err(ExecutionFailure::NoKeyboard)
+        );
+        assert_eq!(scripted.steps(), 1);
+    }
+}
@@ -1,6 +1,6 @@
 //! Dangerous and/or inefficient LC3 executors.
 
-use std::future::{ready, Ready};
+use std::future::{ready, Future};
 
 use crate::{
     executors::LC3,
@@ -20,8 +20,10 @@ impl SyncHarness for IgnoreIO {
 }
 
 impl AsyncHarness for IgnoreIO {
-    type Output = Ready<Result<(), ExecutionFailure>>;
-    fn step<P: LC3>(&mut self, processor: &mut P) -> Self::Output {
+    fn step<P: LC3>(
+        &mut self,
+        processor: &mut P,
+    ) -> impl Future<Output = Result<(), ExecutionFailure>> {
         ready(<Self as SyncHarness>::step(self, processor))
     }
 }
@@ -46,8 +48,10 @@ impl SyncHarness for FailIO {
 }
 
 impl AsyncHarness for FailIO {
-    type Output = Ready<Result<(), ExecutionFailure>>;
-    fn step<P: LC3>(&mut self, processor: &mut P) -> Self::Output {
+    fn step<P: LC3>(
+        &mut self,
+        processor: &mut P,
+    ) -> impl Future<Output = Result<(), ExecutionFailure>> {
         ready(<Self as SyncHarness>::step(self, processor))
     }
 }
@@ -1,3 +1,5 @@
+use std::fmt;
+
 use crate::{
     defs::{LC3Word, RegAddr},
     executors::LC3,
@@ -106,6 +108,23 @@ impl From<IAdd> for LC3Word {
     }
 }
 
+impl fmt::Display for IAdd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Reg(InstrRegReg {
+                dest_reg,
+                src_reg_1,
+                src_reg_2,
+            }) => write!(f, "ADD {dest_reg}, {src_reg_1}, {src_reg_2}"),
+            Self::Imm(InstrRegSignedImm {
+                dest_reg,
+                src_reg,
+                imm,
+            }) => write!(f, "ADD {dest_reg}, {src_reg}, #{imm}"),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::instruction::TWELVE_SET;
@@ -196,4 +215,16 @@ mod tests {
             assert_eq!(LC3Word::from(IAdd::parse(valid).unwrap()), valid)
         }
     }
+
+    #[test]
+    fn display() {
+        let full = BASE_OPCODE | (1 << 9) | BITMASK_5 | 5;
+        assert_eq!(IAdd::parse(full).unwrap().to_string(), "ADD R1, R0, #5");
+
+        let full = BASE_OPCODE | (7 << 9) | (3 << 6) | BITMASK_5 | 0b11111;
+        assert_eq!(IAdd::parse(full).unwrap().to_string(), "ADD R7, R3, #-1");
+
+        let full = BASE_OPCODE | (2 << 9) | (3 << 6) | 4;
+        assert_eq!(IAdd::parse(full).unwrap().to_string(), "ADD R2, R3, R4");
+    }
 }
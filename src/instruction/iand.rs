@@ -1,3 +1,5 @@
+use std::fmt;
+
 use crate::{
     defs::{LC3Word, RegAddr},
     executors::LC3,
@@ -5,6 +7,7 @@ use crate::{
         args::{InstrRegImm, InstrRegReg},
         get_bit, get_bits, get_opcode, set_condition_codes, Instruction, InstructionErr,
     },
+    util::shift_to_signed,
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -99,6 +102,26 @@ impl From<IAnd> for LC3Word {
     }
 }
 
+impl fmt::Display for IAnd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Reg(InstrRegReg {
+                dest_reg,
+                src_reg_1,
+                src_reg_2,
+            }) => write!(f, "AND {dest_reg}, {src_reg_1}, {src_reg_2}"),
+            Self::Imm(InstrRegImm {
+                dest_reg,
+                src_reg,
+                imm,
+            }) => {
+                let imm = shift_to_signed::<{ LC3Word::BITS - 5 }>(*imm);
+                write!(f, "AND {dest_reg}, {src_reg}, #{imm}")
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::instruction::TWELVE_SET;
@@ -189,4 +212,16 @@ mod tests {
             assert_eq!(LC3Word::from(IAnd::parse(valid).unwrap()), valid)
         }
     }
+
+    #[test]
+    fn display() {
+        let full = BASE_OPCODE | (1 << 9) | BITMASK_5;
+        assert_eq!(IAnd::parse(full).unwrap().to_string(), "AND R1, R0, #0");
+
+        let full = BASE_OPCODE | (7 << 9) | (3 << 6) | BITMASK_5 | 0b11110;
+        assert_eq!(IAnd::parse(full).unwrap().to_string(), "AND R7, R3, #-2");
+
+        let full = BASE_OPCODE | (2 << 9) | (3 << 6) | 4;
+        assert_eq!(IAnd::parse(full).unwrap().to_string(), "AND R2, R3, R4");
+    }
 }
@@ -1,8 +1,10 @@
+use std::fmt;
+
 use crate::{
     defs::{LC3Word, SignedLC3Word},
     executors::LC3,
     instruction::{
-        args::ConditionCodes, get_bit, get_bits, get_opcode, Instruction, InstructionErr,
+        args::ConditionCodes, get_bit, get_bits, get_opcode, Instruction, InstructionErr, PCOperand,
     },
     util::{apply_offset, shift_to_signed, shift_to_unsigned},
 };
@@ -76,6 +78,36 @@ impl From<IBranch> for LC3Word {
     }
 }
 
+impl IBranch {
+    pub(crate) fn fmt_at(&self, f: &mut fmt::Formatter<'_>, pc: PCOperand) -> fmt::Result {
+        let ConditionCodes {
+            positive,
+            negative,
+            zero,
+        } = self.cond_codes;
+
+        // No assembly syntax produces a branch that is never taken
+        if !(negative || zero || positive) {
+            return write!(f, "NOP");
+        }
+
+        write!(f, "BR")?;
+        for (set, flag) in [(negative, "n"), (zero, "z"), (positive, "p")] {
+            if set {
+                write!(f, "{flag}")?;
+            }
+        }
+        write!(f, " ")?;
+        pc.fmt(f, self.pc_offset)
+    }
+}
+
+impl fmt::Display for IBranch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_at(f, PCOperand::default())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::instruction::TWELVE_SET;
@@ -138,4 +170,15 @@ mod tests {
             assert_eq!(LC3Word::from(IBranch::parse(valid).unwrap()), valid)
         }
     }
+
+    #[test]
+    fn display() {
+        let full = BASE_OPCODE | (0b110 << 9) | 0b111111101;
+        assert_eq!(IBranch::parse(full).unwrap().to_string(), "BRnz #-3");
+
+        let full = BASE_OPCODE | (0b111 << 9) | 4;
+        assert_eq!(IBranch::parse(full).unwrap().to_string(), "BRnzp #4");
+
+        assert_eq!(IBranch::parse(BASE_OPCODE).unwrap().to_string(), "NOP");
+    }
 }
@@ -1,3 +1,5 @@
+use std::fmt;
+
 use crate::{
     defs::{LC3Word, RegAddr, STACK_REG},
     executors::LC3,
@@ -23,8 +25,12 @@ impl Instruction for IJump {
         let dest = match self {
             Self::Instr(base_reg) => processor.reg(base_reg),
             Self::PrivClear(base_reg) => {
+                // Keep the caller's stack rather than swapping to the saved one
+                let dest = processor.reg(base_reg);
+                let stack = processor.reg(STACK_REG);
                 processor.set_privileged(false);
-                processor.reg(base_reg)
+                processor.set_reg(STACK_REG, stack);
+                dest
             }
             Self::Ret => processor.reg(RegAddr::Seven),
             Self::InterRet => {
@@ -34,9 +40,9 @@ impl Instruction for IJump {
                     let stack_reg = processor.reg(STACK_REG);
 
                     // Pop PC and PSR from the supervisor stack
-                    let pc = processor.mem(stack_reg + 1);
-                    let psr = processor.mem(stack_reg + 2);
-                    processor.set_reg(STACK_REG, stack_reg - 2);
+                    let pc = processor.mem(stack_reg);
+                    let psr = processor.mem(stack_reg.wrapping_add(1));
+                    processor.set_reg(STACK_REG, stack_reg.wrapping_add(2));
 
                     // Restoring the status register also assigns STACK_REG
                     // correctly
@@ -98,6 +104,17 @@ impl From<IJump> for LC3Word {
     }
 }
 
+impl fmt::Display for IJump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Instr(base_reg) => write!(f, "JMP {base_reg}"),
+            Self::PrivClear(base_reg) => write!(f, "JMPT {base_reg}"),
+            Self::Ret => write!(f, "RET"),
+            Self::InterRet => write!(f, "RTI"),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::instruction::TWELVE_SET;
@@ -180,7 +197,10 @@ mod tests {
 
     mod rti {
         use crate::{
-            defs::{IO_PRIORITY, KEYBOARD_INTERRUPT, OS_SUPER_STACK, SUPERVISOR_SP_INIT},
+            defs::{
+                IO_PRIORITY, IR_VEC_TBL, KEYBOARD_INTERRUPT, OS_SUPER_STACK, SUPERVISOR_SP_INIT,
+                USER_SPACE,
+            },
             executors::{core::CoreLC3, StepFailure},
         };
 
@@ -209,10 +229,13 @@ mod tests {
             processor.set_privileged(false);
             processor.set_reg(STACK_REG, INIT_STACK_REG);
 
-            // Execute the original interrupt
+            // Execute the original interrupt, through the vector table
+            const HANDLER: LC3Word = 0x1000;
+            processor.set_mem(IR_VEC_TBL + KEYBOARD_INTERRUPT, HANDLER);
             processor.interrupt(KEYBOARD_INTERRUPT, Some(IO_PRIORITY));
             assert!(processor.privileged());
-            assert_eq!(processor.pc(), KEYBOARD_INTERRUPT + 0x0100);
+            assert_eq!(processor.priority(), IO_PRIORITY);
+            assert_eq!(processor.pc(), HANDLER);
             assert_eq!(processor.reg(STACK_REG), SUPERVISOR_SP_INIT - 2);
 
             // Execute the return jump
@@ -222,12 +245,19 @@ mod tests {
             assert_eq!(processor.pc(), OS_SUPER_STACK);
             assert_eq!(processor.reg(STACK_REG), INIT_STACK_REG);
             assert!(!processor.privileged());
+            assert_eq!(processor.priority(), 0);
+
+            // The supervisor stack is fully popped
+            processor.set_privileged(true);
+            assert_eq!(processor.reg(STACK_REG), SUPERVISOR_SP_INIT);
         }
 
         #[test]
         fn invalid_return() {
             let mut processor = CoreLC3::new();
+            processor.set_strict(true);
             processor.set_privileged(false);
+            processor.set_pc(USER_SPACE);
 
             // Fail this instruction due to insufficient perms
             processor.set_mem(processor.pc(), IJump::InterRet.into());
@@ -245,4 +275,12 @@ mod tests {
             )
         }
     }
+
+    #[test]
+    fn display() {
+        assert_eq!(IJump::Instr(RegAddr::Two).to_string(), "JMP R2");
+        assert_eq!(IJump::PrivClear(RegAddr::Six).to_string(), "JMPT R6");
+        assert_eq!(IJump::Ret.to_string(), "RET");
+        assert_eq!(IJump::InterRet.to_string(), "RTI");
+    }
 }
@@ -1,8 +1,11 @@
+use std::fmt;
+
 use crate::{
     defs::{LC3Word, RegAddr},
     executors::LC3,
     instruction::{
         args::InstrPCOffset11, get_bit, get_bits, get_opcode, Instruction, InstructionErr,
+        PCOperand,
     },
     util::{apply_offset, shift_to_signed, shift_to_unsigned},
 };
@@ -16,17 +19,18 @@ pub const JSR_OPCODE: u8 = 0b0100;
 
 impl Instruction for IJumpSubRoutine {
     fn execute<P: LC3>(self, processor: &mut P) -> Result<(), InstructionErr> {
-        processor.set_reg(RegAddr::Seven, processor.pc()); //save return address
+        let next_pc = processor.pc() + 1;
         let jump_addr = match self {
             Self::Offset(InstrPCOffset11 { pc_offset }) => {
                 //JSR
-                apply_offset(processor.pc(), pc_offset)
+                apply_offset(next_pc, pc_offset)
             }
             Self::Reg(base_reg) => {
-                //JSRR
+                //JSRR, read before R7 is overwritten
                 processor.reg(base_reg)
             }
         };
+        processor.set_reg(RegAddr::Seven, next_pc); //save return address
         processor.set_pc(jump_addr);
 
         Ok(())
@@ -65,6 +69,24 @@ impl From<IJumpSubRoutine> for LC3Word {
     }
 }
 
+impl IJumpSubRoutine {
+    pub(crate) fn fmt_at(&self, f: &mut fmt::Formatter<'_>, pc: PCOperand) -> fmt::Result {
+        match self {
+            Self::Offset(InstrPCOffset11 { pc_offset }) => {
+                write!(f, "JSR ")?;
+                pc.fmt(f, *pc_offset)
+            }
+            Self::Reg(base_reg) => write!(f, "JSRR {base_reg}"),
+        }
+    }
+}
+
+impl fmt::Display for IJumpSubRoutine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_at(f, PCOperand::default())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::instruction::TWELVE_SET;
@@ -137,4 +159,13 @@ mod tests {
             assert_eq!(LC3Word::from(IJumpSubRoutine::parse(valid).unwrap()), valid)
         }
     }
+
+    #[test]
+    fn display() {
+        let full = BASE_OPCODE | BITMASK_11 | 0b11111111110;
+        assert_eq!(IJumpSubRoutine::parse(full).unwrap().to_string(), "JSR #-2");
+
+        let full = BASE_OPCODE | (5 << 6);
+        assert_eq!(IJumpSubRoutine::parse(full).unwrap().to_string(), "JSRR R5");
+    }
 }
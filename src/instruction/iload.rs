@@ -1,9 +1,11 @@
+use std::fmt;
+
 use crate::{
     defs::{LC3MemAddr, LC3Word, RegAddr},
     executors::LC3,
     instruction::{
         args::{InstrOffset6, InstrPCOffset9},
-        get_bits, get_opcode, set_condition_codes, Instruction, InstructionErr,
+        get_bits, get_opcode, set_condition_codes, Instruction, InstructionErr, PCOperand,
     },
     util::{apply_offset, shift_to_signed, shift_to_unsigned},
 };
@@ -32,7 +34,7 @@ impl Instruction for ILoad {
                 pc_offset,
             }) => {
                 let target_addr = apply_offset(advanced_addr, pc_offset);
-                result = processor.mem(target_addr);
+                result = processor.load(target_addr);
                 processor.set_reg(target_reg, result);
             }
             Self::Indirect(InstrPCOffset9 {
@@ -40,8 +42,8 @@ impl Instruction for ILoad {
                 pc_offset,
             }) => {
                 let target_addr = apply_offset(advanced_addr, pc_offset);
-                let target_loc: u16 = processor.mem(target_addr);
-                result = processor.mem(target_loc);
+                let target_loc: u16 = processor.load(target_addr);
+                result = processor.load(target_loc);
                 processor.set_reg(target_reg, result);
             }
             Self::Reg(InstrOffset6 {
@@ -50,7 +52,7 @@ impl Instruction for ILoad {
                 offset,
             }) => {
                 let target_addr = apply_offset(processor.reg(base_reg), offset);
-                result = processor.mem(target_addr);
+                result = processor.load(target_addr);
                 processor.set_reg(target_reg, result);
             }
             Self::Addr(InstrPCOffset9 {
@@ -111,6 +113,40 @@ impl Instruction for ILoad {
     }
 }
 
+impl ILoad {
+    /// Address this load reads its result from, when executed at the
+    /// processor's PC.
+    ///
+    /// Follows LDI's pointer without side effects. `None` for LEA, which does
+    /// not read memory.
+    pub fn source_addr<P: LC3 + ?Sized>(&self, processor: &P) -> Option<LC3MemAddr> {
+        let advanced_addr = processor.pc().wrapping_add(1);
+
+        match *self {
+            Self::Std(InstrPCOffset9 { pc_offset, .. }) => {
+                Some(apply_offset(advanced_addr, pc_offset))
+            }
+            Self::Indirect(InstrPCOffset9 { pc_offset, .. }) => {
+                Some(processor.mem(apply_offset(advanced_addr, pc_offset)))
+            }
+            Self::Reg(InstrOffset6 {
+                base_reg, offset, ..
+            }) => Some(apply_offset(processor.reg(base_reg), offset)),
+            Self::Addr(_) => None,
+        }
+    }
+
+    /// Address of LDI's pointer, `None` for the direct loads.
+    pub fn pointer_addr<P: LC3 + ?Sized>(&self, processor: &P) -> Option<LC3MemAddr> {
+        match *self {
+            Self::Indirect(InstrPCOffset9 { pc_offset, .. }) => {
+                Some(apply_offset(processor.pc().wrapping_add(1), pc_offset))
+            }
+            _ => None,
+        }
+    }
+}
+
 impl From<ILoad> for LC3Word {
     fn from(value: ILoad) -> Self {
         const LD_BASE: LC3Word = (LD_OPCODE as LC3Word) << 12;
@@ -157,6 +193,36 @@ impl From<ILoad> for LC3Word {
     }
 }
 
+impl ILoad {
+    pub(crate) fn fmt_at(&self, f: &mut fmt::Formatter<'_>, pc: PCOperand) -> fmt::Result {
+        let (
+            name,
+            InstrPCOffset9 {
+                target_reg,
+                pc_offset,
+            },
+        ) = match self {
+            Self::Std(args) => ("LD", args),
+            Self::Indirect(args) => ("LDI", args),
+            Self::Addr(args) => ("LEA", args),
+            Self::Reg(InstrOffset6 {
+                target_reg,
+                base_reg,
+                offset,
+            }) => return write!(f, "LDR {target_reg}, {base_reg}, #{offset}"),
+        };
+
+        write!(f, "{name} {target_reg}, ")?;
+        pc.fmt(f, *pc_offset)
+    }
+}
+
+impl fmt::Display for ILoad {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_at(f, PCOperand::default())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -291,4 +357,41 @@ mod tests {
             assert_eq!(LC3Word::from(ILoad::parse(valid).unwrap()), valid)
         }
     }
+
+    #[test]
+    fn display() {
+        let full = ((LD_OPCODE as LC3Word) << 12) | (2 << 9) | 7;
+        assert_eq!(ILoad::parse(full).unwrap().to_string(), "LD R2, #7");
+
+        let full = ((LDI_OPCODE as LC3Word) << 12) | (3 << 9) | 0b111111111;
+        assert_eq!(ILoad::parse(full).unwrap().to_string(), "LDI R3, #-1");
+
+        let full = ((LEA_OPCODE as LC3Word) << 12) | 0x10;
+        assert_eq!(ILoad::parse(full).unwrap().to_string(), "LEA R0, #16");
+
+        let full = ((LDR_OPCODE as LC3Word) << 12) | (1 << 9) | (6 << 6) | 0b111110;
+        assert_eq!(ILoad::parse(full).unwrap().to_string(), "LDR R1, R6, #-2");
+    }
+
+    #[test]
+    fn source_addr() {
+        use crate::executors::core::CoreLC3;
+
+        let mut processor = CoreLC3::new();
+        processor.set_pc(0x3000);
+        processor.set_mem(0x3003, 0xFE00);
+        processor.set_reg(RegAddr::Six, 0x4000);
+
+        let ld = ILoad::parse(((LD_OPCODE as LC3Word) << 12) | 2).unwrap();
+        assert_eq!(ld.source_addr(&processor), Some(0x3003));
+
+        let ldi = ILoad::parse(((LDI_OPCODE as LC3Word) << 12) | 2).unwrap();
+        assert_eq!(ldi.source_addr(&processor), Some(0xFE00));
+
+        let ldr = ILoad::parse(((LDR_OPCODE as LC3Word) << 12) | (6 << 6) | 0b111110).unwrap();
+        assert_eq!(ldr.source_addr(&processor), Some(0x3FFE));
+
+        let lea = ILoad::parse(((LEA_OPCODE as LC3Word) << 12) | 2).unwrap();
+        assert_eq!(lea.source_addr(&processor), None);
+    }
 }
@@ -1,3 +1,5 @@
+use std::fmt;
+
 use crate::{
     defs::{LC3Word, RegAddr},
     executors::LC3,
@@ -54,6 +56,13 @@ impl From<INot> for LC3Word {
     }
 }
 
+impl fmt::Display for INot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let InstrRegOnly { dest_reg, src_reg } = self.0;
+        write!(f, "NOT {dest_reg}, {src_reg}")
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::instruction::TWELVE_SET;
@@ -111,4 +120,13 @@ mod tests {
             assert_eq!(LC3Word::from(INot::parse(valid).unwrap()), valid)
         }
     }
+
+    #[test]
+    fn display() {
+        let not = INot(InstrRegOnly {
+            dest_reg: RegAddr::Three,
+            src_reg: RegAddr::Two,
+        });
+        assert_eq!(not.to_string(), "NOT R3, R2");
+    }
 }
@@ -1,9 +1,11 @@
+use std::fmt;
+
 use crate::{
     defs::{LC3MemAddr, LC3Word, RegAddr},
     executors::LC3,
     instruction::{
         args::{InstrOffset6, InstrPCOffset9},
-        get_bits, get_opcode, Instruction, InstructionErr,
+        get_bits, get_opcode, Instruction, InstructionErr, PCOperand,
     },
     util::{apply_offset, shift_to_signed, shift_to_unsigned},
 };
@@ -36,7 +38,7 @@ impl Instruction for IStore {
                 pc_offset,
             }) => {
                 let calc_addr = apply_offset(advanced_addr, pc_offset);
-                let target_addr: u16 = processor.mem(calc_addr);
+                let target_addr: u16 = processor.load(calc_addr);
                 processor.set_mem(target_addr, processor.reg(target_reg));
             }
             Self::Reg(InstrOffset6 {
@@ -91,6 +93,35 @@ impl Instruction for IStore {
     }
 }
 
+impl IStore {
+    /// Address this store writes to, given the current processor state.
+    ///
+    /// Follows STI's pointer without side effects.
+    pub fn dest_addr<P: LC3 + ?Sized>(&self, processor: &P) -> LC3MemAddr {
+        let advanced_addr = processor.pc().wrapping_add(1);
+
+        match *self {
+            Self::Std(InstrPCOffset9 { pc_offset, .. }) => apply_offset(advanced_addr, pc_offset),
+            Self::Indirect(InstrPCOffset9 { pc_offset, .. }) => {
+                processor.mem(apply_offset(advanced_addr, pc_offset))
+            }
+            Self::Reg(InstrOffset6 {
+                base_reg, offset, ..
+            }) => apply_offset(processor.reg(base_reg), offset),
+        }
+    }
+
+    /// Address of STI's pointer, `None` for the direct stores.
+    pub fn pointer_addr<P: LC3 + ?Sized>(&self, processor: &P) -> Option<LC3MemAddr> {
+        match *self {
+            Self::Indirect(InstrPCOffset9 { pc_offset, .. }) => {
+                Some(apply_offset(processor.pc().wrapping_add(1), pc_offset))
+            }
+            _ => None,
+        }
+    }
+}
+
 impl From<IStore> for LC3Word {
     fn from(value: IStore) -> Self {
         const ST_BASE: LC3Word = (ST_OPCODE as LC3Word) << 12;
@@ -128,6 +159,35 @@ impl From<IStore> for LC3Word {
     }
 }
 
+impl IStore {
+    pub(crate) fn fmt_at(&self, f: &mut fmt::Formatter<'_>, pc: PCOperand) -> fmt::Result {
+        let (
+            name,
+            InstrPCOffset9 {
+                target_reg,
+                pc_offset,
+            },
+        ) = match self {
+            Self::Std(args) => ("ST", args),
+            Self::Indirect(args) => ("STI", args),
+            Self::Reg(InstrOffset6 {
+                target_reg,
+                base_reg,
+                offset,
+            }) => return write!(f, "STR {target_reg}, {base_reg}, #{offset}"),
+        };
+
+        write!(f, "{name} {target_reg}, ")?;
+        pc.fmt(f, *pc_offset)
+    }
+}
+
+impl fmt::Display for IStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_at(f, PCOperand::default())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -236,4 +296,16 @@ mod tests {
             assert_eq!(LC3Word::from(IStore::parse(valid).unwrap()), valid)
         }
     }
+
+    #[test]
+    fn display() {
+        let full = ((ST_OPCODE as LC3Word) << 12) | (2 << 9) | 7;
+        assert_eq!(IStore::parse(full).unwrap().to_string(), "ST R2, #7");
+
+        let full = ((STI_OPCODE as LC3Word) << 12) | (3 << 9) | 0b111111111;
+        assert_eq!(IStore::parse(full).unwrap().to_string(), "STI R3, #-1");
+
+        let full = ((STR_OPCODE as LC3Word) << 12) | (1 << 9) | (6 << 6) | 0b000011;
+        assert_eq!(IStore::parse(full).unwrap().to_string(), "STR R1, R6, #3");
+    }
 }
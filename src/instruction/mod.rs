@@ -1,6 +1,13 @@
 //TODO: TRAP instructions
 
-use crate::{defs::LC3Word, executors::LC3};
+use std::fmt;
+
+use crate::{
+    assembler::SymbolTable,
+    defs::{LC3MemAddr, LC3Word, SignedLC3Word},
+    executors::LC3,
+    util::apply_offset,
+};
 
 mod args;
 pub use args::*;
@@ -13,9 +20,14 @@ pub(crate) use ijumpsr::JSR_OPCODE;
 pub(crate) use iload::ALL_LOAD_OPCODES;
 pub(crate) use inot::NOT_OPCODE;
 pub(crate) use istore::ALL_STORE_OPCODES;
+use strum_macros::EnumDiscriminants;
 use thiserror::Error;
 pub(crate) use trap::TRAP_OPCODE;
 use util::*;
+pub(crate) use util::{get_opcode, set_condition_codes};
+
+/// Opcode the ISA reserves, raising an illegal opcode exception.
+pub(crate) const RESERVED_OPCODE: u8 = 0b1101;
 
 mod iadd;
 pub use iadd::IAdd;
@@ -56,11 +68,38 @@ pub trait Instruction: Into<LC3Word> {
         Self: Sized;
 }
 
+/// Renders the PC-relative operand of an instruction.
+///
+/// Without a known address, the operand is shown as the raw `#offset`.
+/// Otherwise it is shown as the target's label, falling back to its address.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct PCOperand<'a> {
+    /// Address of the instruction.
+    pub addr: Option<LC3MemAddr>,
+    pub symbols: Option<&'a SymbolTable>,
+}
+
+impl PCOperand<'_> {
+    pub fn fmt(&self, f: &mut fmt::Formatter<'_>, offset: SignedLC3Word) -> fmt::Result {
+        let Some(addr) = self.addr else {
+            return write!(f, "#{offset}");
+        };
+
+        // Offsets are applied to the incremented PC
+        let target = apply_offset(addr.wrapping_add(1), offset);
+        match self.symbols.and_then(|symbols| symbols.label(target)) {
+            Some(label) => write!(f, "{label}"),
+            None => write!(f, "x{target:04X}"),
+        }
+    }
+}
+
 /// Captures all LC-3 [`Instruction`]s.
 ///
 /// If this does not parse and execute, there is no valid LC-3 instruction
 /// that could parse and execute the given word.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, EnumDiscriminants)]
+#[strum_discriminants(name(InstructionClass), derive(PartialOrd, Ord, Hash))]
 pub enum InstructionEnum {
     IAdd(IAdd),
     IAnd(IAnd),
@@ -108,6 +147,79 @@ impl Instruction for InstructionEnum {
     }
 }
 
+impl InstructionEnum {
+    /// Display this instruction as located at `addr`.
+    ///
+    /// PC-relative operands are shown as the target address, or its label if
+    /// `symbols` has one.
+    pub fn display_at<'a>(
+        &self,
+        addr: LC3MemAddr,
+        symbols: Option<&'a SymbolTable>,
+    ) -> impl fmt::Display + 'a {
+        DisplayAt {
+            instr: *self,
+            pc: PCOperand {
+                addr: Some(addr),
+                symbols,
+            },
+        }
+    }
+
+    fn fmt_at(&self, f: &mut fmt::Formatter<'_>, pc: PCOperand) -> fmt::Result {
+        match self {
+            Self::IAdd(x) => fmt::Display::fmt(x, f),
+            Self::IAnd(x) => fmt::Display::fmt(x, f),
+            Self::INot(x) => fmt::Display::fmt(x, f),
+            Self::IBranch(x) => x.fmt_at(f, pc),
+            Self::IJump(x) => fmt::Display::fmt(x, f),
+            Self::IJumpSubRoutine(x) => x.fmt_at(f, pc),
+            Self::ILoad(x) => x.fmt_at(f, pc),
+            Self::IStore(x) => x.fmt_at(f, pc),
+            Self::Trap(x) => fmt::Display::fmt(x, f),
+        }
+    }
+}
+
+/// Canonical assembly syntax, e.g. `ADD R1, R0, #5`.
+///
+/// PC-relative operands are shown as offsets, e.g. `BRnz #-3`. See
+/// [`InstructionEnum::display_at`] to show them as addresses.
+impl fmt::Display for InstructionEnum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_at(f, PCOperand::default())
+    }
+}
+
+/// Mnemonics of the class, e.g. `LD/LDI/LDR/LEA`.
+impl fmt::Display for InstructionClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::IAdd => "ADD",
+            Self::IAnd => "AND",
+            Self::INot => "NOT",
+            Self::IBranch => "BR",
+            Self::IJump => "JMP/RET/RTI",
+            Self::IJumpSubRoutine => "JSR/JSRR",
+            Self::ILoad => "LD/LDI/LDR/LEA",
+            Self::IStore => "ST/STI/STR",
+            Self::Trap => "TRAP",
+        })
+    }
+}
+
+/// Output of [`InstructionEnum::display_at`].
+struct DisplayAt<'a> {
+    instr: InstructionEnum,
+    pc: PCOperand<'a>,
+}
+
+impl fmt::Display for DisplayAt<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.instr.fmt_at(f, self.pc)
+    }
+}
+
 impl From<InstructionEnum> for LC3Word {
     fn from(value: InstructionEnum) -> Self {
         match value {
@@ -1,41 +1,71 @@
+use std::fmt;
+
 use crate::{
-    defs::{LC3Word, RegAddr},
+    defs::{LC3Word, RegAddr, TRAP_VEC_TBL},
     executors::LC3,
     instruction::{get_bits, get_opcode, Instruction, InstructionErr},
 };
 
+/// TRAP, by vector.
+///
+/// The standard service routines have named variants. [`Self::Custom`] holds
+/// every other vector; use [`Self::from_vector`] to keep that invariant.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Trap {
-    Getc,  // 0x20
-    Out,   // 0x21
-    PutS,  // 0x22
-    In,    // 0x23
-    PutSp, // 0x24
-    Halt,  // 0x25
+    Getc,       // 0x20
+    Out,        // 0x21
+    PutS,       // 0x22
+    In,         // 0x23
+    PutSp,      // 0x24
+    Halt,       // 0x25
+    Custom(u8), // Any other vector
 }
 pub const TRAP_OPCODE: u8 = 0b1111;
 
-const GETC: u16 = 0x20;
-const OUT: u16 = 0x21;
-const PUTS: u16 = 0x22;
-const IN: u16 = 0x23;
-const PUTSP: u16 = 0x24;
-const HALT: u16 = 0x25;
+const GETC: u8 = 0x20;
+const OUT: u8 = 0x21;
+const PUTS: u8 = 0x22;
+const IN: u8 = 0x23;
+const PUTSP: u8 = 0x24;
+const HALT: u8 = 0x25;
+
+impl Trap {
+    /// TRAP for `vector`, named if it is a standard service routine.
+    pub const fn from_vector(vector: u8) -> Self {
+        match vector {
+            GETC => Self::Getc,
+            OUT => Self::Out,
+            PUTS => Self::PutS,
+            IN => Self::In,
+            PUTSP => Self::PutSp,
+            HALT => Self::Halt,
+            x => Self::Custom(x),
+        }
+    }
+
+    /// Index of this TRAP's service routine in the trap vector table.
+    pub const fn vector(&self) -> u8 {
+        match *self {
+            Self::Getc => GETC,
+            Self::Out => OUT,
+            Self::PutS => PUTS,
+            Self::In => IN,
+            Self::PutSp => PUTSP,
+            Self::Halt => HALT,
+            Self::Custom(x) => x,
+        }
+    }
+}
 
 impl Instruction for Trap {
     fn execute<P: LC3>(self, processor: &mut P) -> Result<(), InstructionErr> {
-        let vector = match self {
-            Trap::Getc => GETC,
-            Trap::Out => OUT,
-            Trap::PutS => PUTS,
-            Trap::In => IN,
-            Trap::PutSp => PUTSP,
-            Trap::Halt => {
-                processor.halt();
-                HALT
-            }
-        };
+        if self == Self::Halt {
+            processor.halt();
+        }
+        let vector = TRAP_VEC_TBL + LC3Word::from(self.vector());
 
+        // Privilege and the stack are left alone: the service routine
+        // returns through R7 with RET
         processor.set_reg(RegAddr::Seven, processor.pc() + 1);
         processor.set_pc(processor.mem(vector));
 
@@ -47,15 +77,7 @@ impl Instruction for Trap {
         Self: Sized,
     {
         if (get_opcode(word) == TRAP_OPCODE) && (get_bits(word, 11, 8) == 0) {
-            match get_bits(word, 7, 0) {
-                GETC => Some(Self::Getc),
-                OUT => Some(Self::Out),
-                PUTS => Some(Self::PutS),
-                IN => Some(Self::In),
-                PUTSP => Some(Self::PutSp),
-                HALT => Some(Self::Halt),
-                _ => None,
-            }
+            Some(Self::from_vector(get_bits(word, 7, 0) as u8))
         } else {
             None
         }
@@ -66,14 +88,23 @@ impl From<Trap> for LC3Word {
     fn from(value: Trap) -> Self {
         const BASE: LC3Word = (TRAP_OPCODE as LC3Word) << 12;
 
-        match value {
-            Trap::Getc => BASE | GETC,
-            Trap::Out => BASE | OUT,
-            Trap::PutS => BASE | PUTS,
-            Trap::In => BASE | IN,
-            Trap::PutSp => BASE | PUTSP,
-            Trap::Halt => BASE | HALT,
-        }
+        BASE | LC3Word::from(value.vector())
+    }
+}
+
+/// Written as the trap's alias, e.g. `HALT`, or as `TRAP x26` without one.
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Trap::Custom(vector) => return write!(f, "TRAP x{vector:02X}"),
+            Trap::Getc => "GETC",
+            Trap::Out => "OUT",
+            Trap::PutS => "PUTS",
+            Trap::In => "IN",
+            Trap::PutSp => "PUTSP",
+            Trap::Halt => "HALT",
+        };
+        write!(f, "{name}")
     }
 }
 
@@ -83,7 +114,7 @@ mod tests {
 
     const BASE_OPCODE: u16 = (TRAP_OPCODE as u16) << 12;
 
-    const ALL_VECS: [u16; 6] = [GETC, OUT, PUTS, IN, PUTSP, HALT];
+    const ALL_VECS: [u8; 6] = [GETC, OUT, PUTS, IN, PUTSP, HALT];
 
     const FULL_11: u16 = (1 << 12) - 1;
     const BITMASK_11_8: u16 = (FULL_11 >> 8) << 8;
@@ -101,10 +132,8 @@ mod tests {
 
     #[test]
     fn reject_invalid_parses() {
-        // Invalid when bottom 5 bits aren't set
-        let invalid_parses = (BASE_OPCODE..=LC3Word::MAX).filter(|word| {
-            ((word & BITMASK_11_8) != 0) || !ALL_VECS.contains(&(word & BITMASK_7_0))
-        });
+        // Invalid when bits 11 through 8 are set
+        let invalid_parses = (BASE_OPCODE..=LC3Word::MAX).filter(|word| (word & BITMASK_11_8) != 0);
 
         for invalid in invalid_parses {
             assert!(Trap::parse(invalid).is_none())
@@ -113,19 +142,88 @@ mod tests {
 
     #[test]
     fn parse() {
-        assert_eq!(Trap::parse(BASE_OPCODE | GETC).unwrap(), Trap::Getc);
-        assert_eq!(Trap::parse(BASE_OPCODE | OUT).unwrap(), Trap::Out);
-        assert_eq!(Trap::parse(BASE_OPCODE | PUTS).unwrap(), Trap::PutS);
-        assert_eq!(Trap::parse(BASE_OPCODE | IN).unwrap(), Trap::In);
-        assert_eq!(Trap::parse(BASE_OPCODE | HALT).unwrap(), Trap::Halt);
+        let parse = |vector: u8| Trap::parse(BASE_OPCODE | LC3Word::from(vector)).unwrap();
+
+        assert_eq!(parse(GETC), Trap::Getc);
+        assert_eq!(parse(OUT), Trap::Out);
+        assert_eq!(parse(PUTS), Trap::PutS);
+        assert_eq!(parse(IN), Trap::In);
+        assert_eq!(parse(HALT), Trap::Halt);
+        assert_eq!(parse(0x00), Trap::Custom(0x00));
+        assert_eq!(parse(0x26), Trap::Custom(0x26));
+        assert_eq!(parse(0xFF), Trap::Custom(0xFF));
+
+        // Named vectors are never custom
+        for vector in ALL_VECS {
+            assert!(!matches!(parse(vector), Trap::Custom(_)));
+        }
     }
 
     #[test]
     fn reconstruct() {
-        let valid_opcodes = ALL_VECS.into_iter().map(|vec| BASE_OPCODE | vec);
+        let valid_opcodes = (0..=BITMASK_7_0).map(|vec| BASE_OPCODE | vec);
 
         for valid in valid_opcodes {
             assert_eq!(LC3Word::from(Trap::parse(valid).unwrap()), valid)
         }
     }
+
+    #[test]
+    fn custom_vector() {
+        use crate::executors::core::CoreLC3;
+
+        let mut processor = CoreLC3::new();
+        processor.set_pc(0x3000);
+        processor.set_mem(0x0026, 0x1000);
+        processor.set_mem(0x3000, Trap::Custom(0x26).into());
+
+        processor.step().unwrap();
+        assert_eq!(processor.pc(), 0x1000);
+        assert_eq!(processor.reg(RegAddr::Seven), 0x3001);
+        assert!(!processor.is_halted());
+    }
+
+    #[test]
+    fn keeps_user_mode() {
+        use crate::{
+            defs::STACK_REG,
+            executors::{core::load_program, StepFailure},
+        };
+
+        // A service routine in user space, then a store to the vector table
+        let mut processor = load_program(
+            ".ORIG x3000
+TRAP x26
+STI R0, VEC
+VEC .FILL x0010
+.END",
+            false,
+        );
+        processor.populate(0x4000, [0xC1C0]);
+        processor.set_mem(0x0026, 0x4000);
+        processor.set_strict(true);
+        processor.set_privileged(false);
+        processor.set_reg(STACK_REG, 0xFE00);
+
+        // TRAP leaves the privilege and the stack alone, and RET returns
+        processor.step().unwrap();
+        assert_eq!(processor.pc(), 0x4000);
+        assert!(!processor.privileged());
+        assert_eq!(processor.reg(STACK_REG), 0xFE00);
+        processor.step().unwrap();
+        assert_eq!(processor.pc(), 0x3001);
+
+        assert_eq!(processor.step(), Err(StepFailure::AccessViolation(0x0010)));
+    }
+
+    #[test]
+    fn display() {
+        let parse = |vector: u8| Trap::parse(BASE_OPCODE | LC3Word::from(vector)).unwrap();
+
+        assert_eq!(parse(GETC).to_string(), "GETC");
+        assert_eq!(parse(PUTSP).to_string(), "PUTSP");
+        assert_eq!(parse(HALT).to_string(), "HALT");
+        assert_eq!(parse(0x26).to_string(), "TRAP x26");
+        assert_eq!(parse(0x05).to_string(), "TRAP x05");
+    }
 }
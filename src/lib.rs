@@ -1,6 +1,12 @@
 pub mod assembler;
+pub mod debugger;
 pub mod defs;
+pub mod devices;
+pub mod disassembler;
 pub mod executors;
+pub mod gdb;
 pub mod harnesses;
 pub mod instruction;
+pub mod os;
+pub mod trace;
 pub mod util;
@@ -0,0 +1,230 @@
+//! Operating system support, so user programs run without a separate OS.
+//!
+//! Either load [`LC3OS`], the OS PennSim ships with, or service the standard
+//! TRAPs natively with [`CoreLC3::set_native_os`].
+//!
+//! [`CoreLC3::set_native_os`]: crate::executors::core::CoreLC3::set_native_os
+
+use crate::{
+    defs::{LC3MemAddr, LC3Word, RegAddr, DISPLAY_DATA_REGISTER, KEYBOARD_DATA_REGISTER},
+    executors::{populate_from_bin, LC3},
+    instruction::{set_condition_codes, Trap},
+};
+
+/// PennSim's `lc3os.asm`, assembled into an object file.
+///
+/// Boots from x0200 into user mode at x3000. Identical to PennSim's own
+/// assembly of the source.
+pub const LC3OS: &[u8] = include_bytes!("../penn_sim/lc3os.obj");
+
+/// Prompt written by the IN TRAP, matching [`LC3OS`].
+pub const IN_PROMPT: &[u8] = b"\nInput a character> ";
+
+/// Loads [`LC3OS`] into `processor`.
+pub fn load_lc3os<P: LC3>(processor: &mut P) {
+    populate_from_bin(processor, LC3OS);
+}
+
+/// Services a standard TRAP in place of the OS routine.
+///
+/// Output goes to the display and input comes from the keyboard, so any
+/// harness serves the native OS as it would [`LC3OS`]. GETC and IN wait for
+/// a key without advancing, then return it in R0. Every TRAP leaves the
+/// return address in R7, other registers unchanged, and the condition codes
+/// as the [`LC3OS`] routine would.
+///
+/// `prompted` is the address of an IN that has already written its prompt.
+/// Returns false, changing nothing, for TRAPs without a standard routine.
+pub(crate) fn service_trap<P: LC3>(
+    processor: &mut P,
+    trap: Trap,
+    prompted: &mut Option<LC3MemAddr>,
+) -> bool {
+    let pc = processor.pc();
+    let r0 = processor.reg(RegAddr::Zero);
+
+    // Register the routine last loads, setting the condition codes
+    let last_loaded = match trap {
+        Trap::Getc => {
+            if !processor.devices().keyboard.is_ready() {
+                return true;
+            }
+            read_key(processor);
+            RegAddr::Zero
+        }
+        Trap::Out => {
+            display(processor, [r0]);
+            RegAddr::One
+        }
+        Trap::PutS => {
+            display(processor, string_at(processor, r0));
+            RegAddr::Seven
+        }
+        Trap::In => {
+            if *prompted != Some(pc) {
+                display(processor, IN_PROMPT.iter().map(|&byte| byte.into()));
+                *prompted = Some(pc);
+            }
+            if !processor.devices().keyboard.is_ready() {
+                return true;
+            }
+            *prompted = None;
+
+            let key = read_key(processor);
+            display(processor, [key, b'\n'.into()]);
+            RegAddr::Seven
+        }
+        Trap::PutSp => {
+            let bytes = string_at(processor, r0)
+                .into_iter()
+                .flat_map(|word| [word & 0xFF, word >> 8])
+                .take_while(|&byte| byte != 0);
+            display(processor, bytes);
+            RegAddr::Seven
+        }
+        Trap::Halt => {
+            processor.halt();
+            RegAddr::Seven
+        }
+        Trap::Custom(_) => return false,
+    };
+
+    let next = pc.wrapping_add(1);
+    processor.set_reg(RegAddr::Seven, next);
+    processor.set_pc(next);
+    set_condition_codes(processor, processor.reg(last_loaded));
+    true
+}
+
+/// Reads KBDR into R0.
+fn read_key<P: LC3>(processor: &mut P) -> LC3Word {
+    let key = processor.load(KEYBOARD_DATA_REGISTER);
+    processor.set_reg(RegAddr::Zero, key);
+    key
+}
+
+fn display<P: LC3, I: IntoIterator<Item = LC3Word>>(processor: &mut P, words: I) {
+    for word in words {
+        processor.set_mem(DISPLAY_DATA_REGISTER, word);
+    }
+}
+
+/// Words from `addr` up to the next zero word.
+fn string_at<P: LC3>(processor: &P, mut addr: LC3MemAddr) -> Vec<LC3Word> {
+    std::iter::from_fn(|| {
+        let word = processor.mem(addr);
+        addr = addr.wrapping_add(1);
+        (word != 0).then_some(word)
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        assembler::assemble,
+        executors::core::{load_program, CoreLC3},
+        harnesses::{
+            scripted::ScriptedIO,
+            sync::{step_continue, SyncHarness},
+        },
+    };
+
+    #[test]
+    fn bundled_image_matches_source() {
+        let mut bundled = CoreLC3::new();
+        load_lc3os(&mut bundled);
+
+        let mut assembled = CoreLC3::new();
+        assemble(include_str!("../penn_sim/lc3os.asm"))
+            .unwrap()
+            .populate(&mut assembled);
+
+        assert!(bundled.iter().eq(assembled.iter()));
+    }
+
+    /// Runs `source` at [`USER_SPACE`], typing `input`.
+    fn run(source: &str, native: bool, input: &str) -> (CoreLC3, String) {
+        let mut lc3 = load_program(source, native);
+        if !native {
+            load_lc3os(&mut lc3);
+        }
+
+        let mut scripted = ScriptedIO::with_delay(input, 3);
+        step_continue(&mut scripted, &mut lc3).unwrap();
+        (lc3, scripted.output_string())
+    }
+
+    #[test]
+    fn native_matches_lc3os() {
+        let source = r#"
+                    .ORIG x3000
+                    LEA R0, PROMPT
+                    PUTS
+                    GETC
+                    OUT
+                    IN
+                    ADD R1, R0, #0
+                    LEA R0, PACKED
+                    PUTSP
+                    HALT
+            PROMPT  .STRINGZ "Keys: "
+            PACKED  .FILL x4241     ; "AB"
+                    .FILL x0043     ; "C"
+                    .END
+        "#;
+
+        let (native, native_output) = run(source, true, "qr");
+        let (os, os_output) = run(source, false, "qr");
+
+        assert_eq!(native_output, os_output);
+        assert_eq!(native_output, "Keys: q\nInput a character> r\nABC");
+        assert_eq!(native.reg(RegAddr::One), b'r'.into());
+        assert_eq!(native.reg(RegAddr::Seven), 0x3009);
+        assert_eq!(native.reg(RegAddr::Seven), os.reg(RegAddr::Seven));
+    }
+
+    /// Registers and condition codes after `trap`, natively and on [`LC3OS`].
+    fn after_trap(trap: &str) -> [(LC3Word, LC3Word, [bool; 3]); 2] {
+        let source =
+            format!(".ORIG x3000\nLD R0, CHAR\nAND R1, R1, #0\n{trap}\nHALT\nCHAR .FILL x2D\n.END");
+        [true, false].map(|native| {
+            let mut lc3 = load_program(&source, native);
+            if !native {
+                load_lc3os(&mut lc3);
+            }
+
+            let mut scripted = ScriptedIO::new("x");
+            while lc3.pc() != 0x3003 {
+                <ScriptedIO as SyncHarness>::step(&mut scripted, &mut lc3).unwrap();
+            }
+            (
+                lc3.reg(RegAddr::Zero),
+                lc3.reg(RegAddr::Seven),
+                [lc3.negative_cond(), lc3.zero_cond(), lc3.positive_cond()],
+            )
+        })
+    }
+
+    #[test]
+    fn native_registers() {
+        for trap in ["GETC", "OUT", "PUTS", "IN", "PUTSP"] {
+            let [native, os] = after_trap(trap);
+            assert_eq!(native, os, "{trap}");
+        }
+
+        // OUT leaves the codes from the restored R1
+        assert_eq!(after_trap("OUT")[0], (0x2D, 0x3003, [false, true, false]));
+    }
+
+    #[test]
+    fn native_halt() {
+        let mut lc3 = load_program(".ORIG x3000\nHALT\n.END", true);
+
+        assert_eq!(lc3.step(), Ok(()));
+        assert!(lc3.is_halted());
+        assert_eq!(lc3.reg(RegAddr::Seven), 0x3001);
+    }
+}
@@ -0,0 +1,306 @@
+//! Compact binary trace format.
+//!
+//! A trace is [`MAGIC`] and a [`VERSION`] byte, then one entry per step:
+//! the PC, the instruction word and the number of changes, then each change
+//! as a tag byte and its fields. Every word is big endian, like object files.
+//!
+//! | Tag | Change   | Fields                  |
+//! |-----|----------|-------------------------|
+//! | 0   | PC       | old, new                |
+//! | 1   | Register | register byte, old, new |
+//! | 2   | Memory   | address, old, new       |
+//! | 3   | Device   | address, value          |
+//! | 4   | PSR      | old, new                |
+//! | 5   | Halted   | old byte, new byte      |
+
+use std::io::{self, ErrorKind, Read, Write};
+
+use thiserror::Error;
+
+use crate::{
+    defs::{LC3Word, RegAddr},
+    executors::Change,
+};
+
+use super::{StepRecord, TraceSink};
+
+/// Start of every binary trace.
+pub const MAGIC: [u8; 4] = *b"LC3T";
+/// Current format version.
+pub const VERSION: u8 = 1;
+
+const PC: u8 = 0;
+const REG: u8 = 1;
+const MEM: u8 = 2;
+const DEVICE: u8 = 3;
+const PSR: u8 = 4;
+const HALTED: u8 = 5;
+
+#[derive(Debug, Error)]
+pub enum TraceError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Not a binary trace")]
+    BadMagic,
+    #[error("Trace format version {0} is not supported")]
+    UnsupportedVersion(u8),
+    #[error("The trace ends partway through a step")]
+    Truncated,
+    #[error("{0} is not a known change tag")]
+    UnknownChange(u8),
+    #[error("{0} is not a register")]
+    BadRegister(u8),
+}
+
+/// Writes [`StepRecord`]s in the binary format.
+#[derive(Debug)]
+pub struct BinaryWriter<W> {
+    output: W,
+}
+
+impl<W: Write> BinaryWriter<W> {
+    /// Starts a trace on `output`, writing the header.
+    pub fn new(mut output: W) -> io::Result<Self> {
+        output.write_all(&MAGIC)?;
+        output.write_all(&[VERSION])?;
+        Ok(Self { output })
+    }
+
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+}
+
+impl<W: Write> TraceSink for BinaryWriter<W> {
+    fn record(&mut self, record: &StepRecord) -> io::Result<()> {
+        let count = u16::try_from(record.changes.len())
+            .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "too many changes in one step"))?;
+
+        let mut bytes = Vec::with_capacity(6 + 7 * record.changes.len());
+        push_words(&mut bytes, [record.pc, record.word, count]);
+
+        for change in &record.changes {
+            match *change {
+                Change::Pc { old, new } => {
+                    bytes.push(PC);
+                    push_words(&mut bytes, [old, new]);
+                }
+                Change::Reg { reg, old, new } => {
+                    bytes.extend([REG, reg.into()]);
+                    push_words(&mut bytes, [old, new]);
+                }
+                Change::Mem { addr, old, new } => {
+                    bytes.push(MEM);
+                    push_words(&mut bytes, [addr, old, new]);
+                }
+                Change::Device { addr, value } => {
+                    bytes.push(DEVICE);
+                    push_words(&mut bytes, [addr, value]);
+                }
+                Change::Psr { old, new } => {
+                    bytes.push(PSR);
+                    push_words(&mut bytes, [old, new]);
+                }
+                Change::Halted { old, new } => bytes.extend([HALTED, old.into(), new.into()]),
+            }
+        }
+
+        self.output.write_all(&bytes)
+    }
+}
+
+fn push_words<const N: usize>(bytes: &mut Vec<u8>, words: [LC3Word; N]) {
+    for word in words {
+        bytes.extend(word.to_be_bytes());
+    }
+}
+
+/// Reads [`StepRecord`]s from the binary format, in order.
+#[derive(Debug)]
+pub struct BinaryReader<R> {
+    input: R,
+}
+
+impl<R: Read> BinaryReader<R> {
+    /// Reads the header from `input`, checking the version.
+    pub fn new(mut input: R) -> Result<Self, TraceError> {
+        let mut header = [0; 5];
+        input.read_exact(&mut header).map_err(|e| match e.kind() {
+            ErrorKind::UnexpectedEof => TraceError::BadMagic,
+            _ => e.into(),
+        })?;
+
+        if header[..4] != MAGIC {
+            return Err(TraceError::BadMagic);
+        }
+        match header[4] {
+            VERSION => Ok(Self { input }),
+            version => Err(TraceError::UnsupportedVersion(version)),
+        }
+    }
+
+    fn read_record(&mut self) -> Result<Option<StepRecord>, TraceError> {
+        // A trace may only end between steps
+        let mut first = [0; 1];
+        if self.input.read(&mut first)? == 0 {
+            return Ok(None);
+        }
+        let pc = LC3Word::from_be_bytes([first[0], self.byte()?]);
+        let word = self.word()?;
+        let count = self.word()?;
+
+        let changes = (0..count)
+            .map(|_| self.change())
+            .collect::<Result<_, _>>()?;
+        Ok(Some(StepRecord { pc, word, changes }))
+    }
+
+    fn change(&mut self) -> Result<Change, TraceError> {
+        Ok(match self.byte()? {
+            PC => Change::Pc {
+                old: self.word()?,
+                new: self.word()?,
+            },
+            REG => {
+                let reg = self.byte()?;
+                Change::Reg {
+                    reg: RegAddr::try_from(reg).map_err(|_| TraceError::BadRegister(reg))?,
+                    old: self.word()?,
+                    new: self.word()?,
+                }
+            }
+            MEM => Change::Mem {
+                addr: self.word()?,
+                old: self.word()?,
+                new: self.word()?,
+            },
+            DEVICE => Change::Device {
+                addr: self.word()?,
+                value: self.word()?,
+            },
+            PSR => Change::Psr {
+                old: self.word()?,
+                new: self.word()?,
+            },
+            HALTED => Change::Halted {
+                old: self.byte()? != 0,
+                new: self.byte()? != 0,
+            },
+            tag => return Err(TraceError::UnknownChange(tag)),
+        })
+    }
+
+    fn byte(&mut self) -> Result<u8, TraceError> {
+        let mut byte = [0; 1];
+        self.input.read_exact(&mut byte).map_err(truncated)?;
+        Ok(byte[0])
+    }
+
+    fn word(&mut self) -> Result<LC3Word, TraceError> {
+        let mut word = [0; 2];
+        self.input.read_exact(&mut word).map_err(truncated)?;
+        Ok(LC3Word::from_be_bytes(word))
+    }
+}
+
+fn truncated(e: io::Error) -> TraceError {
+    match e.kind() {
+        ErrorKind::UnexpectedEof => TraceError::Truncated,
+        _ => e.into(),
+    }
+}
+
+impl<R: Read> Iterator for BinaryReader<R> {
+    type Item = Result<StepRecord, TraceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::trace::test::traced;
+
+    fn encode(records: &[StepRecord]) -> Vec<u8> {
+        let mut writer = BinaryWriter::new(Vec::new()).unwrap();
+        for record in records {
+            writer.record(record).unwrap();
+        }
+        writer.into_inner()
+    }
+
+    #[test]
+    fn round_trip() {
+        let (_, _, records) = traced();
+        let bytes = encode(&records);
+
+        let read: Vec<_> = BinaryReader::new(bytes.as_slice())
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(read, records);
+    }
+
+    #[test]
+    fn layout() {
+        let record = StepRecord {
+            pc: 0x3000,
+            word: 0xF025,
+            changes: vec![
+                Change::Reg {
+                    reg: RegAddr::Seven,
+                    old: 0,
+                    new: 0x3001,
+                },
+                Change::Halted {
+                    old: false,
+                    new: true,
+                },
+            ],
+        };
+
+        assert_eq!(
+            encode(&[record]),
+            [
+                b'L', b'C', b'3', b'T', 1, // Header
+                0x30, 0x00, 0xF0, 0x25, 0x00, 0x02, // PC, word, count
+                1, 7, 0x00, 0x00, 0x30, 0x01, // R7
+                5, 0, 1, // Halted
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_traces() {
+        let (_, _, records) = traced();
+        let bytes = encode(&records);
+
+        assert!(matches!(
+            BinaryReader::new(&b"LC3X\x01"[..]),
+            Err(TraceError::BadMagic)
+        ));
+        assert!(matches!(
+            BinaryReader::new(&b"LC3T\x02"[..]),
+            Err(TraceError::UnsupportedVersion(2))
+        ));
+
+        let mut truncated = BinaryReader::new(&bytes[..bytes.len() - 1]).unwrap();
+        assert!(matches!(
+            truncated.nth(records.len() - 1),
+            Some(Err(TraceError::Truncated))
+        ));
+
+        let unknown = [
+            &MAGIC[..],
+            &[VERSION, 0x30, 0x00, 0x10, 0x00, 0x00, 0x01, 9],
+        ]
+        .concat();
+        assert!(matches!(
+            BinaryReader::new(unknown.as_slice()).unwrap().next(),
+            Some(Err(TraceError::UnknownChange(9)))
+        ));
+    }
+}
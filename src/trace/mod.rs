@@ -0,0 +1,330 @@
+//! Step by step recordings of everything a program does.
+//!
+//! Wrap a harness in a [`Tracer`] to send a [`StepRecord`] of every step to
+//! a [`TraceSink`], such as a [`binary::BinaryWriter`] or a
+//! [`text::TextWriter`]. Binary traces are read back with
+//! [`binary::BinaryReader`], to [`replay`] or [`diff`].
+
+use std::io;
+
+use crate::{
+    defs::{LC3MemAddr, LC3Word},
+    executors::{Change, LC3},
+    harnesses::{sync::SyncHarness, ExecutionFailure},
+    instruction::{Instruction, InstructionEnum},
+};
+
+pub mod binary;
+pub mod text;
+
+/// Everything a single step did.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StepRecord {
+    /// Address of the instruction.
+    pub pc: LC3MemAddr,
+    /// Instruction word at [`Self::pc`].
+    pub word: LC3Word,
+    /// Every change made by the step, in order.
+    pub changes: Vec<Change>,
+}
+
+impl StepRecord {
+    /// The decoded instruction, if [`Self::word`] is one.
+    pub fn inst(&self) -> Option<InstructionEnum> {
+        InstructionEnum::parse(self.word)
+    }
+
+    /// Repeats this step's changes on `processor`, without executing it.
+    pub fn apply<P: LC3>(&self, processor: &mut P) {
+        for change in &self.changes {
+            change.apply(processor);
+        }
+    }
+
+    /// Undoes this step's changes on `processor`, which must have every
+    /// later step reverted already.
+    pub fn revert<P: LC3>(&self, processor: &mut P) {
+        for change in self.changes.iter().rev() {
+            change.revert(processor);
+        }
+    }
+}
+
+/// Destination for [`StepRecord`]s.
+pub trait TraceSink {
+    fn record(&mut self, record: &StepRecord) -> io::Result<()>;
+}
+
+impl TraceSink for Vec<StepRecord> {
+    fn record(&mut self, record: &StepRecord) -> io::Result<()> {
+        self.push(record.clone());
+        Ok(())
+    }
+}
+
+/// Records every step taken on `harness` into `sink`.
+///
+/// Turns on [`LC3::set_recording`] for the processor. Steps that fail
+/// without changing anything, such as stepping while halted, are not
+/// recorded.
+///
+/// If the sink fails, the step returns [`ExecutionFailure::Trace`], and so
+/// does every later step without executing, so a trace never skips a step.
+#[derive(Debug)]
+pub struct Tracer<H, S> {
+    harness: H,
+    sink: S,
+    error: Option<io::ErrorKind>,
+}
+
+impl<H: SyncHarness, S: TraceSink> Tracer<H, S> {
+    pub fn new(harness: H, sink: S) -> Self {
+        Self {
+            harness,
+            sink,
+            error: None,
+        }
+    }
+
+    pub fn harness(&self) -> &H {
+        &self.harness
+    }
+
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    pub fn into_inner(self) -> (H, S) {
+        (self.harness, self.sink)
+    }
+}
+
+impl<H: SyncHarness, S: TraceSink> SyncHarness for Tracer<H, S> {
+    fn step<P: LC3>(&mut self, processor: &mut P) -> Result<(), ExecutionFailure> {
+        if let Some(kind) = self.error {
+            return Err(ExecutionFailure::Trace(kind));
+        }
+
+        let pc = processor.pc();
+        let word = processor.mem(pc);
+
+        processor.set_recording(true);
+        let result = self.harness.step(processor);
+        let changes = processor.take_changes();
+
+        if result.is_ok() || !changes.is_empty() {
+            if let Err(e) = self.sink.record(&StepRecord { pc, word, changes }) {
+                self.error = Some(e.kind());
+                return Err(ExecutionFailure::Trace(e.kind()));
+            }
+        }
+        result
+    }
+}
+
+/// Repeats every step of a trace on `processor`, without executing them.
+///
+/// Starting from the state the trace was recorded from, this reproduces the
+/// state after each step. Device writes are not repeated.
+pub fn replay<P: LC3, I: IntoIterator<Item = StepRecord>>(processor: &mut P, records: I) {
+    for record in records {
+        record.apply(processor);
+    }
+}
+
+/// First step where two traces differ.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Divergence {
+    /// Number of matching steps before this one.
+    pub step: u64,
+    /// The left step, or `None` if the left trace ended first.
+    pub left: Option<StepRecord>,
+    /// The right step, or `None` if the right trace ended first.
+    pub right: Option<StepRecord>,
+}
+
+/// Finds the first step where `left` and `right` differ, if any.
+pub fn diff<L, R>(left: L, right: R) -> Option<Divergence>
+where
+    L: IntoIterator<Item = StepRecord>,
+    R: IntoIterator<Item = StepRecord>,
+{
+    let mut left = left.into_iter();
+    let mut right = right.into_iter();
+
+    let mut step = 0;
+    loop {
+        match (left.next(), right.next()) {
+            (None, None) => return None,
+            (left, right) if left != right => return Some(Divergence { step, left, right }),
+            _ => step += 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        defs::RegAddr,
+        executors::core::{load_program, CoreLC3},
+        harnesses::{scripted::ScriptedIO, simple::IgnoreIO, sync::step_continue},
+    };
+
+    /// Doubles R1 three times, storing each result.
+    pub(super) const DOUBLES: &str = r#"
+                .ORIG x3000
+                ADD R1, R1, #1
+                LEA R3, RESULT
+                AND R2, R2, #0
+                ADD R2, R2, #3
+        LOOP    ADD R1, R1, R1
+                STR R1, R3, #0
+                ADD R3, R3, #1
+                ADD R2, R2, #-1
+                BRp LOOP
+                LEA R0, MSG
+                PUTS
+                HALT
+        MSG     .STRINGZ "ok"
+        RESULT  .BLKW 3
+                .END
+    "#;
+
+    /// Records [`DOUBLES`], returning the processors before and after.
+    pub(super) fn traced() -> (CoreLC3, CoreLC3, Vec<StepRecord>) {
+        let before = load_program(DOUBLES, true);
+        let mut after = before.clone();
+
+        let mut tracer = Tracer::new(ScriptedIO::new(""), Vec::new());
+        step_continue(&mut tracer, &mut after).unwrap();
+        let (scripted, records) = tracer.into_inner();
+        assert_eq!(scripted.output_string(), "ok");
+
+        (before, after, records)
+    }
+
+    #[test]
+    fn records_steps() {
+        let (_, _, records) = traced();
+
+        // 4 setup, 5 per loop, then LEA, PUTS and HALT
+        assert_eq!(records.len(), 4 + 5 * 3 + 3);
+        assert_eq!(
+            records[0],
+            StepRecord {
+                pc: 0x3000,
+                word: 0x1261,
+                changes: vec![
+                    Change::Reg {
+                        reg: RegAddr::One,
+                        old: 0,
+                        new: 1
+                    },
+                    Change::Psr {
+                        old: 0x0002,
+                        new: 0x0001
+                    },
+                    Change::Pc {
+                        old: 0x3000,
+                        new: 0x3001
+                    },
+                ],
+            }
+        );
+        assert!(matches!(records[0].inst(), Some(InstructionEnum::IAdd(_))));
+
+        // The store, then PUTS writing the display
+        assert!(records[5].changes.contains(&Change::Mem {
+            addr: 0x300F,
+            old: 0,
+            new: 2
+        }));
+        assert!(records[20].changes.contains(&Change::Device {
+            addr: 0xFE06,
+            value: b'k'.into()
+        }));
+        assert!(records[21].changes.contains(&Change::Halted {
+            old: false,
+            new: true
+        }));
+    }
+
+    /// Takes as many more records as it holds, then fails.
+    struct FullSink(usize);
+
+    impl TraceSink for FullSink {
+        fn record(&mut self, _: &StepRecord) -> io::Result<()> {
+            match self.0.checked_sub(1) {
+                Some(left) => {
+                    self.0 = left;
+                    Ok(())
+                }
+                None => Err(io::ErrorKind::StorageFull.into()),
+            }
+        }
+    }
+
+    #[test]
+    fn sink_failure() {
+        let mut lc3 = load_program(DOUBLES, true);
+        let mut tracer = Tracer::new(IgnoreIO, FullSink(2));
+
+        // Fails on the step that couldn't be recorded
+        let failure = ExecutionFailure::Trace(io::ErrorKind::StorageFull);
+        assert_eq!(step_continue(&mut tracer, &mut lc3), Err(failure));
+        assert_eq!(lc3.pc(), 0x3003);
+        assert!(!lc3.is_halted());
+
+        // Then refuses to step, even with room again
+        tracer.sink.0 = 1;
+        assert_eq!(tracer.step(&mut lc3), Err(failure));
+        assert_eq!(lc3.pc(), 0x3003);
+    }
+
+    #[test]
+    fn replay_and_revert() {
+        let (before, after, records) = traced();
+
+        let mut replayed = before.clone();
+        replay(&mut replayed, records.iter().cloned());
+        assert_eq!(replayed.pc(), after.pc());
+        assert!(replayed.iter().eq(after.iter()));
+        assert!(replayed.is_halted());
+
+        for record in records.iter().rev() {
+            record.revert(&mut replayed);
+        }
+        assert_eq!(replayed.pc(), before.pc());
+        assert_eq!(
+            replayed.processor_status_reg(),
+            before.processor_status_reg()
+        );
+        assert!(replayed.iter().eq(before.iter()));
+        assert!(!replayed.is_halted());
+    }
+
+    #[test]
+    fn diff_traces() {
+        let (_, _, records) = traced();
+        assert_eq!(diff(records.clone(), records.clone()), None);
+
+        // Starting R1 at 1 instead changes the first step
+        let mut lc3 = load_program(DOUBLES, true);
+        lc3.set_reg(RegAddr::One, 1);
+        let mut tracer = Tracer::new(IgnoreIO, Vec::new());
+        step_continue(&mut tracer, &mut lc3).unwrap();
+        let (_, other) = tracer.into_inner();
+
+        let divergence = diff(records.clone(), other.clone()).unwrap();
+        assert_eq!(divergence.step, 0);
+        assert_eq!(divergence.left.as_ref(), records.first());
+        assert_eq!(divergence.right.as_ref(), other.first());
+
+        // A trace that stops early
+        let divergence = diff(records.clone(), records[..5].to_vec()).unwrap();
+        assert_eq!(divergence.step, 5);
+        assert_eq!(divergence.right, None);
+    }
+}
@@ -0,0 +1,190 @@
+//! Human readable trace format.
+//!
+//! Each step is one line, as the [`fmt::Display`] of its [`StepRecord`]: the
+//! address, the instruction word and its disassembly, then every change.
+//!
+//! ```text
+//! x3000  x1261  ADD R1, R1, #1          R1 x0000 -> x0001, CC z -> p
+//! x3005  x72C0  STR R1, R3, #0          x300F x0000 -> x0002
+//! ```
+//!
+//! Moving the PC to the next instruction is left out, and runs of ASCII
+//! written to a device are shown as one string, such as `xFE06 <- "Hi\n"`.
+
+use std::{
+    fmt,
+    io::{self, Write},
+};
+
+use crate::{
+    defs::{LC3MemAddr, LC3Word},
+    disassembler::disassemble_word,
+    executors::Change,
+};
+
+use super::{StepRecord, TraceSink};
+
+/// Bits of the PSR other than the condition codes.
+const STATUS_MASK: LC3Word = !0b111;
+
+/// Writes [`StepRecord`]s in the text format.
+#[derive(Debug)]
+pub struct TextWriter<W> {
+    output: W,
+}
+
+impl<W: Write> TextWriter<W> {
+    pub fn new(output: W) -> Self {
+        Self { output }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+}
+
+impl<W: Write> TraceSink for TextWriter<W> {
+    fn record(&mut self, record: &StepRecord) -> io::Result<()> {
+        writeln!(self.output, "{record}")
+    }
+}
+
+impl fmt::Display for StepRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = disassemble_word(self.word, self.pc, None);
+        write!(f, "x{:04X}  x{:04X}  {text:<24}", self.pc, self.word)?;
+
+        let next = self.pc.wrapping_add(1);
+        let mut shown = self
+            .changes
+            .iter()
+            .filter(|change| !matches!(change, Change::Pc { new, .. } if *new == next))
+            .peekable();
+
+        let mut separator = "";
+        while let Some(change) = shown.next() {
+            f.write_str(separator)?;
+            separator = ", ";
+
+            // Runs of text written to a device are shown as a string
+            let Some((addr, first)) = device_char(change) else {
+                write!(f, "{change}")?;
+                continue;
+            };
+            let mut text = String::from(first);
+            while let Some(c) = shown
+                .peek()
+                .and_then(|next| device_char(next))
+                .and_then(|(next_addr, c)| (next_addr == addr).then_some(c))
+            {
+                text.push(c);
+                shown.next();
+            }
+            write!(f, "x{addr:04X} <- {text:?}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The address and ASCII character of a device write, if it is one.
+fn device_char(change: &Change) -> Option<(LC3MemAddr, char)> {
+    match *change {
+        Change::Device { addr, value } => u8::try_from(value)
+            .ok()
+            .filter(u8::is_ascii)
+            .map(|byte| (addr, byte.into())),
+        _ => None,
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Pc { old, new } => write!(f, "PC x{old:04X} -> x{new:04X}"),
+            Self::Reg { reg, old, new } => write!(f, "{reg} x{old:04X} -> x{new:04X}"),
+            Self::Mem { addr, old, new } => write!(f, "x{addr:04X} x{old:04X} -> x{new:04X}"),
+            Self::Device { addr, value } => write!(f, "x{addr:04X} <- x{value:04X}"),
+            Self::Psr { old, new } if old & STATUS_MASK == new & STATUS_MASK => {
+                write!(f, "CC {} -> {}", cond_codes(old), cond_codes(new))
+            }
+            Self::Psr { old, new } => write!(f, "PSR x{old:04X} -> x{new:04X}"),
+            Self::Halted { new: true, .. } => write!(f, "halted"),
+            Self::Halted { new: false, .. } => write!(f, "unhalted"),
+        }
+    }
+}
+
+/// `n`, `z` or `p` for the set condition code of `psr`, or `-` for none.
+fn cond_codes(psr: LC3Word) -> &'static str {
+    match psr & 0b111 {
+        0b100 => "n",
+        0b010 => "z",
+        0b001 => "p",
+        0 => "-",
+        _ => "?",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{defs::RegAddr, trace::test::traced};
+
+    #[test]
+    fn lines() {
+        let (_, _, records) = traced();
+
+        let mut writer = TextWriter::new(Vec::new());
+        for record in &records {
+            writer.record(record).unwrap();
+        }
+        let text = String::from_utf8(writer.into_inner()).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+
+        assert_eq!(lines.len(), records.len());
+        assert_eq!(
+            lines[0],
+            "x3000  x1261  ADD R1, R1, #1          R1 x0000 -> x0001, CC z -> p"
+        );
+        assert_eq!(
+            lines[5],
+            "x3005  x72C0  STR R1, R3, #0          x300F x0000 -> x0002"
+        );
+        // The last loop falls through, the others jump back
+        assert_eq!(
+            lines[8],
+            "x3008  x03FB  BRp x3004               PC x3008 -> x3004"
+        );
+        assert_eq!(
+            lines[20],
+            "x300A  xF022  PUTS                    xFE06 <- \"ok\", R7 x0000 -> x300B, CC p -> p"
+        );
+        assert_eq!(
+            lines[21],
+            "x300B  xF025  HALT                    halted, R7 x300B -> x300C, CC p -> p"
+        );
+    }
+
+    #[test]
+    fn changes() {
+        let psr = Change::Psr {
+            old: 0x8002,
+            new: 0x0002,
+        };
+        assert_eq!(psr.to_string(), "PSR x8002 -> x0002");
+
+        let reg = Change::Reg {
+            reg: RegAddr::Six,
+            old: 0x3000,
+            new: 0x2FFE,
+        };
+        assert_eq!(reg.to_string(), "R6 x3000 -> x2FFE");
+
+        let device = Change::Device {
+            addr: 0xFE06,
+            value: 0x0100,
+        };
+        assert_eq!(device.to_string(), "xFE06 <- x0100");
+    }
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use paste::paste;
+
+mod asm {
+    use super::*;
+
+    use lc3sim_project::{
+        assembler::{assemble, SymbolTable},
+        disassembler::disassemble_source,
+    };
+
+    macro_rules! cmp_test {
+        ( $name:ident, $path:literal ) => {
+            paste! {
+                #[test]
+                fn [<$name _asm>]() {
+                    let compiled = static_compiled!($path);
+
+                    let assembled = assemble(compiled.asm()).unwrap();
+                    assert_eq!(assembled.blocks.len(), 1);
+                    assert_eq!(*assembled.blocks[0].obj(), **compiled.obj());
+
+                    // PennSim writes labels upper case
+                    let symbols: SymbolTable = assembled
+                        .symbols
+                        .iter()
+                        .map(|(label, addr)| (label.to_uppercase(), addr))
+                        .collect();
+                    let penn_symbols = SymbolTable::read_sym(compiled.sym().as_bytes()).unwrap();
+                    assert_eq!(symbols, penn_symbols);
+
+                    // Labelled disassembly reassembles to the same words
+                    let block = &assembled.blocks[0];
+                    let source = disassemble_source(
+                        block.origin,
+                        block.words.iter().copied(),
+                        Some(&assembled.symbols),
+                    );
+                    assert_eq!(assemble(&source).unwrap().blocks, assembled.blocks);
+                }
+            }
+        };
+    }
+
+    cmp_test!(mult_10, "../test_data/unca/split_apart/mult_10.asm");
+    cmp_test!(rev_string, "../test_data/unca/split_apart/rev_string.asm");
+    cmp_test!(char_count, "../test_data/unca/split_apart/char_count.asm");
+    cmp_test!(r1_pop, "../test_data/unca/split_apart/r1_pop.asm");
+    cmp_test!(xor, "../test_data/unca/split_apart/xor.asm");
+}
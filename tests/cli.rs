@@ -0,0 +1,293 @@
+use std::{
+    env::temp_dir,
+    fs::{self, create_dir_all},
+    io::Write,
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+};
+
+use uuid::Uuid;
+
+const HELLO: &str = r#"
+        .ORIG x3000
+        LEA R0, MSG
+        PUTS
+        GETC
+        OUT
+        LEA R0, PACKED
+        PUTSP
+        HALT
+MSG     .STRINGZ "Hi "
+PACKED  .FILL x6261     ; "ab"
+        .FILL x0063     ; "c"
+        .END
+"#;
+
+/// Writes `source` to `name` in a fresh temporary directory.
+fn write_temp(name: &str, source: &str) -> PathBuf {
+    let dir = temp_dir().join(Uuid::new_v4().to_string());
+    create_dir_all(&dir).unwrap();
+
+    let path = dir.join(name);
+    fs::write(&path, source).unwrap();
+    path
+}
+
+/// Runs `lc3sim` with `args`, feeding it `input`.
+fn lc3sim(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lc3sim"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn run_asm() {
+    let path = write_temp("hello.asm", HELLO);
+    let output = lc3sim(&["run", path.to_str().unwrap(), "--show", "x3000"], "z");
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "Hi zabc");
+
+    let report = String::from_utf8(output.stderr).unwrap();
+    assert!(report.contains("PC  x3007"), "{report}");
+    assert!(report.contains("(halted)"), "{report}");
+    assert!(report.contains("R0  x300B"), "{report}");
+    assert!(
+        report.contains("x3000  xE006              LEA R0, MSG"),
+        "{report}"
+    );
+}
+
+#[test]
+fn run_on_lc3os() {
+    let path = write_temp("hello.asm", HELLO);
+    let output = lc3sim(&["run", "--lc3os", path.to_str().unwrap()], "z");
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "Hi zabc");
+}
+
+#[test]
+fn poll_without_os() {
+    // Reads a key straight from the keyboard registers, then halts
+    let source = r#"
+        .ORIG x3000
+POLL    LDI R1, KBSR
+        BRzp POLL
+        LDI R0, KBDR
+        OUT
+        HALT
+KBSR    .FILL xFE00
+KBDR    .FILL xFE02
+        .END
+"#;
+    let path = write_temp("poll.asm", source);
+    let output = lc3sim(&["run", path.to_str().unwrap()], "z");
+
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "z");
+}
+
+#[test]
+fn asm_then_run_obj() {
+    let path = write_temp("hello.asm", HELLO);
+    let output = lc3sim(&["asm", path.to_str().unwrap()], "");
+    assert!(output.status.success());
+
+    let obj = path.with_extension("obj");
+    assert!(path.with_extension("sym").exists());
+
+    let disasm = lc3sim(&["disasm", obj.to_str().unwrap()], "");
+    let source = String::from_utf8(disasm.stdout).unwrap();
+    assert!(source.contains("            LEA R0, MSG\n"), "{source}");
+
+    // Stops on the step limit, before GETC needs any input
+    let output = lc3sim(&["run", obj.to_str().unwrap(), "--limit", "2"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "Hi ");
+}
+
+#[test]
+fn save_and_resume() {
+    let path = write_temp("hello.asm", HELLO);
+    let state = path.with_extension("lc3state");
+    let args = [
+        "run",
+        path.to_str().unwrap(),
+        "--limit",
+        "2",
+        "--save-state",
+        state.to_str().unwrap(),
+    ];
+    let output = lc3sim(&args, "");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "Hi ");
+
+    // Picks up at GETC, without the program
+    let output = lc3sim(&["run", "--state", state.to_str().unwrap()], "z");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "zabc");
+}
+
+#[test]
+fn trace_and_diff() {
+    let path = write_temp("hello.asm", HELLO);
+    let dir = path.parent().unwrap();
+    let traces = ["z", "y"].map(|input| {
+        let trace = dir.join(format!("{input}.trace"));
+        let args = [
+            "run",
+            path.to_str().unwrap(),
+            "--trace",
+            trace.to_str().unwrap(),
+            "--trace-format",
+            "binary",
+        ];
+        assert!(lc3sim(&args, input).status.success());
+        trace.to_str().unwrap().to_string()
+    });
+
+    let output = lc3sim(&["trace", &traces[0]], "");
+    assert!(output.status.success());
+    let text = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(text.lines().count(), 7, "{text}");
+    assert!(
+        text.starts_with("x3000  xE006  LEA R0, x3007           R0 x0000 -> x3007"),
+        "{text}"
+    );
+
+    // The runs differ once GETC reads the key
+    let output = lc3sim(&["trace", &traces[0], "--diff", &traces[1]], "");
+    assert_eq!(output.status.code(), Some(1));
+    let text = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        text.starts_with("Traces differ after 2 matching steps"),
+        "{text}"
+    );
+    assert!(text.contains("R0 x3007 -> x0079"), "{text}");
+}
+
+#[test]
+fn profile() {
+    let path = write_temp("hello.asm", HELLO);
+    let report = path.with_extension("profile");
+    let folded = path.with_extension("folded");
+
+    let args = [
+        "run",
+        path.to_str().unwrap(),
+        "--profile",
+        report.to_str().unwrap(),
+    ];
+    assert!(lc3sim(&args, "z").status.success());
+    let text = fs::read_to_string(&report).unwrap();
+    assert!(text.starts_with("7 instructions executed\n"), "{text}");
+    assert!(
+        text.contains("  TRAP                        5  71.43%\n"),
+        "{text}"
+    );
+
+    // Under LC3OS, each TRAP is a call into the OS
+    let args = [
+        "run",
+        "--lc3os",
+        path.to_str().unwrap(),
+        "--profile",
+        folded.to_str().unwrap(),
+        "--profile-format",
+        "folded",
+    ];
+    assert!(lc3sim(&args, "z").status.success());
+    let text = fs::read_to_string(&folded).unwrap();
+    assert!(text.starts_with("x3000 7\n"), "{text}");
+    assert!(
+        text.lines()
+            .skip(1)
+            .all(|line| line.starts_with("x3000;x0")),
+        "{text}"
+    );
+}
+
+#[test]
+fn gdb_over_stdio() {
+    let path = write_temp("hello.asm", HELLO);
+    let packets = ["QStartNoAckMode", "p8", "Z0,6004,2", "c", "c", "p0", "k"]
+        .map(|packet| {
+            let sum = packet
+                .bytes()
+                .fold(0_u8, |sum, byte| sum.wrapping_add(byte));
+            format!("${packet}#{sum:02x}")
+        })
+        .concat();
+    let output = lc3sim(&["gdb", "--stdio", path.to_str().unwrap()], &packets);
+    assert!(output.status.success());
+
+    // Stops at GETC, then again with no keyboard
+    let replies = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        replies,
+        "+$OK#9a$3000#c3$OK#9a$T05swbreak:;#1d$S17#bb$3007#ca",
+    );
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "Hi ");
+}
+
+#[test]
+fn dap_over_stdio() {
+    let path = write_temp("hello.asm", HELLO);
+    let requests = [
+        r#"{"seq":1,"type":"request","command":"initialize","arguments":{"adapterID":"lc3"}}"#
+            .to_string(),
+        format!(
+            r#"{{"seq":2,"type":"request","command":"launch","arguments":{{"program":{:?}}}}}"#,
+            path.to_str().unwrap()
+        ),
+        r#"{"seq":3,"type":"request","command":"configurationDone"}"#.to_string(),
+        r#"{"seq":4,"type":"request","command":"evaluate","arguments":{"expression":"x","context":"repl"}}"#
+            .to_string(),
+    ]
+    .map(|request| format!("Content-Length: {}\r\n\r\n{request}", request.len()))
+    .concat();
+    let output = lc3sim(&["dap"], &requests);
+    assert!(output.status.success());
+
+    // The typed line is the program's keyboard input
+    let messages = String::from_utf8(output.stdout).unwrap();
+    for expected in [
+        r#""event":"initialized""#,
+        r#""output":"Hi ""#,
+        r#""output":"xabc""#,
+        r#""event":"terminated""#,
+    ] {
+        assert!(messages.contains(expected), "{messages}");
+    }
+}
+
+#[test]
+fn report_errors() {
+    let path = write_temp("bad.asm", ".ORIG x3000\nADD R1, R1, #99\n.END\n");
+    let output = lc3sim(&["asm", path.to_str().unwrap()], "");
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("error[E008]"), "{stderr}");
+    assert!(stderr.contains("bad.asm:2:13"), "{stderr}");
+
+    // Running out of keyboard input is an execution failure
+    let path = write_temp("hello.asm", HELLO);
+    let output = lc3sim(&["run", path.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("execution failed at x3002"), "{stderr}");
+}
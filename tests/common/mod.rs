@@ -1 +1,4 @@
+// Each integration test only uses a subset of the shared helpers
+#![allow(dead_code)]
+
 pub mod penn_sim;
@@ -12,7 +12,7 @@ use std::{
 use derive_getters::Getters;
 use lc3sim_project::{
     defs::{LC3Word, DEV_REG_ADDR},
-    executors::{populate_from_bin, LC3},
+    os::LC3OS,
 };
 use once_map::OnceMap;
 use uuid::Uuid;
@@ -125,10 +125,10 @@ impl CompileSet {
     }
 
     /// Get output lines and final memory after a pennsim run.
-    pub fn post_process_mem_dump<S: AsRef<str>>(
-        &self,
-        input: S,
-    ) -> (String, [LC3Word; DEV_REG_ADDR as usize]) {
+    ///
+    /// PennSim runs without input. Programs that read the keyboard should be
+    /// driven by [`lc3sim_project::harnesses::scripted::ScriptedIO`] instead.
+    pub fn post_process_mem_dump(&self) -> (String, [LC3Word; DEV_REG_ADDR as usize]) {
         // Create the temporary directory
         let temp_dir = temp_dir().join(Uuid::new_v4().to_string());
         create_dir_all(&temp_dir).unwrap();
@@ -139,25 +139,17 @@ impl CompileSet {
 
         // Create the temp OS obj file path
         let os_obj_path = temp_dir.join("lc3os.obj");
-        let _ = File::create_new(&os_obj_path).unwrap().write_all(OS.obj());
+        let _ = File::create_new(&os_obj_path).unwrap().write_all(LC3OS);
 
         // Create the dump file path
         let dump_path = temp_dir.join("dump.log");
 
-        // Create the temp OS obj file path
-        let input_path = temp_dir.join("input.txt");
-        let _ = File::create_new(&input_path)
-            .unwrap()
-            .write_all(input.as_ref().as_bytes());
-
         let script_path = temp_dir.join("script");
         let _ = File::create_new(&script_path).unwrap().write_all(
             ("ld ".to_string()
                 + os_obj_path.to_str().unwrap()
                 + "\nld "
                 + obj_path.to_str().unwrap()
-                + "\ninput "
-                + input_path.to_str().unwrap()
                 + "\ncontinue"
                 + "\ndump x0 xFE00 "
                 + dump_path.to_str().unwrap()
@@ -225,13 +217,3 @@ macro_rules! static_compiled {
         $crate::common::penn_sim::get_compiled($x, include_str!($x))
     };
 }
-
-static OS: LazyLock<&'static CompileSet> =
-    LazyLock::new(|| static_compiled!("../../penn_sim/lc3os.asm"));
-
-pub fn load_os<P: LC3>(processor: &mut P) {
-    populate_from_bin(
-        processor,
-        &**static_compiled!("../../penn_sim/lc3os.asm").obj(),
-    );
-}
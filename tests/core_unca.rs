@@ -5,11 +5,11 @@ use paste::paste;
 mod exec {
     use super::*;
 
-    use common::penn_sim::load_os;
     use lc3sim_project::{
         defs::{LC3MemAddr, USER_SPACE},
         executors::{core::CoreLC3, populate_from_bin, LC3},
-        harnesses::{simple::FailIO, sync::lim_step_continue},
+        harnesses::{scripted::ScriptedIO, sync::lim_step_continue},
+        os::load_lc3os,
     };
 
     /// Prevent infinite loops when the implementation jumps incorrectly
@@ -23,7 +23,7 @@ mod exec {
                     let mult_10 = static_compiled!($path);
 
                     let mut lc3 = CoreLC3::new();
-                    load_os(&mut lc3);
+                    load_lc3os(&mut lc3);
                     populate_from_bin(&mut lc3, &**mult_10.obj());
 
                     // Confirm the memory loaded correctly
@@ -33,11 +33,12 @@ mod exec {
                     }
 
                     // Step all the way through execution
-                    assert!(lim_step_continue(&mut FailIO, &mut lc3, EXEC_LIMIT).unwrap());
+                    let mut scripted = ScriptedIO::new("");
+                    assert!(lim_step_continue(&mut scripted, &mut lc3, EXEC_LIMIT).unwrap());
 
                     // Confirm full memory match with penn-sim
-                    let (output, mem_lines) = mult_10.post_process_mem_dump("");
-                    assert_eq!(output, "");
+                    let (output, mem_lines) = mult_10.post_process_mem_dump();
+                    assert_eq!(scripted.output_string(), output);
                     for (lc3_mem, penn_mem) in lc3.iter().zip(mem_lines) {
                         assert_eq!(lc3_mem, penn_mem)
                     }
@@ -0,0 +1,206 @@
+use lc3sim_project::{
+    assembler::assemble,
+    defs::{RegAddr, IO_PRIORITY, USER_SPACE},
+    executors::{core::CoreLC3, StepFailure, LC3},
+    harnesses::{console::ConsoleIO, scripted::ScriptedIO, sync::lim_step_continue},
+    os::load_lc3os,
+};
+
+/// Prevent infinite loops when the implementation jumps incorrectly
+const EXEC_LIMIT: u64 = 100_000;
+
+/// `source` loaded on top of PennSim's OS, ready to run.
+fn boot(source: &str) -> CoreLC3 {
+    let mut lc3 = CoreLC3::new();
+    load_lc3os(&mut lc3);
+    assemble(source).unwrap().populate(&mut lc3);
+    lc3.set_pc(USER_SPACE);
+    lc3
+}
+
+/// Runs `source` on PennSim's OS, typing each of `input` whenever the
+/// keyboard is empty. Returns the display output.
+fn run_on_os(source: &str, input: &[u8]) -> String {
+    let mut lc3 = boot(source);
+    let mut scripted = ScriptedIO::new(input);
+    assert!(lim_step_continue(&mut scripted, &mut lc3, EXEC_LIMIT).unwrap());
+    scripted.output_string()
+}
+
+#[test]
+fn os_console_routines() {
+    let source = r#"
+                .ORIG x3000
+                LEA R0, PROMPT
+                PUTS
+                GETC
+                OUT
+                ADD R0, R0, #1
+                OUT
+                LEA R0, PACKED
+                PUTSP
+                HALT
+        PROMPT  .STRINGZ "Key: "
+        PACKED  .FILL x4241     ; "AB"
+                .FILL x0043     ; "C"
+                .END
+    "#;
+
+    assert_eq!(run_on_os(source, b"q"), "Key: qrABC");
+}
+
+#[test]
+fn os_in_routine() {
+    let source = r#"
+                .ORIG x3000
+                IN
+                IN
+                HALT
+                .END
+    "#;
+
+    assert_eq!(
+        run_on_os(source, b"xy"),
+        "\nInput a character> x\n\nInput a character> y\n"
+    );
+}
+
+#[test]
+fn console_harness_on_os() {
+    let source = r#"
+                .ORIG x3000
+                GETC
+                OUT
+                LEA R0, PACKED
+                PUTSP
+                HALT
+        PACKED  .FILL x6968     ; "hi"
+                .FILL x0000
+                .END
+    "#;
+
+    let mut lc3 = boot(source);
+    let mut console = ConsoleIO::new(&b">"[..], Vec::new());
+
+    assert!(lim_step_continue(&mut console, &mut lc3, EXEC_LIMIT).unwrap());
+    assert_eq!(console.output(), b">hi");
+}
+
+#[test]
+fn scripted_transcript_on_os() {
+    let source = r#"
+                .ORIG x3000
+                GETC
+                OUT
+                LEA R0, DONE
+                PUTS
+                HALT
+        DONE    .STRINGZ "!?"
+                .END
+    "#;
+
+    let mut lc3 = boot(source);
+    let mut scripted = ScriptedIO::with_delay("k", 50);
+    assert!(lim_step_continue(&mut scripted, &mut lc3, EXEC_LIMIT).unwrap());
+
+    assert_eq!(scripted.output_string(), "k!?");
+    // Each character is attributed to the user TRAP that printed it
+    let traps: Vec<_> = scripted.transcript().iter().map(|w| w.trap).collect();
+    assert_eq!(traps, [Some(0x3001), Some(0x3003), Some(0x3003)]);
+    // The write itself happens inside the OS
+    assert!(scripted.transcript().iter().all(|w| w.pc < USER_SPACE));
+}
+
+/// Busy loops until a keyboard interrupt stores a key, textbook style.
+const INTERRUPT_DRIVEN: &str = r#"
+                .ORIG x3000
+                LD R0, ISR_PTR
+                STI R0, KB_VEC
+                LD R0, IE
+                STI R0, KBSR
+        LOOP    ADD R2, R2, #1
+                LD R1, KEY
+                BRz LOOP
+                HALT
+        ISR     LDI R1, KBDR
+                ST R1, KEY
+                RTI
+        ISR_PTR .FILL ISR
+        KB_VEC  .FILL x0180
+        IE      .FILL x4000
+        KBSR    .FILL xFE00
+        KBDR    .FILL xFE02
+        KEY     .FILL 0
+                .END
+"#;
+
+#[test]
+fn keyboard_interrupt() {
+    let mut lc3 = CoreLC3::new();
+    assemble(INTERRUPT_DRIVEN).unwrap().populate(&mut lc3);
+    lc3.set_pc(USER_SPACE);
+
+    let mut scripted = ScriptedIO::with_delay("k", 30);
+    assert!(lim_step_continue(&mut scripted, &mut lc3, EXEC_LIMIT).unwrap());
+
+    assert_eq!(lc3.reg(RegAddr::One), b'k'.into());
+    // The main loop ran while waiting for the key
+    assert!(lc3.reg(RegAddr::Two) > 1);
+    assert!(!lc3.devices().keyboard.is_ready());
+    assert_eq!(lc3.priority(), 0);
+}
+
+#[test]
+fn masked_keyboard_interrupt() {
+    let mut lc3 = CoreLC3::new();
+    assemble(INTERRUPT_DRIVEN).unwrap().populate(&mut lc3);
+    lc3.set_pc(USER_SPACE);
+    lc3.set_priority(IO_PRIORITY);
+
+    // Equal priority never preempts
+    let mut scripted = ScriptedIO::new("k");
+    assert!(!lim_step_continue(&mut scripted, &mut lc3, 1_000).unwrap());
+    assert!(lc3.devices().keyboard.is_ready());
+}
+
+#[test]
+fn user_mode_access_control() {
+    let source = r#"
+                .ORIG x3000
+                AND R0, R0, #0
+                STI R0, HALT_VEC
+                HALT
+        HALT_VEC .FILL x0025
+                .END
+    "#;
+
+    // Boot through the OS, which drops to user mode at x3000
+    let mut lc3 = CoreLC3::new();
+    load_lc3os(&mut lc3);
+    assemble(source).unwrap().populate(&mut lc3);
+    lc3.set_strict(true);
+
+    // Stops at the STI, like PennSim
+    assert_eq!(
+        lim_step_continue(&mut ScriptedIO::new(""), &mut lc3, EXEC_LIMIT),
+        Err(StepFailure::AccessViolation(0x0025).into())
+    );
+    assert_eq!(lc3.pc(), 0x3001);
+    assert!(!lc3.privileged());
+}
+
+#[test]
+fn os_bad_trap() {
+    let source = r#"
+                .ORIG x3000
+                TRAP x26
+                .END
+    "#;
+
+    // lc3os.asm halts by clearing the MCR clock bit
+    let mut lc3 = boot(source);
+    assert_eq!(
+        lim_step_continue(&mut ScriptedIO::new(""), &mut lc3, EXEC_LIMIT),
+        Err(StepFailure::ClockDisabled.into())
+    );
+}